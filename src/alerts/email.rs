@@ -74,16 +74,12 @@ impl Alert for Email {
                 MultiPart::alternative()
                     .singlepart(
                         SinglePart::builder()
-                            .header(header::ContentType(
-                                "text/plain; charset=utf8".parse().unwrap(),
-                            ))
+                            .header(header::ContentType::TEXT_PLAIN)
                             .body(format!("{}\n{}", notif.check, notif.message)),
                     )
                     .singlepart(
                         SinglePart::builder()
-                            .header(header::ContentType(
-                                "text/html; charset=utf8".parse().unwrap(),
-                            ))
+                            .header(header::ContentType::TEXT_HTML)
                             .body(format!("<p>{}</p>{}", notif.check, message_html)),
                     ),
             ),
@@ -51,7 +51,7 @@ async fn main() -> Result<()> {
         let path_to_store = config.path_to_store.as_ref().unwrap_or(&default_path);
         let store = sled::open(path_to_store)?;
 
-        web::start(&config, stop_tx.clone())?;
+        web::start(&config, store.clone(), stop_tx.clone())?;
 
         let probes = probes::register_from(&config);
         let alerts = alerts::register_from(&config);
@@ -1,32 +1,130 @@
 use super::{alerts::Alert, register_plugins, Config};
-use anyhow::Result;
+use ::prometheus::{register_gauge_vec, GaugeVec};
+use anyhow::{Context, Result};
 use async_trait::async_trait;
 use chrono::Utc;
 use cron::Schedule;
+use lazy_static::lazy_static;
+use reqwest::{header, StatusCode};
 use serde_derive::{Deserialize, Serialize};
 use sled::Db;
-use std::{collections::HashMap, str::FromStr, sync::Arc};
+use std::{
+    collections::HashMap,
+    fs,
+    str::FromStr,
+    sync::{Arc, RwLock},
+};
 use tokio::{sync::broadcast, time::sleep};
 
 pub mod atom;
+pub mod composite;
+pub mod docker;
+pub mod domain;
 pub mod exec;
+pub mod file;
+pub mod grpc;
+pub mod heartbeat;
 pub mod http;
+pub mod imap;
+pub mod kubernetes;
+pub mod ldap;
+pub mod logfile;
+pub mod mail;
+pub mod mqtt;
+pub mod mysql;
+pub mod ntp;
+pub mod page_change;
+pub mod pop3;
+pub mod postgres;
+pub mod process;
+pub mod prometheus;
+pub mod redis;
 pub mod rss;
+pub mod s3;
+pub mod smtp;
+pub mod snmp;
+pub mod ssh;
+pub mod system;
+pub mod udp;
+pub mod websocket;
 
 #[derive(Debug, Clone, Default, Deserialize)]
 pub struct Probes {
     pub atom: Option<Vec<atom::Atom>>,
+    pub composite: Option<Vec<composite::Composite>>,
+    pub docker: Option<Vec<docker::Docker>>,
+    pub domain: Option<Vec<domain::Domain>>,
     pub exec: Option<Vec<exec::Exec>>,
+    pub file: Option<Vec<file::File>>,
+    pub grpc: Option<Vec<grpc::Grpc>>,
+    pub heartbeat: Option<Vec<heartbeat::Heartbeat>>,
     pub http: Option<Vec<http::Http>>,
+    pub imap: Option<Vec<imap::Imap>>,
+    pub kubernetes: Option<Vec<kubernetes::Kubernetes>>,
+    pub ldap: Option<Vec<ldap::Ldap>>,
+    pub logfile: Option<Vec<logfile::Logfile>>,
+    pub mqtt: Option<Vec<mqtt::Mqtt>>,
+    pub mysql: Option<Vec<mysql::Mysql>>,
+    pub ntp: Option<Vec<ntp::Ntp>>,
+    pub page_change: Option<Vec<page_change::PageChange>>,
+    pub pop3: Option<Vec<pop3::Pop3>>,
+    pub postgres: Option<Vec<postgres::Postgres>>,
+    pub process: Option<Vec<process::Process>>,
+    pub prometheus: Option<Vec<prometheus::Prometheus>>,
+    pub redis: Option<Vec<redis::Redis>>,
     pub rss: Option<Vec<rss::Rss>>,
+    pub s3: Option<Vec<s3::S3>>,
+    pub smtp: Option<Vec<smtp::Smtp>>,
+    pub snmp: Option<Vec<snmp::Snmp>>,
+    pub ssh: Option<Vec<ssh::Ssh>>,
+    pub system: Option<Vec<system::System>>,
+    pub udp: Option<Vec<udp::Udp>>,
+    pub websocket: Option<Vec<websocket::Websocket>>,
+}
+
+lazy_static! {
+    static ref UNREACHABLE: GaugeVec = register_gauge_vec!(
+        "probe_unreachable",
+        "probe skipped because a probe it depends on is in incident",
+        &["plugin", "name"]
+    )
+    .unwrap();
+    // names of dependent probes by the name of the probe they depend on
+    static ref DEPENDENTS: RwLock<HashMap<String, Vec<String>>> = RwLock::new(HashMap::new());
 }
 
 pub fn register_from(config: &Config) -> HashMap<String, Vec<Box<dyn Probe>>> {
     let mut probes = HashMap::new();
     register_plugins!(Probe => config.probes.atom);
+    register_plugins!(Probe => config.probes.composite);
+    register_plugins!(Probe => config.probes.docker);
+    register_plugins!(Probe => config.probes.domain);
     register_plugins!(Probe => config.probes.exec);
+    register_plugins!(Probe => config.probes.file);
+    register_plugins!(Probe => config.probes.grpc);
+    register_plugins!(Probe => config.probes.heartbeat);
     register_plugins!(Probe => config.probes.http);
+    register_plugins!(Probe => config.probes.imap);
+    register_plugins!(Probe => config.probes.kubernetes);
+    register_plugins!(Probe => config.probes.ldap);
+    register_plugins!(Probe => config.probes.logfile);
+    register_plugins!(Probe => config.probes.mqtt);
+    register_plugins!(Probe => config.probes.mysql);
+    register_plugins!(Probe => config.probes.ntp);
+    register_plugins!(Probe => config.probes.page_change);
+    register_plugins!(Probe => config.probes.pop3);
+    register_plugins!(Probe => config.probes.postgres);
+    register_plugins!(Probe => config.probes.process);
+    register_plugins!(Probe => config.probes.prometheus);
+    register_plugins!(Probe => config.probes.redis);
     register_plugins!(Probe => config.probes.rss);
+    register_plugins!(Probe => config.probes.s3);
+    register_plugins!(Probe => config.probes.smtp);
+    register_plugins!(Probe => config.probes.snmp);
+    register_plugins!(Probe => config.probes.ssh);
+    register_plugins!(Probe => config.probes.system);
+    register_plugins!(Probe => config.probes.udp);
+    register_plugins!(Probe => config.probes.websocket);
     probes
 }
 
@@ -42,10 +140,24 @@ pub fn start(
     let alerts = Arc::new(alerts);
     let global = config.schedule.clone();
 
+    // replaced rather than added to, start runs again on every reload
+    let names = full_names(&probes);
+    *DEPENDENTS.write().unwrap() = dependents(&probes, &names)?;
+    for key in store.scan_prefix("name-").keys() {
+        store.remove(key?)?;
+    }
+
     for (name, plugins) in probes.into_iter() {
         log::info!("starting plugins: {} x {}", plugins.len(), name);
         for plugin in plugins.into_iter() {
             let schedule = Schedule::from_str(&plugin.schedule(&global))?;
+            let full_name = plugin.name(&name, plugin.local_name());
+            // so other probes can look up the state of this one by name, unless the name is
+            // shared and the lookup would be ambiguous
+            if names.get(&full_name) == Some(&1) {
+                store.insert(format!("name-{}", full_name), plugin.slug().as_bytes())?;
+            }
+            let depends_on = plugin.depends_on().unwrap_or_default();
             let name = name.clone();
             let cloned_store = Arc::clone(&store);
             let cloned_alerts = Arc::clone(&alerts);
@@ -58,6 +170,19 @@ pub fn start(
                     if let Ok(duration) = datetime.signed_duration_since(now).to_std() {
                         tokio::select! {
                             _ = sleep(duration) => {
+                                let parents = in_incident(local_store.as_ref(), &depends_on);
+                                UNREACHABLE
+                                    .with_label_values(&[&format!("probe.{}", name), &full_name])
+                                    .set(if parents.is_empty() { 0.0 } else { 1.0 });
+                                if !parents.is_empty() {
+                                    log::info!(
+                                        "[probe][{}] {} unreachable, skipped while {} in incident",
+                                        name,
+                                        full_name,
+                                        parents.join(", ")
+                                    );
+                                    continue;
+                                }
                                 plugin
                                     .observe(local_store.as_ref(), local_alerts.as_ref())
                                     .await
@@ -79,6 +204,58 @@ pub fn start(
     Ok(())
 }
 
+// Number of probes by full name, eg. `http.primary`, unnamed probes of a type share one
+fn full_names(probes: &HashMap<String, Vec<Box<dyn Probe>>>) -> HashMap<String, usize> {
+    let mut names = HashMap::new();
+    for (name, plugins) in probes.iter() {
+        for plugin in plugins.iter() {
+            *names
+                .entry(plugin.name(name, plugin.local_name()))
+                .or_default() += 1;
+        }
+    }
+    names
+}
+
+// Names of dependent probes by the name of the probe they depend on, which should be unique
+fn dependents(
+    probes: &HashMap<String, Vec<Box<dyn Probe>>>,
+    names: &HashMap<String, usize>,
+) -> Result<HashMap<String, Vec<String>>> {
+    let mut dependents: HashMap<String, Vec<String>> = HashMap::new();
+    for (name, plugins) in probes.iter() {
+        for plugin in plugins.iter() {
+            for parent in plugin.depends_on().unwrap_or_default() {
+                dependents
+                    .entry(parent)
+                    .or_default()
+                    .push(plugin.name(name, plugin.local_name()));
+            }
+        }
+    }
+    for parent in dependents.keys() {
+        match names.get(parent) {
+            None => log::warn!("depends_on refers to unknown probe {}", parent),
+            Some(count) if *count > 1 => anyhow::bail!(
+                "depends_on refers to {}, which is shared by {} probes, give each a unique name",
+                parent,
+                count
+            ),
+            Some(_) => {}
+        }
+    }
+    Ok(dependents)
+}
+
+// Probes among the given names that are currently in incident
+fn in_incident(store: &Db, names: &[String]) -> Vec<String> {
+    names
+        .iter()
+        .filter(|name| matches!(incident_of(store, name), Ok(Some(true))))
+        .cloned()
+        .collect()
+}
+
 #[async_trait]
 pub trait Probe: Send + Sync {
     fn new() -> Self
@@ -87,6 +264,11 @@ pub trait Probe: Send + Sync {
 
     fn local_schedule(&self) -> Option<String>;
 
+    fn local_name(&self) -> Option<String>;
+
+    // names of probes this one depends on, eg. `exec.vpn-gateway`
+    fn depends_on(&self) -> Option<Vec<String>>;
+
     fn slug(&self) -> String;
 
     async fn observe(
@@ -116,8 +298,16 @@ pub trait Probe: Send + Sync {
     async fn notify(
         &self,
         alerts: &HashMap<String, Vec<Box<dyn Alert>>>,
-        notif: Notification,
+        mut notif: Notification,
     ) -> Result<()> {
+        // dependents are skipped while this probe is in incident, so list them here instead
+        if let Some(dependents) = DEPENDENTS.read().unwrap().get(&notif.name) {
+            let suppressed = format!("suppressed dependent probes: {}", dependents.join(", "));
+            notif.message = format!("{}\n\n{}", notif.message, suppressed);
+            if let Some(message_html) = notif.message_html.as_mut() {
+                message_html.push_str(&format!("<p>{}</p>", suppressed));
+            }
+        }
         for (name, plugins) in alerts.iter() {
             log::info!(
                 "[{}] calling alert plugins: {} x {}",
@@ -158,6 +348,225 @@ pub struct MessageEntry {
 const HAS_INCIDENT: &[u8] = &[1, 1, 1];
 const NO_INCIDENT: &[u8] = &[0, 0, 0];
 
+// Whether the probe with the given name, eg. `http.primary`, is in incident state,
+// None when there's no such probe or it hasn't run yet
+pub fn incident_of(store: &Db, name: &str) -> Result<Option<bool>> {
+    let slug = match store.get(format!("name-{}", name))? {
+        Some(slug) => slug,
+        None => return Ok(None),
+    };
+    Ok(store.get(slug)?.map(|state| state.as_ref() == HAS_INCIDENT))
+}
+
+// Condition to alert on, eg. `> 0.9`, `== 0`, `!= up` or `absent`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Comparison {
+    Absent,
+    Compare(String, String),
+}
+
+impl FromStr for Comparison {
+    type Err = anyhow::Error;
+
+    fn from_str(condition: &str) -> Result<Self> {
+        let condition = condition.trim();
+        if condition == "absent" {
+            return Ok(Comparison::Absent);
+        }
+        for operator in [">=", "<=", "==", "!=", ">", "<"].iter() {
+            if let Some(threshold) = condition.strip_prefix(operator) {
+                let threshold = threshold.trim().trim_matches('"');
+                if threshold.is_empty() {
+                    break;
+                }
+                return Ok(Comparison::Compare(
+                    operator.to_string(),
+                    threshold.to_string(),
+                ));
+            }
+        }
+        anyhow::bail!("invalid condition: {}", condition)
+    }
+}
+
+impl Comparison {
+    // Whether the observed value meets the condition, `None` being an absent value.
+    // Values are compared as numbers when both sides are numeric, as strings otherwise.
+    pub fn is_met(&self, value: Option<&str>) -> bool {
+        let (operator, threshold, value) = match (self, value) {
+            (Comparison::Absent, value) => return value.is_none(),
+            (Comparison::Compare(_, _), None) => return false,
+            (Comparison::Compare(operator, threshold), Some(value)) => {
+                (operator.as_str(), threshold.as_str(), value.trim())
+            }
+        };
+        match (value.parse::<f64>(), threshold.parse::<f64>()) {
+            (Ok(value), Ok(threshold)) => match operator {
+                ">=" => value >= threshold,
+                "<=" => value <= threshold,
+                "==" => (value - threshold).abs() < f64::EPSILON,
+                "!=" => (value - threshold).abs() >= f64::EPSILON,
+                ">" => value > threshold,
+                "<" => value < threshold,
+                _ => false,
+            },
+            _ => match operator {
+                "==" => value == threshold,
+                "!=" => value != threshold,
+                _ => false,
+            },
+        }
+    }
+}
+
+// Strip password from a connection url, so it can be logged and used as a metric label
+pub fn redact_url(dsn: &str) -> String {
+    match url::Url::parse(dsn) {
+        Ok(mut parsed) => {
+            if parsed.password().is_some() {
+                let _ = parsed.set_password(Some("xxxxx"));
+            }
+            parsed.to_string()
+        }
+        Err(_) => dsn
+            .split_whitespace()
+            .filter(|param| !param.starts_with("password="))
+            .collect::<Vec<&str>>()
+            .join(" "),
+    }
+}
+
+// Password from config, or read from password file
+pub fn password(password: &Option<String>, password_file: &Option<String>) -> Result<String> {
+    match (password, password_file) {
+        (Some(password), _) => Ok(password.to_owned()),
+        (None, Some(password_file)) => Ok(fs::read_to_string(password_file)
+            .with_context(|| format!("failed reading password_file {}", password_file))?
+            .trim()
+            .to_owned()),
+        (None, None) => Ok("".to_owned()),
+    }
+}
+
+pub enum Fetched {
+    // previous response is still fresh, no request sent
+    Fresh,
+    // server responded 304 to the conditional request
+    NotModified,
+    // new content, cache to be saved after the content is evaluated
+    Modified(Vec<u8>, FeedCache),
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct FeedCache {
+    etag: Option<String>,
+    last_modified: Option<String>,
+    fresh_until: Option<i64>,
+    // seconds the content stays fresh, to renew fresh_until on a 304
+    #[serde(default)]
+    fresh_for: Option<i64>,
+}
+
+impl FeedCache {
+    fn key(slug: &str) -> String {
+        format!("{}-cache", slug)
+    }
+
+    fn load(store: &Db, slug: &str) -> Result<Option<Self>> {
+        match store.get(Self::key(slug).as_bytes())? {
+            Some(stored) => Ok(serde_json::from_slice(&stored).ok()),
+            None => Ok(None),
+        }
+    }
+
+    pub fn save(&self, store: &Db, slug: &str) -> Result<()> {
+        store.insert(Self::key(slug).as_bytes(), serde_json::to_vec(self)?)?;
+        Ok(())
+    }
+
+    pub fn keep_fresh_for(&mut self, seconds: i64) {
+        let until = Utc::now().timestamp() + seconds;
+        if self.fresh_until.unwrap_or_default() < until {
+            self.fresh_until = Some(until);
+        }
+        if self.fresh_for.unwrap_or_default() < seconds {
+            self.fresh_for = Some(seconds);
+        }
+    }
+
+    fn update_from(&mut self, headers: &header::HeaderMap, not_modified: bool) {
+        let value_of = |name| {
+            headers
+                .get(name)
+                .and_then(|value: &header::HeaderValue| value.to_str().ok())
+                .map(String::from)
+        };
+        if let Some(etag) = value_of(header::ETAG) {
+            self.etag = Some(etag);
+        }
+        if let Some(last_modified) = value_of(header::LAST_MODIFIED) {
+            self.last_modified = Some(last_modified);
+        }
+        // unchanged content stays fresh as long as before, eg. for the RSS <ttl> that is
+        // only known from the 200 response
+        let renew_for = match not_modified {
+            true => self.fresh_for,
+            false => None,
+        };
+        self.fresh_until = None;
+        self.fresh_for = None;
+        if let Some(cache_control) = value_of(header::CACHE_CONTROL) {
+            let directives: Vec<&str> = cache_control.split(',').map(str::trim).collect();
+            if directives.contains(&"no-cache") || directives.contains(&"no-store") {
+                return;
+            }
+            if let Some(max_age) = directives
+                .iter()
+                .find_map(|directive| directive.strip_prefix("max-age="))
+                .and_then(|max_age| max_age.parse::<i64>().ok())
+            {
+                self.keep_fresh_for(max_age);
+            }
+        }
+        if let Some(renew_for) = renew_for {
+            self.keep_fresh_for(renew_for);
+        }
+    }
+}
+
+// Fetch a feed with conditional request, the stored ETag, Last-Modified and
+// freshness are only used when the feed has been evaluated before.
+pub async fn fetch_feed(store: &Db, slug: &str, url: &str) -> Result<Fetched> {
+    let evaluated = store.get(slug.as_bytes())?.is_some();
+    let mut cache = match FeedCache::load(store, slug)? {
+        Some(cache) if evaluated => cache,
+        _ => FeedCache::default(),
+    };
+    if let Some(fresh_until) = cache.fresh_until {
+        if Utc::now().timestamp() < fresh_until {
+            return Ok(Fetched::Fresh);
+        }
+    }
+
+    let client = reqwest::Client::new();
+    let mut req = client.get(url);
+    if let Some(etag) = cache.etag.as_ref() {
+        req = req.header(header::IF_NONE_MATCH, etag);
+    }
+    if let Some(last_modified) = cache.last_modified.as_ref() {
+        req = req.header(header::IF_MODIFIED_SINCE, last_modified);
+    }
+    let resp = req.send().await?;
+    let not_modified = resp.status() == StatusCode::NOT_MODIFIED;
+    cache.update_from(resp.headers(), not_modified);
+    if not_modified {
+        cache.save(store, slug)?;
+        return Ok(Fetched::NotModified);
+    }
+    let content = resp.bytes().await?;
+    Ok(Fetched::Modified(content.to_vec(), cache))
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -204,8 +613,242 @@ mod test {
         }
     }
 
+    // Serves feeds named by their path, answering conditional requests for ETag "v1" with
+    // 304, and records the If-None-Match header of every request
+    async fn feed_server(requests: Arc<std::sync::Mutex<Vec<String>>>) -> String {
+        use warp::{http::Response, Filter};
+        let route = warp::path!(String)
+            .and(warp::header::optional::<String>("if-none-match"))
+            .and(warp::header::optional::<String>("if-modified-since"))
+            .map(
+                move |feed: String, etag: Option<String>, since: Option<String>| {
+                    requests.lock().unwrap().push(format!(
+                        "{} {}",
+                        feed,
+                        etag.clone().unwrap_or_default()
+                    ));
+                    let builder = Response::builder()
+                        .header("etag", "\"v1\"")
+                        .header("last-modified", "Mon, 19 Oct 2026 00:00:00 GMT");
+                    let builder = match feed.as_str() {
+                        "max-age" => builder.header("cache-control", "public, max-age=600"),
+                        "no-cache" => builder.header("cache-control", "no-cache, max-age=600"),
+                        _ => builder,
+                    };
+                    match (etag.as_deref(), since.as_deref()) {
+                        (Some("\"v1\""), Some("Mon, 19 Oct 2026 00:00:00 GMT")) => {
+                            builder.status(304).body(String::new())
+                        }
+                        _ => builder.body(format!("<feed>{}</feed>", feed)),
+                    }
+                    .unwrap()
+                },
+            );
+        let (addr, server) = warp::serve(route)
+            .bind_ephemeral("127.0.0.1:0".parse::<std::net::SocketAddr>().unwrap());
+        tokio::spawn(server);
+        format!("http://{}", addr)
+    }
+
+    // Fetch as a probe would, saving the cache and marking the feed evaluated
+    async fn fetch(store: &Db, url: &str, slug: &str) -> &'static str {
+        match fetch_feed(store, slug, &format!("{}/{}", url, slug))
+            .await
+            .unwrap()
+        {
+            Fetched::Fresh => "fresh",
+            Fetched::NotModified => "not modified",
+            Fetched::Modified(content, cache) => {
+                assert_eq!(content, format!("<feed>{}</feed>", slug).as_bytes());
+                cache.save(store, slug).unwrap();
+                store.insert(slug.as_bytes(), NO_INCIDENT).unwrap();
+                "modified"
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fetch_feed() {
+        let requests = Arc::new(std::sync::Mutex::new(vec![]));
+        let url = feed_server(requests.clone()).await;
+        let store = sled::Config::new().temporary(true).open().unwrap();
+        let sent = || requests.lock().unwrap().drain(..).collect::<Vec<String>>();
+
+        // validators are sent once the feed has been evaluated, and a 304 reuses it
+        assert_eq!(fetch(&store, &url, "plain").await, "modified");
+        assert_eq!(fetch(&store, &url, "plain").await, "not modified");
+        assert_eq!(sent(), vec!["plain ", "plain \"v1\""]);
+
+        // validators of a feed that was never evaluated are ignored
+        FeedCache {
+            etag: Some("\"v1\"".to_owned()),
+            ..Default::default()
+        }
+        .save(&store, "unevaluated")
+        .unwrap();
+        assert_eq!(fetch(&store, &url, "unevaluated").await, "modified");
+        assert_eq!(sent(), vec!["unevaluated "]);
+
+        // max-age skips requests, no-cache doesn't
+        assert_eq!(fetch(&store, &url, "max-age").await, "modified");
+        assert_eq!(fetch(&store, &url, "max-age").await, "fresh");
+        assert_eq!(fetch(&store, &url, "no-cache").await, "modified");
+        assert_eq!(fetch(&store, &url, "no-cache").await, "not modified");
+        assert_eq!(sent(), vec!["max-age ", "no-cache ", "no-cache \"v1\""]);
+
+        // an RSS <ttl> of 10 minutes whose freshness has run out is renewed by a bare 304
+        let mut cache = FeedCache::load(&store, "plain").unwrap().unwrap();
+        cache.keep_fresh_for(600);
+        cache.fresh_until = Some(Utc::now().timestamp() - 1);
+        cache.save(&store, "plain").unwrap();
+        assert_eq!(fetch(&store, &url, "plain").await, "not modified");
+        assert_eq!(fetch(&store, &url, "plain").await, "fresh");
+        assert_eq!(sent(), vec!["plain \"v1\""]);
+        let cache = FeedCache::load(&store, "plain").unwrap().unwrap();
+        assert!(cache.fresh_until.unwrap() > Utc::now().timestamp() + 590);
+    }
+
+    #[test]
+    fn test_dependents() {
+        let config = |depends_on: &str| -> Config {
+            toml::from_str(&format!(
+                r#"
+                schedule = "* * * * * *"
+                [[probes.exec]]
+                name = "vpn"
+                cmd = "true"
+                [[probes.http]]
+                url = "https://a.internal"
+                method = "get"
+                expected_code = 200
+                depends_on = [{}]
+                [[probes.http]]
+                url = "https://b.internal"
+                method = "get"
+                expected_code = 200
+                "#,
+                depends_on
+            ))
+            .unwrap()
+        };
+
+        let probes = register_from(&config(r#""exec.vpn", "exec.gone""#));
+        let names = full_names(&probes);
+        assert_eq!(names.get("exec.vpn"), Some(&1));
+        assert_eq!(names.get("http."), Some(&2));
+        let dependents = dependents(&probes, &names).unwrap();
+        assert_eq!(dependents.get("exec.vpn"), Some(&vec!["http.".to_owned()]));
+        assert_eq!(dependents.get("exec.gone"), Some(&vec!["http.".to_owned()]));
+
+        // unnamed http probes share a name, depending on it would be ambiguous
+        let probes = register_from(&config(r#""http.""#));
+        let names = full_names(&probes);
+        assert!(super::dependents(&probes, &names).is_err());
+    }
+
+    #[test]
+    fn test_comparison() {
+        let absent = Comparison::from_str("absent").unwrap();
+        assert!(absent.is_met(None));
+        assert!(!absent.is_met(Some("0")));
+
+        let above = Comparison::from_str("> 0.9").unwrap();
+        assert!(above.is_met(Some("0.95")));
+        assert!(!above.is_met(Some("0.9")));
+        assert!(!above.is_met(None));
+
+        let zero = Comparison::from_str("==0").unwrap();
+        assert!(zero.is_met(Some("0")));
+        assert!(!zero.is_met(Some("1")));
+
+        let not_up = Comparison::from_str("!= \"up\"").unwrap();
+        assert!(not_up.is_met(Some("down")));
+        assert!(!not_up.is_met(Some("up")));
+
+        assert!(Comparison::from_str("0.9").is_err());
+        assert!(Comparison::from_str(">").is_err());
+    }
+
     test_probe!(test_atom_notify, atom::Atom);
+    test_probe!(test_composite_notify, composite::Composite);
+    test_probe!(test_docker_notify, docker::Docker);
+    test_probe!(test_domain_notify, domain::Domain);
     test_probe!(test_exec_notify, exec::Exec);
+    test_probe!(test_file_notify, file::File);
+    test_probe!(test_grpc_notify, grpc::Grpc);
+    test_probe!(test_heartbeat_notify, heartbeat::Heartbeat);
     test_probe!(test_http_notify, http::Http);
+    test_probe!(test_imap_notify, imap::Imap);
+    test_probe!(test_kubernetes_notify, kubernetes::Kubernetes);
+    test_probe!(test_ldap_notify, ldap::Ldap);
+    test_probe!(test_logfile_notify, logfile::Logfile);
+    test_probe!(test_mqtt_notify, mqtt::Mqtt);
+    test_probe!(test_mysql_notify, mysql::Mysql);
+    test_probe!(test_ntp_notify, ntp::Ntp);
+    test_probe!(test_page_change_notify, page_change::PageChange);
+    test_probe!(test_pop3_notify, pop3::Pop3);
+    test_probe!(test_postgres_notify, postgres::Postgres);
+    test_probe!(test_process_notify, process::Process);
+    test_probe!(test_prometheus_notify, self::prometheus::Prometheus);
+    test_probe!(test_redis_notify, self::redis::Redis);
     test_probe!(test_rss_notify, self::rss::Rss);
+    test_probe!(test_s3_notify, self::s3::S3);
+    test_probe!(test_smtp_notify, smtp::Smtp);
+    test_probe!(test_snmp_notify, snmp::Snmp);
+    test_probe!(test_ssh_notify, ssh::Ssh);
+    test_probe!(test_system_notify, system::System);
+    test_probe!(test_udp_notify, udp::Udp);
+    test_probe!(test_websocket_notify, websocket::Websocket);
+}
+
+// Helpers for probe tests that run `observe` end to end
+#[cfg(test)]
+pub mod testing {
+    use super::*;
+    use std::sync::Mutex;
+
+    // Records the title and message of every notification it receives
+    #[derive(Default)]
+    pub struct RecordingAlert {
+        pub sent: Sent,
+    }
+
+    #[async_trait]
+    impl Alert for RecordingAlert {
+        fn new(_namepass: Vec<&str>) -> Self {
+            Self::default()
+        }
+        fn namepass(&self) -> Option<Vec<String>> {
+            None
+        }
+        async fn notify(&self, notif: &Notification) -> Result<()> {
+            self.sent
+                .lock()
+                .unwrap()
+                .push((notif.title.to_owned(), notif.message.to_owned()));
+            Ok(())
+        }
+    }
+
+    pub type Sent = Arc<Mutex<Vec<(String, String)>>>;
+    pub type Alerts = HashMap<String, Vec<Box<dyn Alert>>>;
+
+    // A temporary store, and alerts with a single recording plugin
+    pub fn setup() -> (Db, Alerts, Sent) {
+        let store = sled::Config::new().temporary(true).open().unwrap();
+        let alert = RecordingAlert::default();
+        let sent = alert.sent.clone();
+        let mut alerts: Alerts = HashMap::new();
+        alerts.insert("recording".to_owned(), vec![Box::new(alert)]);
+        (store, alerts, sent)
+    }
+
+    // Notifications as "title: message" since the last call
+    pub fn drain(sent: &Sent) -> Vec<String> {
+        sent.lock()
+            .unwrap()
+            .drain(..)
+            .map(|(title, message)| format!("{}: {}", title, message))
+            .collect()
+    }
 }
@@ -1,6 +1,6 @@
 use crate::{
     alerts::Alert,
-    probes::{MessageEntry, Notification, Probe, HAS_INCIDENT, NO_INCIDENT},
+    probes::{fetch_feed, Fetched, MessageEntry, Notification, Probe, HAS_INCIDENT, NO_INCIDENT},
 };
 use anyhow::{Context, Result};
 use async_trait::async_trait;
@@ -17,6 +17,7 @@ use std::collections::HashMap;
 pub struct Atom {
     name: Option<String>,
     schedule: Option<String>,
+    depends_on: Option<Vec<String>>,
     feed_url: String,
     title_regex: Option<String>,
     content_regex: Option<String>,
@@ -35,6 +36,12 @@ lazy_static! {
         &["plugin", "feed_url"]
     )
     .unwrap();
+    static ref CACHE_HITS_TOTAL: CounterVec = register_counter_vec!(
+        "probe_atom_cache_hits_total",
+        "cache hit counter for Atom probe plugin",
+        &["plugin", "feed_url"]
+    )
+    .unwrap();
     static ref TRIGGERED: GaugeVec = register_gauge_vec!(
         "probe_atom_triggered",
         "Atom probe plugin triggered",
@@ -55,6 +62,14 @@ impl Probe for Atom {
         self.schedule.to_owned()
     }
 
+    fn local_name(&self) -> Option<String> {
+        self.name.to_owned()
+    }
+
+    fn depends_on(&self) -> Option<Vec<String>> {
+        self.depends_on.to_owned()
+    }
+
     fn slug(&self) -> String {
         slugify(format!("atom-{}", self.feed_url))
     }
@@ -72,7 +87,25 @@ impl Probe for Atom {
         let stored = store.get(self.slug().as_bytes())?;
         let mut to_store = NO_INCIDENT;
         let mut triggered = 0;
-        let content = reqwest::get(&self.feed_url).await?.bytes().await?;
+        let (content, cache) = match fetch_feed(store, &self.slug(), &self.feed_url).await? {
+            Fetched::Modified(content, cache) => (content, cache),
+            _ => {
+                log::info!(
+                    "Atom feed {} not modified, reusing previous result",
+                    self.feed_url
+                );
+                CACHE_HITS_TOTAL
+                    .with_label_values(&["probe.atom", &self.feed_url])
+                    .inc();
+                if stored == Some(IVec::from(HAS_INCIDENT)) {
+                    triggered = 1;
+                }
+                TRIGGERED
+                    .with_label_values(&["probe.atom", &self.feed_url])
+                    .set(triggered as f64);
+                return Ok(());
+            }
+        };
         let feed = Feed::read_from(&content[..])?;
 
         let mut found_incidents = 0;
@@ -152,6 +185,7 @@ impl Probe for Atom {
         }
 
         store.insert(self.slug().as_bytes(), to_store)?;
+        cache.save(store, &self.slug())?;
 
         TRIGGERED
             .with_label_values(&["probe.atom", &self.feed_url])
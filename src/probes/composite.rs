@@ -0,0 +1,391 @@
+use crate::{
+    alerts::Alert,
+    probes::{incident_of, MessageEntry, Notification, Probe, HAS_INCIDENT, NO_INCIDENT},
+};
+use anyhow::Result;
+use async_trait::async_trait;
+use lazy_static::lazy_static;
+use prometheus::{register_counter_vec, register_gauge_vec, CounterVec, GaugeVec};
+use serde_derive::Deserialize;
+use sled::{Db, IVec};
+use slug::slugify;
+use std::{collections::HashMap, str::FromStr};
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Composite {
+    name: Option<String>,
+    schedule: Option<String>,
+    depends_on: Option<Vec<String>>,
+    // boolean expression over other probes referenced by name, each true while in incident,
+    // eg. `http.primary AND http.replica` or `at_least(2, http.us, http.eu, http.ap)`
+    condition: String,
+}
+
+lazy_static! {
+    static ref RUNS_TOTAL: CounterVec = register_counter_vec!(
+        "probe_composite_runs_total",
+        "run counter for composite probe plugin",
+        &["plugin", "name"]
+    )
+    .unwrap();
+    static ref TRIGGERED_TOTAL: CounterVec = register_counter_vec!(
+        "probe_composite_triggered_total",
+        "triggered counter for composite probe plugin",
+        &["plugin", "name"]
+    )
+    .unwrap();
+    static ref TRIGGERED: GaugeVec = register_gauge_vec!(
+        "probe_composite_triggered",
+        "Composite probe plugin triggered",
+        &["plugin", "name"]
+    )
+    .unwrap();
+}
+
+#[derive(Debug, PartialEq)]
+enum Expr {
+    Probe(String),
+    Not(Box<Expr>),
+    And(Vec<Expr>),
+    Or(Vec<Expr>),
+    AtLeast(usize, Vec<Expr>),
+}
+
+impl Expr {
+    fn eval(&self, incident: &dyn Fn(&str) -> bool) -> bool {
+        match self {
+            Expr::Probe(name) => incident(name),
+            Expr::Not(expr) => !expr.eval(incident),
+            Expr::And(exprs) => exprs.iter().all(|expr| expr.eval(incident)),
+            Expr::Or(exprs) => exprs.iter().any(|expr| expr.eval(incident)),
+            Expr::AtLeast(n, exprs) => {
+                exprs.iter().filter(|expr| expr.eval(incident)).count() >= *n
+            }
+        }
+    }
+
+    fn probes<'a>(&'a self, names: &mut Vec<&'a str>) {
+        match self {
+            Expr::Probe(name) => {
+                if !names.contains(&name.as_str()) {
+                    names.push(name)
+                }
+            }
+            Expr::Not(expr) => expr.probes(names),
+            Expr::And(exprs) | Expr::Or(exprs) | Expr::AtLeast(_, exprs) => {
+                exprs.iter().for_each(|expr| expr.probes(names))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Open,
+    Close,
+    Comma,
+    Word(String),
+    // quoted probe name, never a keyword
+    Quoted(String),
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>> {
+    let mut tokens = vec![];
+    let mut chars = input.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '(' => tokens.push(Token::Open),
+            ')' => tokens.push(Token::Close),
+            ',' => tokens.push(Token::Comma),
+            '"' => {
+                let mut word = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some(c) => word.push(c),
+                        None => anyhow::bail!("unterminated quote in {}", input),
+                    }
+                }
+                tokens.push(Token::Quoted(word));
+            }
+            c if c.is_whitespace() => {}
+            c => {
+                let mut word = c.to_string();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || "(),\"".contains(c) {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                tokens.push(Token::Word(word));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+// Recursive descent over: or := and (OR and)*, and := not (AND not)*,
+// not := NOT not | atom, atom := ( or ) | at_least(N, or, ...) | name
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Token::Word(word)) if word.eq_ignore_ascii_case(keyword))
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<()> {
+        match self.next() {
+            Some(token) if token == expected => Ok(()),
+            other => anyhow::bail!("expected {:?}, found {:?}", expected, other),
+        }
+    }
+
+    fn or(&mut self) -> Result<Expr> {
+        let mut exprs = vec![self.and()?];
+        while self.keyword("OR") {
+            self.next();
+            exprs.push(self.and()?);
+        }
+        Ok(if exprs.len() == 1 {
+            exprs.remove(0)
+        } else {
+            Expr::Or(exprs)
+        })
+    }
+
+    fn and(&mut self) -> Result<Expr> {
+        let mut exprs = vec![self.not()?];
+        while self.keyword("AND") {
+            self.next();
+            exprs.push(self.not()?);
+        }
+        Ok(if exprs.len() == 1 {
+            exprs.remove(0)
+        } else {
+            Expr::And(exprs)
+        })
+    }
+
+    fn not(&mut self) -> Result<Expr> {
+        if self.keyword("NOT") {
+            self.next();
+            return Ok(Expr::Not(Box::new(self.not()?)));
+        }
+        self.atom()
+    }
+
+    fn atom(&mut self) -> Result<Expr> {
+        match self.next() {
+            Some(Token::Open) => {
+                let expr = self.or()?;
+                self.expect(Token::Close)?;
+                Ok(expr)
+            }
+            Some(Token::Word(word))
+                if word.eq_ignore_ascii_case("at_least") && self.peek() == Some(&Token::Open) =>
+            {
+                self.next();
+                let n = match self.next() {
+                    Some(Token::Word(n)) => n
+                        .parse::<usize>()
+                        .map_err(|_| anyhow::anyhow!("invalid count {} in at_least", n))?,
+                    other => anyhow::bail!("expected count in at_least, found {:?}", other),
+                };
+                let mut exprs = vec![];
+                while self.peek() == Some(&Token::Comma) {
+                    self.next();
+                    exprs.push(self.or()?);
+                }
+                self.expect(Token::Close)?;
+                Ok(Expr::AtLeast(n, exprs))
+            }
+            Some(Token::Word(word)) => {
+                if ["AND", "OR", "NOT"]
+                    .iter()
+                    .any(|keyword| word.eq_ignore_ascii_case(keyword))
+                {
+                    anyhow::bail!("unexpected {}", word);
+                }
+                Ok(Expr::Probe(word))
+            }
+            Some(Token::Quoted(name)) => Ok(Expr::Probe(name)),
+            other => anyhow::bail!("expected probe name, found {:?}", other),
+        }
+    }
+}
+
+impl FromStr for Expr {
+    type Err = anyhow::Error;
+
+    fn from_str(input: &str) -> Result<Self> {
+        let mut parser = Parser {
+            tokens: tokenize(input)?,
+            pos: 0,
+        };
+        let expr = parser.or()?;
+        if let Some(token) = parser.peek() {
+            anyhow::bail!("unexpected {:?} in condition {}", token, input);
+        }
+        Ok(expr)
+    }
+}
+
+#[async_trait]
+impl Probe for Composite {
+    fn new() -> Self {
+        Self {
+            ..Default::default()
+        }
+    }
+
+    fn local_schedule(&self) -> Option<String> {
+        self.schedule.to_owned()
+    }
+
+    fn local_name(&self) -> Option<String> {
+        self.name.to_owned()
+    }
+
+    fn depends_on(&self) -> Option<Vec<String>> {
+        self.depends_on.to_owned()
+    }
+
+    fn slug(&self) -> String {
+        slugify(format!("composite-{}", self.condition))
+    }
+
+    async fn observe(
+        &self,
+        store: &Db,
+        alerts: &HashMap<String, Vec<Box<dyn Alert>>>,
+    ) -> Result<()> {
+        let name = self.name("composite", self.name.to_owned());
+        log::info!("evaluating composite condition {}", self.condition);
+        RUNS_TOTAL
+            .with_label_values(&["probe.composite", &name])
+            .inc();
+
+        let stored = store.get(self.slug().as_bytes())?;
+        let mut to_store = NO_INCIDENT;
+        let mut triggered = 0;
+        let expr: Expr = self.condition.parse()?;
+        let mut members = vec![];
+        expr.probes(&mut members);
+        let mut states: HashMap<&str, Option<bool>> = HashMap::new();
+        for member in members.iter() {
+            let state = incident_of(store, member)?;
+            if state.is_none() {
+                log::warn!("composite {}: no state recorded for {}", name, member);
+            }
+            states.insert(member, state);
+        }
+        // probes without a recorded state count as healthy
+        let met = expr.eval(&|member| states.get(member).copied().flatten().unwrap_or(false));
+
+        if met {
+            let failing: Vec<&str> = members
+                .iter()
+                .filter(|member| states.get(*member) == Some(&Some(true)))
+                .copied()
+                .collect();
+            log::info!(
+                "_TRIGGERED_: composite condition {} met, failing: {}",
+                self.condition,
+                failing.join(", ")
+            );
+            if stored.is_none() || stored == Some(IVec::from(NO_INCIDENT)) {
+                log::warn!(
+                    "_NOTIFY_: composite condition {} met, failing: {}",
+                    self.condition,
+                    failing.join(", ")
+                );
+                let message_entries: Vec<(i8, MessageEntry)> = members
+                    .iter()
+                    .take(i8::MAX as usize)
+                    .enumerate()
+                    .map(|(i, member)| {
+                        (
+                            i as i8,
+                            MessageEntry {
+                                title: member.to_string(),
+                                description: match states.get(member) {
+                                    Some(Some(true)) => "in incident",
+                                    Some(Some(false)) => "ok",
+                                    _ => "unknown",
+                                }
+                                .to_owned(),
+                            },
+                        )
+                    })
+                    .collect();
+                self.notify(
+                    alerts,
+                    Notification {
+                        from: "composite".to_owned(),
+                        name: name.clone(),
+                        check: format!("composite condition {}", self.condition),
+                        title: format!("{} of {} probe(s) failing", failing.len(), members.len()),
+                        message: format!("failing probes: {}", failing.join(", ")),
+                        message_html: None,
+                        message_entries: Some(message_entries),
+                    },
+                )
+                .await?;
+            }
+            TRIGGERED_TOTAL
+                .with_label_values(&["probe.composite", &name])
+                .inc();
+            triggered = 1;
+            to_store = HAS_INCIDENT;
+        }
+
+        store.insert(self.slug().as_bytes(), to_store)?;
+
+        TRIGGERED
+            .with_label_values(&["probe.composite", &name])
+            .set(triggered as f64);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_condition() {
+        let failing = |down: &'static [&'static str]| move |name: &str| down.contains(&name);
+
+        let both: Expr = "http.primary AND http.replica".parse().unwrap();
+        assert!(both.eval(&failing(&["http.primary", "http.replica"])));
+        assert!(!both.eval(&failing(&["http.primary"])));
+
+        let quorum: Expr = "at_least(2, http.us, http.eu, http.ap)".parse().unwrap();
+        assert!(quorum.eval(&failing(&["http.us", "http.ap"])));
+        assert!(!quorum.eval(&failing(&["http.eu"])));
+
+        let nested: Expr = "NOT \"exec.maintenance window\" and (tcp.a or tcp.b)"
+            .parse()
+            .unwrap();
+        assert!(nested.eval(&failing(&["tcp.b"])));
+        assert!(!nested.eval(&failing(&["tcp.b", "exec.maintenance window"])));
+
+        assert!("http.a AND".parse::<Expr>().is_err());
+        assert!("(http.a".parse::<Expr>().is_err());
+        assert!("at_least(x, http.a)".parse::<Expr>().is_err());
+    }
+}
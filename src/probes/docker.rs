@@ -0,0 +1,432 @@
+use crate::{
+    alerts::Alert,
+    probes::{MessageEntry, Notification, Probe, HAS_INCIDENT, NO_INCIDENT},
+};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use hyper::{body, Client, StatusCode};
+use hyperlocal::{UnixClientExt, UnixConnector, Uri};
+use lazy_static::lazy_static;
+use prometheus::{register_counter_vec, register_gauge_vec, CounterVec, GaugeVec};
+use serde::de::DeserializeOwned;
+use serde_derive::Deserialize;
+use sled::{Db, IVec};
+use slug::slugify;
+use std::collections::HashMap;
+use tokio::time::{timeout, Duration};
+use url::form_urlencoded;
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Docker {
+    name: Option<String>,
+    schedule: Option<String>,
+    depends_on: Option<Vec<String>>,
+    // default /var/run/docker.sock, or eg. /run/podman/podman.sock for Podman
+    socket: Option<String>,
+    // container name filter, matched the same way as `docker ps --filter name=...`
+    container: Option<String>,
+    // label filters like "com.example.app=web", or just the key
+    labels: Option<Vec<String>>,
+    // trigger when a container restarted more than this many times since the last check
+    max_restarts: Option<u64>,
+    // seconds to wait for the engine API, default 10
+    timeout: Option<u64>,
+}
+
+lazy_static! {
+    static ref RUNS_TOTAL: CounterVec = register_counter_vec!(
+        "probe_docker_runs_total",
+        "run counter for docker probe plugin",
+        &["plugin", "selector"]
+    )
+    .unwrap();
+    static ref TRIGGERED_TOTAL: CounterVec = register_counter_vec!(
+        "probe_docker_triggered_total",
+        "triggered counter for docker probe plugin",
+        &["plugin", "selector"]
+    )
+    .unwrap();
+    static ref TRIGGERED: GaugeVec = register_gauge_vec!(
+        "probe_docker_triggered",
+        "Docker probe plugin triggered",
+        &["plugin", "selector"]
+    )
+    .unwrap();
+    static ref CONTAINERS: GaugeVec = register_gauge_vec!(
+        "probe_docker_containers",
+        "number of containers matching the selector from docker probe plugin",
+        &["plugin", "selector"]
+    )
+    .unwrap();
+    static ref FAILING_CONTAINERS: GaugeVec = register_gauge_vec!(
+        "probe_docker_failing_containers",
+        "number of matching containers that are stopped, unhealthy or restarting",
+        &["plugin", "selector"]
+    )
+    .unwrap();
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Summary {
+    id: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Container {
+    id: String,
+    name: String,
+    restart_count: u64,
+    state: State,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct State {
+    status: String,
+    running: bool,
+    health: Option<Health>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Health {
+    status: String,
+    log: Option<Vec<HealthLog>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct HealthLog {
+    output: String,
+}
+
+impl Docker {
+    fn socket(&self) -> String {
+        self.socket
+            .to_owned()
+            .unwrap_or_else(|| "/var/run/docker.sock".to_owned())
+    }
+
+    fn selector(&self) -> String {
+        let mut selector = vec![];
+        if let Some(container) = self.container.as_ref() {
+            selector.push(format!("name={}", container));
+        }
+        for label in self.labels.to_owned().unwrap_or_default() {
+            selector.push(format!("label={}", label));
+        }
+        selector.join(",")
+    }
+
+    // None when the engine responds 404, eg. a container removed since it was listed
+    async fn get<T: DeserializeOwned>(&self, path: &str) -> Result<Option<T>> {
+        let client: Client<UnixConnector> = Client::unix();
+        let response = client
+            .get(Uri::new(self.socket(), path).into())
+            .await
+            .with_context(|| format!("failed connecting to {}", self.socket()))?;
+        let status = response.status();
+        if status == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let bytes = body::to_bytes(response.into_body()).await?;
+        if !status.is_success() {
+            anyhow::bail!(
+                "GET {} got {}: {}",
+                path,
+                status,
+                String::from_utf8_lossy(&bytes).trim()
+            );
+        }
+        serde_json::from_slice(&bytes)
+            .map(Some)
+            .with_context(|| format!("failed parsing GET {}", path))
+    }
+
+    // List all containers matching the selector, including stopped ones, then inspect
+    // each for its health and restart count
+    async fn containers(&self) -> Result<Vec<Container>> {
+        let mut filters: HashMap<&str, Vec<String>> = HashMap::new();
+        if let Some(container) = self.container.as_ref() {
+            filters.insert("name", vec![container.to_owned()]);
+        }
+        if let Some(labels) = self.labels.as_ref() {
+            filters.insert("label", labels.to_owned());
+        }
+        let filters: String =
+            form_urlencoded::byte_serialize(serde_json::to_string(&filters)?.as_bytes()).collect();
+        let summaries: Vec<Summary> = self
+            .get(&format!("/containers/json?all=true&filters={}", filters))
+            .await?
+            .unwrap_or_default();
+        let mut containers = vec![];
+        for summary in summaries.iter() {
+            match self
+                .get(&format!("/containers/{}/json", summary.id))
+                .await?
+            {
+                Some(container) => containers.push(container),
+                None => log::debug!("container {} is gone since listed", summary.id),
+            }
+        }
+        Ok(containers)
+    }
+
+    // Problems with a single container, given its restart count from the last check
+    fn inspect(&self, container: &Container, restarts: Option<u64>) -> Vec<String> {
+        let mut problems = vec![];
+        if !container.state.running {
+            problems.push(format!("container is {}", container.state.status));
+        }
+        if let Some(health) = container.state.health.as_ref() {
+            if health.status == "unhealthy" {
+                let output = health
+                    .log
+                    .as_ref()
+                    .and_then(|log| log.last())
+                    .map(|last| last.output.trim().to_owned())
+                    .unwrap_or_default();
+                problems.push(format!("healthcheck reports unhealthy: {}", output));
+            }
+        }
+        if let (Some(max_restarts), Some(restarts)) = (self.max_restarts, restarts) {
+            let delta = container.restart_count.saturating_sub(restarts);
+            if delta > max_restarts {
+                problems.push(format!(
+                    "restarted {} times since last check, more than {}",
+                    delta, max_restarts
+                ));
+            }
+        }
+        problems
+    }
+}
+
+#[async_trait]
+impl Probe for Docker {
+    fn new() -> Self {
+        Self {
+            ..Default::default()
+        }
+    }
+
+    fn local_schedule(&self) -> Option<String> {
+        self.schedule.to_owned()
+    }
+
+    fn local_name(&self) -> Option<String> {
+        self.name.to_owned()
+    }
+
+    fn depends_on(&self) -> Option<Vec<String>> {
+        self.depends_on.to_owned()
+    }
+
+    fn slug(&self) -> String {
+        slugify(format!("docker-{}-{}", self.socket(), self.selector()))
+    }
+
+    async fn observe(
+        &self,
+        store: &Db,
+        alerts: &HashMap<String, Vec<Box<dyn Alert>>>,
+    ) -> Result<()> {
+        let selector = self.selector();
+        log::info!("checking containers matching {:?}", selector);
+        RUNS_TOTAL
+            .with_label_values(&["probe.docker", &selector])
+            .inc();
+
+        let stored = store.get(self.slug().as_bytes())?;
+        let mut to_store = NO_INCIDENT;
+        let mut triggered = 0;
+        // restart counts by container id as of the last check
+        let restarts_key = format!("{}-restarts", self.slug());
+        let restarts: HashMap<String, u64> = match store.get(restarts_key.as_bytes())? {
+            Some(restarts) => serde_json::from_slice(&restarts).unwrap_or_default(),
+            None => HashMap::new(),
+        };
+
+        let seconds = self.timeout.unwrap_or(10);
+        let result = match timeout(Duration::from_secs(seconds), self.containers()).await {
+            Ok(result) => result,
+            Err(_) => Err(anyhow::anyhow!(
+                "no response from {} within {} seconds",
+                self.socket(),
+                seconds
+            )),
+        };
+
+        let mut problems: Vec<String> = vec![];
+        let mut message_entries: Vec<(i8, MessageEntry)> = vec![];
+        match result {
+            Ok(containers) => {
+                CONTAINERS
+                    .with_label_values(&["probe.docker", &selector])
+                    .set(containers.len() as f64);
+                if containers.is_empty() {
+                    problems.push(format!("no container matches {:?}", selector));
+                }
+                for container in containers.iter() {
+                    let name = container.name.trim_start_matches('/');
+                    let failed = self.inspect(container, restarts.get(&container.id).copied());
+                    if failed.is_empty() {
+                        continue;
+                    }
+                    problems.push(format!("{} {}", name, failed.join(", ")));
+                    if message_entries.len() < i8::MAX as usize {
+                        message_entries.push((
+                            message_entries.len() as i8, // index of this message entry
+                            MessageEntry {
+                                title: name.to_owned(),
+                                description: failed.join("\n"),
+                            },
+                        ));
+                    }
+                }
+                let restarts: HashMap<&str, u64> = containers
+                    .iter()
+                    .map(|container| (container.id.as_str(), container.restart_count))
+                    .collect();
+                store.insert(restarts_key.as_bytes(), serde_json::to_vec(&restarts)?)?;
+            }
+            Err(err) => problems.push(format!("{:#}", err)),
+        }
+        FAILING_CONTAINERS
+            .with_label_values(&["probe.docker", &selector])
+            .set(message_entries.len() as f64);
+
+        if !problems.is_empty() {
+            log::info!(
+                "_TRIGGERED_: containers matching {:?}: {}",
+                selector,
+                problems.join(", ")
+            );
+            if stored.is_none() || stored == Some(IVec::from(NO_INCIDENT)) {
+                log::warn!(
+                    "_NOTIFY_: containers matching {:?}: {}",
+                    selector,
+                    problems.join(", ")
+                );
+                self.notify(
+                    alerts,
+                    Notification {
+                        from: "docker".to_owned(),
+                        name: self.name("docker", self.name.to_owned()),
+                        check: format!("containers matching {:?} on {}", selector, self.socket()),
+                        title: format!(
+                            "{} problem(s) with containers matching {:?}",
+                            problems.len(),
+                            selector
+                        ),
+                        message: problems.join("\n"),
+                        message_html: None,
+                        message_entries: if message_entries.is_empty() {
+                            None
+                        } else {
+                            Some(message_entries)
+                        },
+                    },
+                )
+                .await?;
+            }
+            TRIGGERED_TOTAL
+                .with_label_values(&["probe.docker", &selector])
+                .inc();
+            triggered = 1;
+            to_store = HAS_INCIDENT;
+        }
+
+        store.insert(self.slug().as_bytes(), to_store)?;
+
+        TRIGGERED
+            .with_label_values(&["probe.docker", &selector])
+            .set(triggered as f64);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tokio::net::UnixListener;
+    use tokio_stream::wrappers::UnixListenerStream;
+    use warp::Filter;
+
+    #[tokio::test]
+    async fn test_containers_from_fake_engine() {
+        let list = warp::path!("containers" / "json")
+            .and(warp::query::<HashMap<String, String>>())
+            .map(|query: HashMap<String, String>| {
+                assert_eq!(query.get("all").map(String::as_str), Some("true"));
+                assert_eq!(
+                    query.get("filters").map(String::as_str),
+                    Some(r#"{"label":["app=web"]}"#)
+                );
+                warp::reply::json(&serde_json::json!([{"Id": "a1"}, {"Id": "b2"}, {"Id": "c3"}]))
+            });
+        let inspect = warp::path!("containers" / String / "json").map(|id: String| {
+            // c3 is removed between listing and inspecting
+            let status = match id.as_str() {
+                "c3" => warp::http::StatusCode::NOT_FOUND,
+                _ => warp::http::StatusCode::OK,
+            };
+            let container = match id.as_str() {
+                "a1" => serde_json::json!({
+                    "Id": "a1",
+                    "Name": "/web-1",
+                    "RestartCount": 5,
+                    "State": {"Status": "running", "Running": true},
+                }),
+                _ => serde_json::json!({
+                    "Id": "b2",
+                    "Name": "/web-2",
+                    "RestartCount": 0,
+                    "State": {
+                        "Status": "running",
+                        "Running": true,
+                        "Health": {"Status": "unhealthy", "Log": [{"Output": "connection refused\n"}]},
+                    },
+                }),
+            };
+            warp::reply::with_status(warp::reply::json(&container), status)
+        });
+        let dir = std::env::temp_dir().join(format!("otto-docker-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let socket = dir.join("docker.sock");
+        let _ = std::fs::remove_file(&socket);
+        let listener = UnixListener::bind(&socket).unwrap();
+        tokio::spawn(
+            warp::serve(warp::get().and(list.or(inspect)))
+                .run_incoming(UnixListenerStream::new(listener)),
+        );
+
+        let probe = Docker {
+            socket: Some(socket.to_string_lossy().to_string()),
+            labels: Some(vec!["app=web".to_owned()]),
+            max_restarts: Some(2),
+            ..Default::default()
+        };
+        let containers = probe.containers().await.unwrap();
+        assert_eq!(containers.len(), 2);
+        assert!(probe.inspect(&containers[0], None).is_empty());
+        assert!(probe.inspect(&containers[0], Some(3)).is_empty());
+        assert_eq!(
+            probe.inspect(&containers[0], Some(1)),
+            vec!["restarted 4 times since last check, more than 2"]
+        );
+        assert_eq!(
+            probe.inspect(&containers[1], Some(0)),
+            vec!["healthcheck reports unhealthy: connection refused"]
+        );
+
+        let missing = Docker {
+            socket: Some(dir.join("missing.sock").to_string_lossy().to_string()),
+            ..Default::default()
+        };
+        assert!(missing.containers().await.is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
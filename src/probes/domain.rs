@@ -0,0 +1,656 @@
+use crate::{
+    alerts::Alert,
+    probes::{Notification, Probe, HAS_INCIDENT, NO_INCIDENT},
+};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use lazy_static::lazy_static;
+use prometheus::{register_counter_vec, register_gauge_vec, CounterVec, GaugeVec};
+use serde_derive::{Deserialize, Serialize};
+use sled::{Db, IVec};
+use slug::slugify;
+use std::collections::{BTreeSet, HashMap};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    time::{timeout, Duration},
+};
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Domain {
+    name: Option<String>,
+    schedule: Option<String>,
+    depends_on: Option<Vec<String>>,
+    domain: String,
+    // default "https://rdap.org", which redirects to the registry's RDAP server
+    rdap_url: Option<String>,
+    // WHOIS server to fall back to when RDAP fails, eg. "whois.verisign-grs.com" or "host:43",
+    // default asks whois.iana.org for the server of the TLD
+    whois_server: Option<String>,
+    // default 30
+    warn_days: Option<i64>,
+    // statuses that trigger whenever present, default clientHold, serverHold,
+    // redemptionPeriod and pendingDelete
+    alert_statuses: Option<Vec<String>>,
+    // seconds to wait for the lookup, default 10
+    timeout: Option<u64>,
+}
+
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+struct Snapshot {
+    // rdap or whois, results from different sources are not compared
+    source: String,
+    statuses: BTreeSet<String>,
+    nameservers: BTreeSet<String>,
+}
+
+#[derive(Debug)]
+struct Registration {
+    expires: DateTime<Utc>,
+    snapshot: Snapshot,
+}
+
+#[derive(Debug, Deserialize)]
+struct RdapDomain {
+    #[serde(default)]
+    events: Vec<RdapEvent>,
+    #[serde(default)]
+    status: Vec<String>,
+    #[serde(default)]
+    nameservers: Vec<RdapNameserver>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RdapEvent {
+    event_action: String,
+    event_date: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RdapNameserver {
+    ldh_name: String,
+}
+
+const ALERT_STATUSES: [&str; 4] = [
+    "clientHold",
+    "serverHold",
+    "redemptionPeriod",
+    "pendingDelete",
+];
+
+lazy_static! {
+    static ref RUNS_TOTAL: CounterVec = register_counter_vec!(
+        "probe_domain_runs_total",
+        "run counter for domain probe plugin",
+        &["plugin", "domain"]
+    )
+    .unwrap();
+    static ref TRIGGERED_TOTAL: CounterVec = register_counter_vec!(
+        "probe_domain_triggered_total",
+        "triggered counter for domain probe plugin",
+        &["plugin", "domain"]
+    )
+    .unwrap();
+    static ref TRIGGERED: GaugeVec = register_gauge_vec!(
+        "probe_domain_triggered",
+        "domain probe plugin triggered",
+        &["plugin", "domain"]
+    )
+    .unwrap();
+    static ref EXPIRY_DAYS: GaugeVec = register_gauge_vec!(
+        "probe_domain_expiry_days",
+        "days until the domain registration expires",
+        &["plugin", "domain"]
+    )
+    .unwrap();
+}
+
+// RDAP statuses are spaced words like "client hold", WHOIS uses EPP codes like "clientHold",
+// and RDAP "active" is EPP "ok"
+fn epp_status(status: &str) -> String {
+    let status = status.trim();
+    if status.eq_ignore_ascii_case("active") {
+        return "ok".to_owned();
+    }
+    let mut words = status.split_whitespace();
+    let mut epp = words.next().unwrap_or_default().to_owned();
+    for word in words {
+        let mut chars = word.chars();
+        if let Some(first) = chars.next() {
+            epp.extend(first.to_uppercase());
+            epp.push_str(chars.as_str());
+        }
+    }
+    epp
+}
+
+fn nameserver(name: &str) -> String {
+    name.trim().trim_end_matches('.').to_lowercase()
+}
+
+fn parse_date(date: &str) -> Option<DateTime<Utc>> {
+    let date = date.trim();
+    if let Ok(date) = DateTime::parse_from_rfc3339(date) {
+        return Some(date.with_timezone(&Utc));
+    }
+    for format in ["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M:%S"] {
+        if let Ok(date) = NaiveDateTime::parse_from_str(date, format) {
+            return Some(Utc.from_utc_datetime(&date));
+        }
+    }
+    for format in ["%Y-%m-%d", "%d-%b-%Y", "%Y.%m.%d", "%d.%m.%Y", "%Y/%m/%d"] {
+        if let Ok(date) = NaiveDate::parse_from_str(date, format) {
+            return Some(Utc.from_utc_datetime(&date.and_hms(0, 0, 0)));
+        }
+    }
+    None
+}
+
+fn parse_whois(response: &str) -> Result<Registration> {
+    let mut expires = None;
+    let mut snapshot = Snapshot {
+        source: "whois".to_owned(),
+        ..Default::default()
+    };
+    for line in response.lines() {
+        let (key, value) = match line.split_once(':') {
+            Some((key, value)) => (key.trim().to_lowercase(), value.trim()),
+            None => continue,
+        };
+        if value.is_empty() {
+            continue;
+        }
+        match key.as_str() {
+            "registry expiry date"
+            | "registrar registration expiration date"
+            | "expiration date"
+            | "expiry date"
+            | "expire date"
+            | "expires"
+            | "expires on"
+            | "paid-till"
+                if expires.is_none() =>
+            {
+                expires = parse_date(value);
+            }
+            // "clientTransferProhibited https://icann.org/epp#clientTransferProhibited"
+            "domain status" | "status" => {
+                if let Some(status) = value.split_whitespace().next() {
+                    snapshot.statuses.insert(status.to_owned());
+                }
+            }
+            "name server" | "nserver" | "nameserver" => {
+                if let Some(name) = value.split_whitespace().next() {
+                    snapshot.nameservers.insert(nameserver(name));
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(Registration {
+        expires: expires.ok_or_else(|| anyhow::anyhow!("no expiry date in WHOIS response"))?,
+        snapshot,
+    })
+}
+
+// Differences between the last and the current snapshot, if they come from the same source
+fn changes(last: &Snapshot, current: &Snapshot) -> Vec<String> {
+    let mut changes = vec![];
+    if last.source != current.source {
+        return changes;
+    }
+    if last.statuses != current.statuses {
+        changes.push(format!(
+            "status changed from {} to {}",
+            last.statuses.iter().cloned().collect::<Vec<_>>().join(", "),
+            current
+                .statuses
+                .iter()
+                .cloned()
+                .collect::<Vec<_>>()
+                .join(", ")
+        ));
+    }
+    if last.nameservers != current.nameservers {
+        changes.push(format!(
+            "nameservers changed from {} to {}",
+            last.nameservers
+                .iter()
+                .cloned()
+                .collect::<Vec<_>>()
+                .join(", "),
+            current
+                .nameservers
+                .iter()
+                .cloned()
+                .collect::<Vec<_>>()
+                .join(", ")
+        ));
+    }
+    changes
+}
+
+impl Domain {
+    async fn rdap(&self) -> Result<Registration> {
+        let url = format!(
+            "{}/domain/{}",
+            self.rdap_url
+                .as_deref()
+                .unwrap_or("https://rdap.org")
+                .trim_end_matches('/'),
+            self.domain
+        );
+        let domain: RdapDomain = reqwest::Client::new()
+            .get(&url)
+            .header("Accept", "application/rdap+json, application/json")
+            .send()
+            .await
+            // the error already names the url, and its source chain repeats the cause
+            .map_err(|err| anyhow::anyhow!("{}", err))?
+            .error_for_status()?
+            .json()
+            .await
+            .with_context(|| format!("failed parsing response from {}", url))?;
+        let expires = domain
+            .events
+            .iter()
+            .find(|event| event.event_action == "expiration")
+            .ok_or_else(|| anyhow::anyhow!("no expiration event in response from {}", url))?;
+        Ok(Registration {
+            expires: parse_date(&expires.event_date)
+                .ok_or_else(|| anyhow::anyhow!("invalid expiration date {}", expires.event_date))?,
+            snapshot: Snapshot {
+                source: "rdap".to_owned(),
+                statuses: domain.status.iter().map(|s| epp_status(s)).collect(),
+                nameservers: domain
+                    .nameservers
+                    .iter()
+                    .map(|ns| nameserver(&ns.ldh_name))
+                    .collect(),
+            },
+        })
+    }
+
+    async fn query(server: &str, query: &str) -> Result<String> {
+        let address = match server.contains(':') {
+            true => server.to_owned(),
+            false => format!("{}:43", server),
+        };
+        let mut stream = TcpStream::connect(&address)
+            .await
+            .with_context(|| format!("failed connecting to {}", address))?;
+        stream
+            .write_all(format!("{}\r\n", query).as_bytes())
+            .await?;
+        let mut response = vec![];
+        stream.read_to_end(&mut response).await?;
+        Ok(String::from_utf8_lossy(&response).to_string())
+    }
+
+    async fn whois(&self) -> Result<Registration> {
+        let server = match self.whois_server.to_owned() {
+            Some(server) => server,
+            None => {
+                let tld = self.domain.rsplit('.').next().unwrap_or_default();
+                let response = Self::query("whois.iana.org", tld).await?;
+                response
+                    .lines()
+                    .find_map(|line| line.strip_prefix("refer:"))
+                    .map(|server| server.trim().to_owned())
+                    .ok_or_else(|| anyhow::anyhow!("no WHOIS server known for .{}", tld))?
+            }
+        };
+        parse_whois(&Self::query(&server, &self.domain).await?)
+            .with_context(|| format!("failed looking up {} on {}", self.domain, server))
+    }
+
+    async fn lookup(&self) -> Result<Registration> {
+        let rdap_err = match self.rdap().await {
+            Ok(registration) => return Ok(registration),
+            Err(err) => err,
+        };
+        log::debug!(
+            "RDAP lookup of {} failed, falling back to WHOIS: {:#}",
+            self.domain,
+            rdap_err
+        );
+        self.whois().await.map_err(|whois_err| {
+            anyhow::anyhow!(
+                "RDAP lookup failed: {:#}, WHOIS lookup failed: {:#}",
+                rdap_err,
+                whois_err
+            )
+        })
+    }
+}
+
+#[async_trait]
+impl Probe for Domain {
+    fn new() -> Self {
+        Self {
+            ..Default::default()
+        }
+    }
+
+    fn local_schedule(&self) -> Option<String> {
+        self.schedule.to_owned()
+    }
+
+    fn local_name(&self) -> Option<String> {
+        self.name.to_owned()
+    }
+
+    fn depends_on(&self) -> Option<Vec<String>> {
+        self.depends_on.to_owned()
+    }
+
+    fn slug(&self) -> String {
+        slugify(format!("domain-{}", self.domain))
+    }
+
+    async fn observe(
+        &self,
+        store: &Db,
+        alerts: &HashMap<String, Vec<Box<dyn Alert>>>,
+    ) -> Result<()> {
+        log::info!("checking domain {}", self.domain);
+        RUNS_TOTAL
+            .with_label_values(&["probe.domain", &self.domain])
+            .inc();
+
+        let stored = store.get(self.slug().as_bytes())?;
+        let mut to_store = NO_INCIDENT;
+        let mut triggered = 0;
+        // statuses and nameservers as of the last check
+        let last_key = format!("{}-last", self.slug());
+        let last: Option<Snapshot> = match store.get(last_key.as_bytes())? {
+            Some(last) => serde_json::from_slice(&last).ok(),
+            None => None,
+        };
+
+        let seconds = self.timeout.unwrap_or(10);
+        let result = match timeout(Duration::from_secs(seconds), self.lookup()).await {
+            Ok(result) => result,
+            Err(_) => Err(anyhow::anyhow!("timed out after {} seconds", seconds)),
+        };
+
+        let mut problems: Vec<String> = vec![];
+        let mut changed = false;
+        match result {
+            Ok(registration) => {
+                let days = (registration.expires - Utc::now()).num_days();
+                EXPIRY_DAYS
+                    .with_label_values(&["probe.domain", &self.domain])
+                    .set(days as f64);
+                let date = registration.expires.format("%Y-%m-%d");
+                if days < 0 {
+                    problems.push(format!("expired {} days ago on {}", -days, date));
+                } else if days < self.warn_days.unwrap_or(30) {
+                    problems.push(format!("expires in {} days on {}", days, date));
+                }
+                let alert_statuses: Vec<String> = match self.alert_statuses.to_owned() {
+                    Some(statuses) => statuses,
+                    None => ALERT_STATUSES.iter().map(|s| s.to_string()).collect(),
+                };
+                for status in registration.snapshot.statuses.iter() {
+                    if alert_statuses.contains(status) {
+                        problems.push(format!("status is {}", status));
+                    }
+                }
+                if let Some(last) = last.as_ref() {
+                    let changes = changes(last, &registration.snapshot);
+                    changed = !changes.is_empty();
+                    problems.extend(changes);
+                }
+                store.insert(
+                    last_key.as_bytes(),
+                    serde_json::to_vec(&registration.snapshot)?,
+                )?;
+            }
+            Err(err) => problems.push(format!("{:#}", err)),
+        }
+
+        if !problems.is_empty() {
+            log::info!(
+                "_TRIGGERED_: domain {}: {}",
+                self.domain,
+                problems.join(", ")
+            );
+            // a change is a new event, eg. nameservers moved by a hijack, so notify it even
+            // while already in incident for expiry or a held status
+            if stored.is_none() || stored == Some(IVec::from(NO_INCIDENT)) || changed {
+                log::warn!("_NOTIFY_: domain {}: {}", self.domain, problems.join(", "));
+                self.notify(
+                    alerts,
+                    Notification {
+                        from: "domain".to_owned(),
+                        name: self.name("domain", self.name.to_owned()),
+                        check: format!("domain registration {}", self.domain),
+                        title: format!("{} problem(s) with domain {}", problems.len(), self.domain),
+                        message: problems.join("\n"),
+                        message_html: None,
+                        message_entries: None,
+                    },
+                )
+                .await?;
+            }
+            TRIGGERED_TOTAL
+                .with_label_values(&["probe.domain", &self.domain])
+                .inc();
+            triggered = 1;
+            to_store = HAS_INCIDENT;
+        }
+
+        store.insert(self.slug().as_bytes(), to_store)?;
+
+        TRIGGERED
+            .with_label_values(&["probe.domain", &self.domain])
+            .set(triggered as f64);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::probes::testing;
+    use serde_json::json;
+    use std::{
+        net::SocketAddr,
+        sync::{Arc, Mutex},
+    };
+    use tokio::net::TcpListener;
+    use warp::Filter;
+
+    #[test]
+    fn test_parse_date() {
+        let expected = Utc.ymd(2030, 8, 13).and_hms(4, 0, 0);
+        assert_eq!(parse_date("2030-08-13T04:00:00Z"), Some(expected));
+        assert_eq!(parse_date("2030-08-13T04:00:00.000Z"), Some(expected));
+        assert_eq!(parse_date("2030-08-13 04:00:00"), Some(expected));
+        assert_eq!(
+            parse_date("13-Aug-2030"),
+            Some(Utc.ymd(2030, 8, 13).and_hms(0, 0, 0))
+        );
+        assert_eq!(parse_date("soon"), None);
+    }
+
+    #[test]
+    fn test_changes() {
+        let last = Snapshot {
+            source: "rdap".to_owned(),
+            statuses: vec!["ok".to_owned()].into_iter().collect(),
+            nameservers: vec!["a.ns.test".to_owned(), "b.ns.test".to_owned()]
+                .into_iter()
+                .collect(),
+        };
+        assert!(changes(&last, &last).is_empty());
+        let current = Snapshot {
+            statuses: vec!["clientHold".to_owned()].into_iter().collect(),
+            nameservers: vec!["a.ns.test".to_owned(), "c.ns.test".to_owned()]
+                .into_iter()
+                .collect(),
+            ..Default::default()
+        };
+        assert!(changes(&last, &current).is_empty());
+        let current = Snapshot {
+            source: "rdap".to_owned(),
+            ..current
+        };
+        assert_eq!(
+            changes(&last, &current),
+            vec![
+                "status changed from ok to clientHold",
+                "nameservers changed from a.ns.test, b.ns.test to a.ns.test, c.ns.test",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_lookup_from_fake_rdap_and_whois() {
+        let rdap = warp::path!("domain" / String).map(|domain: String| {
+            let reply = match domain.as_str() {
+                "example.test" => warp::reply::json(&json!({
+                    "objectClassName": "domain",
+                    "ldhName": "EXAMPLE.TEST",
+                    "status": ["client hold", "client transfer prohibited"],
+                    "events": [
+                        {"eventAction": "registration", "eventDate": "2001-08-13T04:00:00Z"},
+                        {"eventAction": "expiration", "eventDate": "2030-08-13T04:00:00Z"},
+                    ],
+                    "nameservers": [
+                        {"objectClassName": "nameserver", "ldhName": "A.NS.TEST."},
+                        {"objectClassName": "nameserver", "ldhName": "b.ns.test"},
+                    ],
+                })),
+                _ => warp::reply::json(&json!({"errorCode": 404})),
+            };
+            let status = match domain.as_str() {
+                "example.test" => warp::http::StatusCode::OK,
+                _ => warp::http::StatusCode::NOT_FOUND,
+            };
+            warp::reply::with_status(reply, status)
+        });
+        let (addr, server) =
+            warp::serve(rdap).bind_ephemeral("127.0.0.1:0".parse::<SocketAddr>().unwrap());
+        tokio::spawn(server);
+
+        let whois = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let whois_addr = whois.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _) = whois.accept().await.unwrap();
+            let mut query = [0u8; 64];
+            let n = stream.read(&mut query).await.unwrap();
+            assert_eq!(&query[..n], b"other.test\r\n");
+            stream
+                .write_all(
+                    b"Domain Name: OTHER.TEST\r\n\
+                    Registry Expiry Date: 2031-01-02T00:00:00Z\r\n\
+                    Domain Status: ok https://icann.org/epp#ok\r\n\
+                    Name Server: NS1.OTHER.TEST\r\n\
+                    >>> Last update of whois database: 2026-10-19T00:00:00Z <<<\r\n",
+                )
+                .await
+                .unwrap();
+        });
+
+        let probe = Domain {
+            domain: "example.test".to_owned(),
+            rdap_url: Some(format!("http://{}/", addr)),
+            ..Default::default()
+        };
+        let registration = probe.lookup().await.unwrap();
+        assert_eq!(registration.expires, Utc.ymd(2030, 8, 13).and_hms(4, 0, 0));
+        assert_eq!(registration.snapshot.source, "rdap");
+        assert_eq!(
+            registration
+                .snapshot
+                .statuses
+                .into_iter()
+                .collect::<Vec<_>>(),
+            vec!["clientHold", "clientTransferProhibited"]
+        );
+        assert_eq!(
+            registration
+                .snapshot
+                .nameservers
+                .into_iter()
+                .collect::<Vec<_>>(),
+            vec!["a.ns.test", "b.ns.test"]
+        );
+
+        let probe = Domain {
+            domain: "other.test".to_owned(),
+            rdap_url: Some(format!("http://{}", addr)),
+            whois_server: Some(whois_addr.to_string()),
+            ..Default::default()
+        };
+        let registration = probe.lookup().await.unwrap();
+        assert_eq!(registration.expires, Utc.ymd(2031, 1, 2).and_hms(0, 0, 0));
+        assert_eq!(registration.snapshot.source, "whois");
+        assert_eq!(
+            registration
+                .snapshot
+                .statuses
+                .into_iter()
+                .collect::<Vec<_>>(),
+            vec!["ok"]
+        );
+        assert_eq!(
+            registration
+                .snapshot
+                .nameservers
+                .into_iter()
+                .collect::<Vec<_>>(),
+            vec!["ns1.other.test"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_nameserver_change_notified_during_incident() {
+        let nameservers = Arc::new(Mutex::new("a.ns.test"));
+        let served = nameservers.clone();
+        // expires within warn_days, so the domain stays in incident throughout
+        let expires = (Utc::now() + chrono::Duration::days(5))
+            .format("%Y-%m-%dT%H:%M:%SZ")
+            .to_string();
+        let rdap = warp::path!("domain" / String).map(move |_domain: String| {
+            warp::reply::json(&json!({
+                "status": ["active"],
+                "events": [{"eventAction": "expiration", "eventDate": expires}],
+                "nameservers": [{"ldhName": *served.lock().unwrap()}],
+            }))
+        });
+        let (addr, server) =
+            warp::serve(rdap).bind_ephemeral("127.0.0.1:0".parse::<SocketAddr>().unwrap());
+        tokio::spawn(server);
+
+        let probe = Domain {
+            domain: "example.test".to_owned(),
+            rdap_url: Some(format!("http://{}", addr)),
+            ..Default::default()
+        };
+        let (store, alerts, sent) = testing::setup();
+        probe.observe(&store, &alerts).await.unwrap();
+        let notified = testing::drain(&sent);
+        assert_eq!(notified.len(), 1);
+        assert!(notified[0].contains("expires in 4 days"));
+        probe.observe(&store, &alerts).await.unwrap();
+        assert!(testing::drain(&sent).is_empty());
+
+        *nameservers.lock().unwrap() = "evil.ns.test";
+        probe.observe(&store, &alerts).await.unwrap();
+        let notified = testing::drain(&sent);
+        assert_eq!(notified.len(), 1);
+        assert!(notified[0].contains("nameservers changed from a.ns.test to evil.ns.test"));
+        assert_eq!(
+            store.get(probe.slug().as_bytes()).unwrap(),
+            Some(IVec::from(HAS_INCIDENT))
+        );
+        probe.observe(&store, &alerts).await.unwrap();
+        assert!(testing::drain(&sent).is_empty());
+    }
+}
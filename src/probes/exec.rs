@@ -15,6 +15,7 @@ use std::{collections::HashMap, process::Command};
 pub struct Exec {
     name: Option<String>,
     schedule: Option<String>,
+    depends_on: Option<Vec<String>>,
     cmd: String,
     args: Option<Vec<String>>,
 }
@@ -52,6 +53,14 @@ impl Probe for Exec {
         self.schedule.to_owned()
     }
 
+    fn local_name(&self) -> Option<String> {
+        self.name.to_owned()
+    }
+
+    fn depends_on(&self) -> Option<Vec<String>> {
+        self.depends_on.to_owned()
+    }
+
     fn slug(&self) -> String {
         slugify(format!(
             "exec-{}-{}",
@@ -0,0 +1,412 @@
+use crate::{
+    alerts::Alert,
+    probes::{process::uid_of, MessageEntry, Notification, Probe, HAS_INCIDENT, NO_INCIDENT},
+};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use lazy_static::lazy_static;
+use prometheus::{register_counter_vec, register_gauge_vec, CounterVec, GaugeVec};
+use serde_derive::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sled::{Db, IVec};
+use slug::slugify;
+use std::{
+    collections::HashMap,
+    fs, io,
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct File {
+    name: Option<String>,
+    schedule: Option<String>,
+    depends_on: Option<Vec<String>>,
+    // path or glob pattern, at least one file should match
+    path: String,
+    // seconds since the newest match was modified
+    max_age: Option<u64>,
+    // bounds of each matching file in bytes
+    min_size: Option<u64>,
+    max_size: Option<u64>,
+    // user name or uid, and octal permissions like "640"
+    owner: Option<String>,
+    permissions: Option<String>,
+    // suffix of sha256sum sidecar files to verify each match against, eg. ".sha256"
+    checksum_sidecar: Option<String>,
+}
+
+lazy_static! {
+    static ref RUNS_TOTAL: CounterVec = register_counter_vec!(
+        "probe_file_runs_total",
+        "run counter for file probe plugin",
+        &["plugin", "path"]
+    )
+    .unwrap();
+    static ref TRIGGERED_TOTAL: CounterVec = register_counter_vec!(
+        "probe_file_triggered_total",
+        "triggered counter for file probe plugin",
+        &["plugin", "path"]
+    )
+    .unwrap();
+    static ref TRIGGERED: GaugeVec = register_gauge_vec!(
+        "probe_file_triggered",
+        "File probe plugin triggered",
+        &["plugin", "path"]
+    )
+    .unwrap();
+    static ref MATCHES: GaugeVec = register_gauge_vec!(
+        "probe_file_matches",
+        "number of files matching the path from file probe plugin",
+        &["plugin", "path"]
+    )
+    .unwrap();
+    static ref NEWEST_AGE_SECONDS: GaugeVec = register_gauge_vec!(
+        "probe_file_newest_age_seconds",
+        "seconds since the newest matching file was modified",
+        &["plugin", "path"]
+    )
+    .unwrap();
+}
+
+// Checksum of a file as of its modification time and size, so unchanged files aren't hashed again
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Checksum {
+    modified: (i64, i64),
+    size: u64,
+    sha256: String,
+}
+
+fn sha256_of(path: &Path) -> Result<String> {
+    let mut file = fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+impl File {
+    // Problems found with a single matching file, blocking as it may hash the file
+    fn inspect(
+        &self,
+        path: &Path,
+        uid: Option<u32>,
+        mode: Option<u32>,
+        checksums: &mut HashMap<String, Checksum>,
+    ) -> Result<Vec<String>> {
+        let metadata = fs::metadata(path)?;
+        let mut problems = vec![];
+        if let Some(min_size) = self.min_size {
+            if metadata.len() < min_size {
+                problems.push(format!(
+                    "size {} bytes below {} bytes",
+                    metadata.len(),
+                    min_size
+                ));
+            }
+        }
+        if let Some(max_size) = self.max_size {
+            if metadata.len() > max_size {
+                problems.push(format!(
+                    "size {} bytes above {} bytes",
+                    metadata.len(),
+                    max_size
+                ));
+            }
+        }
+        if let Some(uid) = uid {
+            if metadata.uid() != uid {
+                problems.push(format!("owned by uid {}, want {}", metadata.uid(), uid));
+            }
+        }
+        if let Some(mode) = mode {
+            if metadata.mode() & 0o7777 != mode {
+                problems.push(format!(
+                    "permissions {:o}, want {:o}",
+                    metadata.mode() & 0o7777,
+                    mode
+                ));
+            }
+        }
+        if let Some(suffix) = self.checksum_sidecar.as_ref() {
+            let mut sidecar = path.as_os_str().to_owned();
+            sidecar.push(suffix);
+            match fs::read_to_string(&sidecar) {
+                // sha256sum format, checksum followed by file name
+                Ok(content) => {
+                    let expected = content
+                        .split_whitespace()
+                        .next()
+                        .unwrap_or_default()
+                        .to_lowercase();
+                    let key = path.to_string_lossy().to_string();
+                    let modified = (metadata.mtime(), metadata.mtime_nsec());
+                    let checksum = match checksums.remove(&key) {
+                        Some(checksum)
+                            if checksum.modified == modified && checksum.size == metadata.len() =>
+                        {
+                            checksum
+                        }
+                        _ => Checksum {
+                            modified,
+                            size: metadata.len(),
+                            sha256: sha256_of(path)?,
+                        },
+                    };
+                    let actual = checksum.sha256.to_owned();
+                    checksums.insert(key, checksum);
+                    if actual != expected {
+                        problems.push(format!(
+                            "sha256 {} does not match {} from {}",
+                            actual,
+                            expected,
+                            sidecar.to_string_lossy()
+                        ));
+                    }
+                }
+                Err(err) => problems.push(format!(
+                    "failed reading checksum {}: {}",
+                    sidecar.to_string_lossy(),
+                    err
+                )),
+            }
+        }
+        Ok(problems)
+    }
+}
+
+#[async_trait]
+impl Probe for File {
+    fn new() -> Self {
+        Self {
+            ..Default::default()
+        }
+    }
+
+    fn local_schedule(&self) -> Option<String> {
+        self.schedule.to_owned()
+    }
+
+    fn local_name(&self) -> Option<String> {
+        self.name.to_owned()
+    }
+
+    fn depends_on(&self) -> Option<Vec<String>> {
+        self.depends_on.to_owned()
+    }
+
+    fn slug(&self) -> String {
+        slugify(format!("file-{}", self.path))
+    }
+
+    async fn observe(
+        &self,
+        store: &Db,
+        alerts: &HashMap<String, Vec<Box<dyn Alert>>>,
+    ) -> Result<()> {
+        log::info!("checking files matching {}", self.path);
+        RUNS_TOTAL
+            .with_label_values(&["probe.file", &self.path])
+            .inc();
+
+        let stored = store.get(self.slug().as_bytes())?;
+        let mut to_store = NO_INCIDENT;
+        let mut triggered = 0;
+        let uid = match self.owner.as_ref() {
+            Some(owner) => Some(uid_of(owner)?),
+            None => None,
+        };
+        let mode = match self.permissions.as_ref() {
+            Some(permissions) => Some(
+                u32::from_str_radix(permissions, 8)
+                    .with_context(|| format!("invalid permissions {}", permissions))?,
+            ),
+            None => None,
+        };
+        let paths: Vec<PathBuf> = glob::glob(&self.path)
+            .with_context(|| format!("invalid path pattern {}", self.path))?
+            .filter_map(|entry| entry.ok())
+            .filter(|path| path.is_file())
+            // don't treat checksum sidecars as files to check
+            .filter(|path| match self.checksum_sidecar.as_ref() {
+                Some(suffix) => !path.to_string_lossy().ends_with(suffix.as_str()),
+                None => true,
+            })
+            .collect();
+        MATCHES
+            .with_label_values(&["probe.file", &self.path])
+            .set(paths.len() as f64);
+
+        let mut problems: Vec<String> = vec![];
+        let mut message_entries: Vec<(i8, MessageEntry)> = vec![];
+        if paths.is_empty() {
+            problems.push(format!("no file matches {}", self.path));
+        }
+
+        let newest = paths
+            .iter()
+            .filter_map(|path| Some((path, fs::metadata(path).ok()?.modified().ok()?)))
+            .max_by_key(|(_, modified)| *modified);
+        if let Some((path, modified)) = newest {
+            let age = SystemTime::now()
+                .duration_since(modified)
+                .unwrap_or_default()
+                .as_secs();
+            NEWEST_AGE_SECONDS
+                .with_label_values(&["probe.file", &self.path])
+                .set(age as f64);
+            if let Some(max_age) = self.max_age {
+                if age > max_age {
+                    problems.push(format!(
+                        "newest file {} was modified {} seconds ago, more than {} seconds",
+                        path.display(),
+                        age,
+                        max_age
+                    ));
+                }
+            }
+        }
+
+        // checksums by path as of the last check
+        let checksums_key = format!("{}-checksums", self.slug());
+        let mut checksums: HashMap<String, Checksum> = match store.get(checksums_key.as_bytes())? {
+            Some(checksums) => serde_json::from_slice(&checksums).unwrap_or_default(),
+            None => HashMap::new(),
+        };
+        let plugin = self.clone();
+        let (inspected, checksums) = tokio::task::spawn_blocking(move || {
+            let inspected: Vec<(PathBuf, Vec<String>)> = paths
+                .into_iter()
+                .map(|path| {
+                    let failed = match plugin.inspect(&path, uid, mode, &mut checksums) {
+                        Ok(failed) => failed,
+                        Err(err) => vec![format!("failed inspecting: {:#}", err)],
+                    };
+                    (path, failed)
+                })
+                .collect();
+            // forget files that no longer match
+            checksums.retain(|key, _| {
+                inspected
+                    .iter()
+                    .any(|(path, _)| path.to_string_lossy() == key.as_str())
+            });
+            (inspected, checksums)
+        })
+        .await?;
+        if self.checksum_sidecar.is_some() {
+            store.insert(checksums_key.as_bytes(), serde_json::to_vec(&checksums)?)?;
+        }
+
+        for (path, failed) in inspected.iter() {
+            if failed.is_empty() {
+                continue;
+            }
+            problems.push(format!("{} {}", path.display(), failed.join(", ")));
+            if message_entries.len() < i8::MAX as usize {
+                message_entries.push((
+                    message_entries.len() as i8, // index of this message entry
+                    MessageEntry {
+                        title: path.display().to_string(),
+                        description: failed.join("\n"),
+                    },
+                ));
+            }
+        }
+
+        if !problems.is_empty() {
+            log::info!(
+                "_TRIGGERED_: files matching {}: {}",
+                self.path,
+                problems.join(", ")
+            );
+            if stored.is_none() || stored == Some(IVec::from(NO_INCIDENT)) {
+                log::warn!(
+                    "_NOTIFY_: files matching {}: {}",
+                    self.path,
+                    problems.join(", ")
+                );
+                self.notify(
+                    alerts,
+                    Notification {
+                        from: "file".to_owned(),
+                        name: self.name("file", self.name.to_owned()),
+                        check: format!("files matching {}", self.path),
+                        title: format!(
+                            "{} problem(s) with files matching {}",
+                            problems.len(),
+                            self.path
+                        ),
+                        message: problems.join("\n"),
+                        message_html: None,
+                        message_entries: if message_entries.is_empty() {
+                            None
+                        } else {
+                            Some(message_entries)
+                        },
+                    },
+                )
+                .await?;
+            }
+            TRIGGERED_TOTAL
+                .with_label_values(&["probe.file", &self.path])
+                .inc();
+            triggered = 1;
+            to_store = HAS_INCIDENT;
+        }
+
+        store.insert(self.slug().as_bytes(), to_store)?;
+
+        TRIGGERED
+            .with_label_values(&["probe.file", &self.path])
+            .set(triggered as f64);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_checksum_cached_until_file_changes() {
+        let dir = std::env::temp_dir().join(format!("otto-file-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("backup.tar");
+        fs::write(&path, "backup").unwrap();
+        // checksum of some other content
+        let sha256 = "9e8d4e62d2ae0c67b1e3d8d79bcaf2d5a8c3bd9c1d37da4bbd7c0c9e3a1cf92e";
+        let actual = sha256_of(&path).unwrap();
+        fs::write(
+            dir.join("backup.tar.sha256"),
+            format!("{}  backup.tar\n", actual),
+        )
+        .unwrap();
+        assert_ne!(actual, sha256);
+
+        let probe = File {
+            checksum_sidecar: Some(".sha256".to_owned()),
+            ..Default::default()
+        };
+        let mut checksums = HashMap::new();
+        assert!(probe
+            .inspect(&path, None, None, &mut checksums)
+            .unwrap()
+            .is_empty());
+        let key = path.to_string_lossy().to_string();
+        assert_eq!(checksums[&key].sha256, actual);
+
+        // unchanged files are not hashed again, so a tampered cache entry shows up
+        checksums.get_mut(&key).unwrap().sha256 = sha256.to_owned();
+        let problems = probe.inspect(&path, None, None, &mut checksums).unwrap();
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].starts_with(&format!("sha256 {} does not match", sha256)));
+
+        // a different size means the file changed and is hashed again
+        fs::write(&path, "backup, take two").unwrap();
+        let problems = probe.inspect(&path, None, None, &mut checksums).unwrap();
+        assert_eq!(problems.len(), 1);
+        assert_eq!(checksums[&key].sha256, sha256_of(&path).unwrap());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,258 @@
+use crate::{
+    alerts::Alert,
+    probes::{Notification, Probe, HAS_INCIDENT, NO_INCIDENT},
+};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use lazy_static::lazy_static;
+use prometheus::{register_counter_vec, register_gauge_vec, CounterVec, GaugeVec};
+use serde_derive::Deserialize;
+use sled::{Db, IVec};
+use slug::slugify;
+use std::{collections::HashMap, fs};
+use tokio::time::{timeout, Duration, Instant};
+use tonic::transport::{Certificate, ClientTlsConfig, Endpoint, Identity};
+use tonic_health::pb::{
+    health_check_response::ServingStatus, health_client::HealthClient, HealthCheckRequest,
+};
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Grpc {
+    name: Option<String>,
+    schedule: Option<String>,
+    depends_on: Option<Vec<String>>,
+    // https:// for TLS, http:// for plaintext
+    target: String,
+    // service to check, overall server health when empty
+    service: Option<String>,
+    // PEM encoded CA to verify the server with, instead of system roots
+    tls_ca: Option<String>,
+    // server name to verify, when different from target host
+    tls_domain: Option<String>,
+    // PEM encoded client certificate and key for mutual TLS
+    tls_client_cert: Option<String>,
+    tls_client_key: Option<String>,
+    // seconds, default 5
+    deadline: Option<u64>,
+}
+
+lazy_static! {
+    static ref RUNS_TOTAL: CounterVec = register_counter_vec!(
+        "probe_grpc_runs_total",
+        "run counter for gRPC probe plugin",
+        &["plugin", "target", "service"]
+    )
+    .unwrap();
+    static ref TRIGGERED_TOTAL: CounterVec = register_counter_vec!(
+        "probe_grpc_triggered_total",
+        "triggered counter for gRPC probe plugin",
+        &["plugin", "target", "service"]
+    )
+    .unwrap();
+    static ref TRIGGERED: GaugeVec = register_gauge_vec!(
+        "probe_grpc_triggered",
+        "gRPC probe plugin triggered",
+        &["plugin", "target", "service"]
+    )
+    .unwrap();
+    static ref RESPONSE_SECONDS: GaugeVec = register_gauge_vec!(
+        "probe_grpc_response_seconds",
+        "time taken by the gRPC health check",
+        &["plugin", "target", "service"]
+    )
+    .unwrap();
+}
+
+impl Grpc {
+    fn endpoint(&self, deadline: Duration) -> Result<Endpoint> {
+        let mut endpoint = Endpoint::from_shared(self.target.to_owned())
+            .with_context(|| format!("invalid target {}", self.target))?
+            .connect_timeout(deadline)
+            .timeout(deadline);
+        if self.target.starts_with("https://") {
+            let mut tls = ClientTlsConfig::new();
+            if let Some(tls_ca) = self.tls_ca.as_ref() {
+                let pem = fs::read(tls_ca)
+                    .with_context(|| format!("failed reading tls_ca {}", tls_ca))?;
+                tls = tls.ca_certificate(Certificate::from_pem(pem));
+            }
+            if let Some(tls_domain) = self.tls_domain.as_ref() {
+                tls = tls.domain_name(tls_domain);
+            }
+            if let (Some(cert), Some(key)) =
+                (self.tls_client_cert.as_ref(), self.tls_client_key.as_ref())
+            {
+                let cert = fs::read(cert)
+                    .with_context(|| format!("failed reading tls_client_cert {}", cert))?;
+                let key = fs::read(key)
+                    .with_context(|| format!("failed reading tls_client_key {}", key))?;
+                tls = tls.identity(Identity::from_pem(cert, key));
+            }
+            endpoint = endpoint.tls_config(tls)?;
+        }
+        Ok(endpoint)
+    }
+
+    async fn check(&self, deadline: Duration) -> Result<ServingStatus> {
+        let channel = self
+            .endpoint(deadline)?
+            .connect()
+            .await
+            .context("failed connecting")?;
+        let response = HealthClient::new(channel)
+            .check(HealthCheckRequest {
+                service: self.service.to_owned().unwrap_or_default(),
+            })
+            .await
+            .map_err(|status| anyhow::anyhow!("{:?}: {}", status.code(), status.message()))?;
+        Ok(response.into_inner().status())
+    }
+}
+
+#[async_trait]
+impl Probe for Grpc {
+    fn new() -> Self {
+        Self {
+            ..Default::default()
+        }
+    }
+
+    fn local_schedule(&self) -> Option<String> {
+        self.schedule.to_owned()
+    }
+
+    fn local_name(&self) -> Option<String> {
+        self.name.to_owned()
+    }
+
+    fn depends_on(&self) -> Option<Vec<String>> {
+        self.depends_on.to_owned()
+    }
+
+    fn slug(&self) -> String {
+        slugify(format!(
+            "grpc-{}-{}",
+            self.target,
+            self.service.to_owned().unwrap_or_default()
+        ))
+    }
+
+    async fn observe(
+        &self,
+        store: &Db,
+        alerts: &HashMap<String, Vec<Box<dyn Alert>>>,
+    ) -> Result<()> {
+        let service = self.service.to_owned().unwrap_or_default();
+        let check = if service.is_empty() {
+            format!("grpc health of {}", self.target)
+        } else {
+            format!("grpc health of {} on {}", service, self.target)
+        };
+        log::info!("checking {}", check);
+        RUNS_TOTAL
+            .with_label_values(&["probe.grpc", &self.target, &service])
+            .inc();
+
+        let stored = store.get(self.slug().as_bytes())?;
+        let mut to_store = NO_INCIDENT;
+        let mut triggered = 0;
+        let deadline = Duration::from_secs(self.deadline.unwrap_or(5));
+        let started = Instant::now();
+        let result = match timeout(deadline, self.check(deadline)).await {
+            Ok(result) => result,
+            Err(_) => Err(anyhow::anyhow!(
+                "deadline of {} seconds exceeded",
+                deadline.as_secs()
+            )),
+        };
+        RESPONSE_SECONDS
+            .with_label_values(&["probe.grpc", &self.target, &service])
+            .set(started.elapsed().as_secs_f64());
+
+        let message = match result {
+            Ok(ServingStatus::Serving) => None,
+            Ok(status) => Some(format!("status is {}", status.as_str_name())),
+            Err(err) => Some(format!("{:#}", err)),
+        };
+
+        if let Some(message) = message {
+            log::info!("_TRIGGERED_: {}: {}", check, message);
+            if stored.is_none() || stored == Some(IVec::from(NO_INCIDENT)) {
+                log::warn!("_NOTIFY_: {}: {}", check, message);
+                self.notify(
+                    alerts,
+                    Notification {
+                        from: "grpc".to_owned(),
+                        name: self.name("grpc", self.name.to_owned()),
+                        check: check.clone(),
+                        title: format!("{} failed", check),
+                        message,
+                        message_html: None,
+                        message_entries: None,
+                    },
+                )
+                .await?;
+            }
+            TRIGGERED_TOTAL
+                .with_label_values(&["probe.grpc", &self.target, &service])
+                .inc();
+            triggered = 1;
+            to_store = HAS_INCIDENT;
+        }
+
+        store.insert(self.slug().as_bytes(), to_store)?;
+
+        TRIGGERED
+            .with_label_values(&["probe.grpc", &self.target, &service])
+            .set(triggered as f64);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tokio::net::TcpListener;
+    use tokio_stream::wrappers::TcpListenerStream;
+    use tonic::transport::Server;
+
+    #[tokio::test]
+    async fn test_check_against_local_server() {
+        let (mut reporter, service) = tonic_health::server::health_reporter();
+        reporter
+            .set_service_status("up", tonic_health::ServingStatus::Serving)
+            .await;
+        reporter
+            .set_service_status("down", tonic_health::ServingStatus::NotServing)
+            .await;
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let target = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(
+            Server::builder()
+                .add_service(service)
+                .serve_with_incoming(TcpListenerStream::new(listener)),
+        );
+
+        let probe = |service: &str| Grpc {
+            target: target.clone(),
+            service: Some(service.to_owned()),
+            ..Default::default()
+        };
+        let deadline = Duration::from_secs(5);
+        assert_eq!(
+            probe("up").check(deadline).await.unwrap(),
+            ServingStatus::Serving
+        );
+        assert_eq!(
+            probe("down").check(deadline).await.unwrap(),
+            ServingStatus::NotServing
+        );
+        assert!(probe("missing").check(deadline).await.is_err());
+
+        let unreachable = Grpc {
+            target: "http://127.0.0.1:1".to_owned(),
+            ..Default::default()
+        };
+        assert!(unreachable.check(deadline).await.is_err());
+    }
+}
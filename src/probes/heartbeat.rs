@@ -0,0 +1,281 @@
+use crate::{
+    alerts::Alert,
+    probes::{Notification, Probe, HAS_INCIDENT, NO_INCIDENT},
+};
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{TimeZone, Utc};
+use lazy_static::lazy_static;
+use prometheus::{register_counter_vec, register_gauge_vec, CounterVec, GaugeVec};
+use serde_derive::Deserialize;
+use sled::{Db, IVec};
+use slug::slugify;
+use std::{collections::HashMap, convert::TryInto};
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Heartbeat {
+    name: Option<String>,
+    schedule: Option<String>,
+    depends_on: Option<Vec<String>>,
+    token: String,
+    // seconds between expected pings
+    period: i64,
+    // extra seconds to wait for a late ping
+    grace: Option<i64>,
+}
+
+lazy_static! {
+    static ref RUNS_TOTAL: CounterVec = register_counter_vec!(
+        "probe_heartbeat_runs_total",
+        "run counter for heartbeat probe plugin",
+        &["plugin", "name"]
+    )
+    .unwrap();
+    static ref PINGS_TOTAL: CounterVec = register_counter_vec!(
+        "probe_heartbeat_pings_total",
+        "ping counter for heartbeat probe plugin",
+        &["plugin", "signal"]
+    )
+    .unwrap();
+    static ref TRIGGERED_TOTAL: CounterVec = register_counter_vec!(
+        "probe_heartbeat_triggered_total",
+        "triggered counter for heartbeat probe plugin",
+        &["plugin", "name"]
+    )
+    .unwrap();
+    static ref TRIGGERED: GaugeVec = register_gauge_vec!(
+        "probe_heartbeat_triggered",
+        "Heartbeat probe plugin triggered",
+        &["plugin", "name"]
+    )
+    .unwrap();
+}
+
+fn key(token: &str, signal: &str) -> String {
+    format!("{}-{}", slugify(format!("heartbeat-{}", token)), signal)
+}
+
+fn read_time(store: &Db, key: &str) -> Result<Option<i64>> {
+    match store.get(key.as_bytes())? {
+        Some(stored) => Ok(stored.as_ref().try_into().ok().map(i64::from_be_bytes)),
+        None => Ok(None),
+    }
+}
+
+fn format_time(timestamp_millis: i64) -> String {
+    Utc.timestamp_millis(timestamp_millis).to_rfc3339()
+}
+
+// Record a ping, start or fail signal received on /heartbeat/{token}
+pub fn record(store: &Db, token: &str, signal: &str) -> Result<()> {
+    match signal {
+        "ping" | "start" | "fail" => {
+            let now = Utc::now().timestamp_millis();
+            store.insert(key(token, signal).as_bytes(), &now.to_be_bytes())?;
+            PINGS_TOTAL
+                .with_label_values(&["probe.heartbeat", signal])
+                .inc();
+            Ok(())
+        }
+        _ => anyhow::bail!("unknown heartbeat signal: {}", signal),
+    }
+}
+
+impl Heartbeat {
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+}
+
+#[async_trait]
+impl Probe for Heartbeat {
+    fn new() -> Self {
+        Self {
+            ..Default::default()
+        }
+    }
+
+    fn local_schedule(&self) -> Option<String> {
+        self.schedule.to_owned()
+    }
+
+    fn local_name(&self) -> Option<String> {
+        self.name.to_owned()
+    }
+
+    fn depends_on(&self) -> Option<Vec<String>> {
+        self.depends_on.to_owned()
+    }
+
+    fn slug(&self) -> String {
+        slugify(format!("heartbeat-{}", self.token))
+    }
+
+    async fn observe(
+        &self,
+        store: &Db,
+        alerts: &HashMap<String, Vec<Box<dyn Alert>>>,
+    ) -> Result<()> {
+        let name = self.name("heartbeat", self.name.to_owned());
+        log::info!("checking heartbeat {}", name);
+        RUNS_TOTAL
+            .with_label_values(&["probe.heartbeat", &name])
+            .inc();
+
+        let stored = store.get(self.slug().as_bytes())?;
+        let mut to_store = NO_INCIDENT;
+        let mut triggered = 0;
+        let now = Utc::now().timestamp_millis();
+        let last_ping = read_time(store, &key(&self.token, "ping"))?;
+        let last_start = read_time(store, &key(&self.token, "start"))?;
+        let last_fail = read_time(store, &key(&self.token, "fail"))?;
+        // without any ping yet, count the period from when the probe first ran
+        let since = match last_ping {
+            Some(last_ping) => last_ping,
+            None => match read_time(store, &key(&self.token, "since"))? {
+                Some(since) => since,
+                None => {
+                    store.insert(key(&self.token, "since").as_bytes(), &now.to_be_bytes())?;
+                    now
+                }
+            },
+        };
+        let deadline = since + (self.period + self.grace.unwrap_or_default()) * 1000;
+
+        let mut title: String = "".to_owned();
+        let mut message: String = "".to_owned();
+        let mut found_incident = false;
+        if last_fail.unwrap_or_default() > last_ping.unwrap_or_default() {
+            found_incident = true;
+            title = format!("{} reported failure", name);
+            message = format!(
+                "heartbeat {} received a fail signal at {}",
+                name,
+                format_time(last_fail.unwrap_or_default())
+            );
+        } else if now > deadline {
+            found_incident = true;
+            title = format!("{} missed its heartbeat", name);
+            message = match last_ping {
+                Some(last_ping) => format!(
+                    "no ping received since {}, expected one by {}",
+                    format_time(last_ping),
+                    format_time(deadline)
+                ),
+                None => format!(
+                    "no ping received, expected one by {}",
+                    format_time(deadline)
+                ),
+            };
+            if let Some(last_start) = last_start {
+                if last_start > since {
+                    message = format!("{}, last started at {}", message, format_time(last_start));
+                }
+            }
+        }
+
+        if found_incident {
+            log::info!("_TRIGGERED_: {}", message);
+            if stored.is_none() || stored == Some(IVec::from(NO_INCIDENT)) {
+                log::warn!("_NOTIFY_: {}", message);
+                self.notify(
+                    alerts,
+                    Notification {
+                        from: "heartbeat".to_owned(),
+                        name: name.clone(),
+                        check: format!("heartbeat {} expected every {} seconds", name, self.period),
+                        title,
+                        message,
+                        message_html: None,
+                        message_entries: None,
+                    },
+                )
+                .await?;
+            }
+            TRIGGERED_TOTAL
+                .with_label_values(&["probe.heartbeat", &name])
+                .inc();
+            triggered = 1;
+            to_store = HAS_INCIDENT;
+        }
+
+        store.insert(self.slug().as_bytes(), to_store)?;
+
+        TRIGGERED
+            .with_label_values(&["probe.heartbeat", &name])
+            .set(triggered as f64);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::probes::testing;
+
+    // Record a signal as received the given number of seconds ago
+    fn received(store: &Db, signal: &str, seconds_ago: i64) {
+        let at = Utc::now().timestamp_millis() - seconds_ago * 1000;
+        store
+            .insert(key("nightly", signal).as_bytes(), &at.to_be_bytes())
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_deadline_and_fail() {
+        let probe = Heartbeat {
+            name: Some("backup".to_owned()),
+            token: "nightly".to_owned(),
+            period: 3600,
+            grace: Some(600),
+            ..Default::default()
+        };
+        let (store, alerts, sent) = testing::setup();
+        assert!(record(&store, "nightly", "since").is_err());
+
+        // the period starts with the first run when no ping was received yet
+        probe.observe(&store, &alerts).await.unwrap();
+        assert!(read_time(&store, &key("nightly", "since"))
+            .unwrap()
+            .is_some());
+        assert!(testing::drain(&sent).is_empty());
+
+        // late, but within the grace period
+        received(&store, "ping", 3900);
+        probe.observe(&store, &alerts).await.unwrap();
+        assert!(testing::drain(&sent).is_empty());
+
+        received(&store, "ping", 4300);
+        received(&store, "start", 100);
+        probe.observe(&store, &alerts).await.unwrap();
+        let notified = testing::drain(&sent);
+        assert_eq!(notified.len(), 1);
+        assert!(notified[0]
+            .starts_with("heartbeat.backup missed its heartbeat: no ping received since"));
+        assert!(notified[0].contains(", last started at"));
+        probe.observe(&store, &alerts).await.unwrap();
+        assert!(testing::drain(&sent).is_empty());
+
+        record(&store, "nightly", "ping").unwrap();
+        probe.observe(&store, &alerts).await.unwrap();
+        assert_eq!(
+            store.get(probe.slug().as_bytes()).unwrap(),
+            Some(IVec::from(NO_INCIDENT))
+        );
+
+        // a fail after the last ping fires right away, and the next ping clears it
+        received(&store, "ping", 10);
+        record(&store, "nightly", "fail").unwrap();
+        probe.observe(&store, &alerts).await.unwrap();
+        let notified = testing::drain(&sent);
+        assert_eq!(notified.len(), 1);
+        assert!(notified[0].starts_with("heartbeat.backup reported failure"));
+        record(&store, "nightly", "ping").unwrap();
+        probe.observe(&store, &alerts).await.unwrap();
+        assert!(testing::drain(&sent).is_empty());
+        assert_eq!(
+            store.get(probe.slug().as_bytes()).unwrap(),
+            Some(IVec::from(NO_INCIDENT))
+        );
+    }
+}
@@ -15,6 +15,7 @@ use std::collections::HashMap;
 pub struct Http {
     name: Option<String>,
     schedule: Option<String>,
+    depends_on: Option<Vec<String>>,
     url: String,
     method: String,
     headers: Option<HashMap<String, String>>,
@@ -55,6 +56,14 @@ impl Probe for Http {
         self.schedule.to_owned()
     }
 
+    fn local_name(&self) -> Option<String> {
+        self.name.to_owned()
+    }
+
+    fn depends_on(&self) -> Option<Vec<String>> {
+        self.depends_on.to_owned()
+    }
+
     fn slug(&self) -> String {
         slugify(format!(
             "http-{}-{}-{}",
@@ -80,6 +89,8 @@ impl Probe for Http {
         let stored = store.get(self.slug().as_bytes())?;
         let mut to_store = NO_INCIDENT;
         let mut triggered = 0;
+        // unlike atom and rss, no conditional request or cache freshness here:
+        // the status code is what's being checked, so every run hits the server
         let client = reqwest::Client::new();
         let mut req = match &self.method as &str {
             "get" => client.get(&self.url),
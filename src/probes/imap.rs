@@ -0,0 +1,424 @@
+use crate::{
+    alerts::Alert,
+    probes::{mail::Connection, password, Notification, Probe, HAS_INCIDENT, NO_INCIDENT},
+};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use fancy_regex::Regex;
+use lazy_static::lazy_static;
+use prometheus::{register_counter_vec, register_gauge_vec, CounterVec, GaugeVec};
+use serde_derive::Deserialize;
+use sled::{Db, IVec};
+use slug::slugify;
+use std::collections::HashMap;
+use tokio::time::{timeout, Duration, Instant};
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Imap {
+    name: Option<String>,
+    schedule: Option<String>,
+    depends_on: Option<Vec<String>>,
+    host: String,
+    // default 993 with tls, otherwise 143
+    port: Option<u16>,
+    // implicit TLS
+    tls: Option<bool>,
+    starttls: Option<bool>,
+    banner_regex: Option<String>,
+    // log in when username is set
+    username: Option<String>,
+    password: Option<String>,
+    password_file: Option<String>,
+    // seconds to wait for the whole check, default 10
+    timeout: Option<u64>,
+}
+
+lazy_static! {
+    static ref RUNS_TOTAL: CounterVec = register_counter_vec!(
+        "probe_imap_runs_total",
+        "run counter for IMAP probe plugin",
+        &["plugin", "host"]
+    )
+    .unwrap();
+    static ref TRIGGERED_TOTAL: CounterVec = register_counter_vec!(
+        "probe_imap_triggered_total",
+        "triggered counter for IMAP probe plugin",
+        &["plugin", "host"]
+    )
+    .unwrap();
+    static ref TRIGGERED: GaugeVec = register_gauge_vec!(
+        "probe_imap_triggered",
+        "IMAP probe plugin triggered",
+        &["plugin", "host"]
+    )
+    .unwrap();
+    static ref RESPONSE_SECONDS: GaugeVec = register_gauge_vec!(
+        "probe_imap_response_seconds",
+        "time taken by the IMAP check",
+        &["plugin", "host"]
+    )
+    .unwrap();
+}
+
+// Minimal IMAP client, enough for probing and the smtp round trip
+pub struct Session {
+    conn: Connection,
+    tag: u32,
+    pub greeting: String,
+}
+
+impl Session {
+    pub async fn open(host: &str, port: u16, tls: bool, starttls: bool) -> Result<Self> {
+        let mut conn = Connection::open(host, port, tls).await?;
+        let greeting = conn.read_line().await?;
+        if !greeting.starts_with("* OK") && !greeting.starts_with("* PREAUTH") {
+            anyhow::bail!("unexpected greeting: {}", greeting);
+        }
+        let mut session = Self {
+            conn,
+            tag: 0,
+            greeting,
+        };
+        if starttls {
+            session.command("STARTTLS").await?;
+            session.conn = session.conn.upgrade().await?;
+        }
+        Ok(session)
+    }
+
+    // Run a tagged command, returning untagged responses when it completes with OK
+    pub async fn command(&mut self, command: &str) -> Result<Vec<String>> {
+        self.tag += 1;
+        let tag = format!("a{}", self.tag);
+        self.conn.send(&format!("{} {}", tag, command)).await?;
+        let mut untagged = vec![];
+        loop {
+            let line = self.conn.read_line().await?;
+            match line.strip_prefix(&format!("{} ", tag)) {
+                Some(status) if status.starts_with("OK") => return Ok(untagged),
+                Some(status) => {
+                    // don't leak credentials into error messages
+                    let verb = command.split_whitespace().next().unwrap_or_default();
+                    anyhow::bail!("{} failed: {}", verb, status)
+                }
+                None => untagged.push(line),
+            }
+        }
+    }
+
+    pub async fn login(&mut self, username: &str, password: &str) -> Result<()> {
+        self.command(&format!("LOGIN {} {}", quote(username), quote(password)))
+            .await?;
+        Ok(())
+    }
+
+    // Message sequence numbers with a subject containing the given text
+    pub async fn search_subject(&mut self, subject: &str) -> Result<Vec<String>> {
+        let untagged = self
+            .command(&format!("SEARCH SUBJECT {}", quote(subject)))
+            .await?;
+        Ok(untagged
+            .iter()
+            .filter_map(|line| line.strip_prefix("* SEARCH"))
+            .flat_map(|ids| ids.split_whitespace().map(String::from))
+            .collect())
+    }
+
+    pub async fn delete(&mut self, ids: &[String]) -> Result<()> {
+        if ids.is_empty() {
+            return Ok(());
+        }
+        self.command(&format!("STORE {} +FLAGS (\\Deleted)", ids.join(",")))
+            .await?;
+        self.command("EXPUNGE").await?;
+        Ok(())
+    }
+
+    pub async fn logout(mut self) -> Result<()> {
+        self.command("LOGOUT").await?;
+        Ok(())
+    }
+}
+
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+impl Imap {
+    fn port(&self) -> u16 {
+        self.port.unwrap_or(if self.tls.unwrap_or_default() {
+            993
+        } else {
+            143
+        })
+    }
+
+    async fn check(&self) -> Result<()> {
+        let mut session = Session::open(
+            &self.host,
+            self.port(),
+            self.tls.unwrap_or_default(),
+            self.starttls.unwrap_or_default(),
+        )
+        .await?;
+        if let Some(banner_regex) = self.banner_regex.as_ref() {
+            let re = Regex::new(banner_regex)
+                .with_context(|| format!("failed parsing banner_regex {}", banner_regex))?;
+            if !re.is_match(&session.greeting)? {
+                anyhow::bail!(
+                    "greeting {} does not match {}",
+                    session.greeting,
+                    banner_regex
+                );
+            }
+        }
+        if let Some(username) = self.username.as_ref() {
+            let password = password(&self.password, &self.password_file)?;
+            session.login(username, &password).await?;
+        }
+        session.logout().await
+    }
+}
+
+#[async_trait]
+impl Probe for Imap {
+    fn new() -> Self {
+        Self {
+            ..Default::default()
+        }
+    }
+
+    fn local_schedule(&self) -> Option<String> {
+        self.schedule.to_owned()
+    }
+
+    fn local_name(&self) -> Option<String> {
+        self.name.to_owned()
+    }
+
+    fn depends_on(&self) -> Option<Vec<String>> {
+        self.depends_on.to_owned()
+    }
+
+    fn slug(&self) -> String {
+        slugify(format!(
+            "imap-{}-{}-{}",
+            self.host,
+            self.port(),
+            self.username.to_owned().unwrap_or_default()
+        ))
+    }
+
+    async fn observe(
+        &self,
+        store: &Db,
+        alerts: &HashMap<String, Vec<Box<dyn Alert>>>,
+    ) -> Result<()> {
+        let host = format!("{}:{}", self.host, self.port());
+        log::info!("checking imap {}", host);
+        RUNS_TOTAL.with_label_values(&["probe.imap", &host]).inc();
+
+        let stored = store.get(self.slug().as_bytes())?;
+        let mut to_store = NO_INCIDENT;
+        let mut triggered = 0;
+        let seconds = self.timeout.unwrap_or(10);
+        let started = Instant::now();
+        let result = match timeout(Duration::from_secs(seconds), self.check()).await {
+            Ok(result) => result,
+            Err(_) => Err(anyhow::anyhow!("timed out after {} seconds", seconds)),
+        };
+        RESPONSE_SECONDS
+            .with_label_values(&["probe.imap", &host])
+            .set(started.elapsed().as_secs_f64());
+
+        if let Err(err) = result {
+            let message = format!("{:#}", err);
+            log::info!("_TRIGGERED_: imap {}: {}", host, message);
+            if stored.is_none() || stored == Some(IVec::from(NO_INCIDENT)) {
+                log::warn!("_NOTIFY_: imap {}: {}", host, message);
+                self.notify(
+                    alerts,
+                    Notification {
+                        from: "imap".to_owned(),
+                        name: self.name("imap", self.name.to_owned()),
+                        check: format!("imap {}", host),
+                        title: format!("imap {} check failed", host),
+                        message,
+                        message_html: None,
+                        message_entries: None,
+                    },
+                )
+                .await?;
+            }
+            TRIGGERED_TOTAL
+                .with_label_values(&["probe.imap", &host])
+                .inc();
+            triggered = 1;
+            to_store = HAS_INCIDENT;
+        }
+
+        store.insert(self.slug().as_bytes(), to_store)?;
+
+        TRIGGERED
+            .with_label_values(&["probe.imap", &host])
+            .set(triggered as f64);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+        net::{TcpListener, TcpStream},
+    };
+
+    pub type Mailbox = Arc<Mutex<Vec<String>>>;
+
+    // Stand-in for an IMAP server over a mailbox of message subjects, accepts the login
+    // otto with password "secret" and knows just enough to search and delete messages
+    async fn session(stream: TcpStream, mailbox: Mailbox) -> Option<()> {
+        let (read, mut write) = stream.into_split();
+        let mut lines = BufReader::new(read).lines();
+        write
+            .write_all(b"* OK [CAPABILITY IMAP4rev1] stand-in ready\r\n")
+            .await
+            .ok()?;
+        let mut deleted: Vec<usize> = vec![];
+        while let Some(line) = lines.next_line().await.ok()? {
+            let mut parts = line.splitn(3, ' ');
+            let (tag, verb, args) = (parts.next()?, parts.next()?, parts.next().unwrap_or(""));
+            let untagged = match verb {
+                "LOGIN" if args == "\"otto\" \"secret\"" => vec![],
+                "LOGIN" => {
+                    let failed =
+                        format!("{} NO [AUTHENTICATIONFAILED] Invalid credentials\r\n", tag);
+                    write.write_all(failed.as_bytes()).await.ok()?;
+                    continue;
+                }
+                "SELECT" => vec![
+                    format!("* {} EXISTS", mailbox.lock().unwrap().len()),
+                    "* FLAGS (\\Deleted \\Seen)".to_owned(),
+                ],
+                "SEARCH" => {
+                    let subject = args.strip_prefix("SUBJECT ")?.trim_matches('"');
+                    let ids: Vec<String> = mailbox
+                        .lock()
+                        .unwrap()
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, message)| message.contains(subject))
+                        .map(|(i, _)| (i + 1).to_string())
+                        .collect();
+                    vec![format!("* SEARCH {}", ids.join(" ")).trim_end().to_owned()]
+                }
+                "STORE" => {
+                    assert!(args.ends_with(" +FLAGS (\\Deleted)"));
+                    let ids = args.split(' ').next()?.split(',');
+                    deleted.extend(ids.filter_map(|id| id.parse::<usize>().ok()));
+                    vec![]
+                }
+                "EXPUNGE" => {
+                    deleted.sort_unstable();
+                    let mut mailbox = mailbox.lock().unwrap();
+                    deleted
+                        .drain(..)
+                        .rev()
+                        .map(|id| {
+                            mailbox.remove(id - 1);
+                            format!("* {} EXPUNGE", id)
+                        })
+                        .collect()
+                }
+                "LOGOUT" => vec!["* BYE logging out".to_owned()],
+                _ => {
+                    let bad = format!("{} BAD unknown command\r\n", tag);
+                    write.write_all(bad.as_bytes()).await.ok()?;
+                    continue;
+                }
+            };
+            let mut reply: String = untagged
+                .iter()
+                .map(|line| format!("{}\r\n", line))
+                .collect();
+            reply.push_str(&format!("{} OK {} completed\r\n", tag, verb));
+            write.write_all(reply.as_bytes()).await.ok()?;
+            if verb == "LOGOUT" {
+                return Some(());
+            }
+        }
+        Some(())
+    }
+
+    // Serve the mailbox on a local port
+    pub async fn stand_in(mailbox: Mailbox) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                tokio::spawn(session(stream, mailbox.clone()));
+            }
+        });
+        port
+    }
+
+    #[tokio::test]
+    async fn test_check_against_stand_in() {
+        let port = stand_in(Mailbox::default()).await;
+        let probe = Imap {
+            host: "127.0.0.1".to_owned(),
+            port: Some(port),
+            banner_regex: Some("stand-in".to_owned()),
+            username: Some("otto".to_owned()),
+            password: Some("secret".to_owned()),
+            ..Default::default()
+        };
+        probe.check().await.unwrap();
+
+        let wrong_password = Imap {
+            password: Some("wrong".to_owned()),
+            ..probe.clone()
+        };
+        assert_eq!(
+            wrong_password.check().await.unwrap_err().to_string(),
+            "LOGIN failed: NO [AUTHENTICATIONFAILED] Invalid credentials"
+        );
+
+        let other_banner = Imap {
+            banner_regex: Some("^\\* OK Dovecot".to_owned()),
+            ..probe
+        };
+        assert_eq!(
+            other_banner.check().await.unwrap_err().to_string(),
+            "greeting * OK [CAPABILITY IMAP4rev1] stand-in ready does not match ^\\* OK Dovecot"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_and_delete() {
+        let mailbox: Mailbox = Arc::new(Mutex::new(vec![
+            "otto round trip 1".to_owned(),
+            "newsletter".to_owned(),
+            "otto round trip 2".to_owned(),
+        ]));
+        let port = stand_in(mailbox.clone()).await;
+        let mut session = Session::open("127.0.0.1", port, false, false)
+            .await
+            .unwrap();
+        session.login("otto", "secret").await.unwrap();
+        // untagged responses are returned once the tagged OK arrives
+        assert_eq!(
+            session.command("SELECT \"INBOX\"").await.unwrap(),
+            vec!["* 3 EXISTS", "* FLAGS (\\Deleted \\Seen)"]
+        );
+        assert!(session.search_subject("missing").await.unwrap().is_empty());
+        let ids = session.search_subject("otto round trip").await.unwrap();
+        assert_eq!(ids, vec!["1", "3"]);
+        session.delete(&ids).await.unwrap();
+        session.logout().await.unwrap();
+        assert_eq!(*mailbox.lock().unwrap(), vec!["newsletter"]);
+    }
+}
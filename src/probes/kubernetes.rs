@@ -0,0 +1,776 @@
+use crate::{
+    alerts::Alert,
+    probes::{MessageEntry, Notification, Probe, HAS_INCIDENT, NO_INCIDENT},
+};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use hyper::{
+    body,
+    client::HttpConnector,
+    header::{ACCEPT, AUTHORIZATION},
+    Body, Client, Request,
+};
+use hyper_tls::HttpsConnector;
+use lazy_static::lazy_static;
+use openssl::pkey::PKey;
+use prometheus::{register_counter_vec, register_gauge_vec, CounterVec, GaugeVec};
+use serde::de::DeserializeOwned;
+use serde_derive::Deserialize;
+use sled::{Db, IVec};
+use slug::slugify;
+use std::{
+    collections::{BTreeMap, HashMap},
+    env, fs,
+    path::{Path, PathBuf},
+};
+use tokio::time::{timeout, Duration};
+use url::form_urlencoded;
+
+// Service account mounted into pods, used when running inside the cluster
+const SERVICE_ACCOUNT: &str = "/var/run/secrets/kubernetes.io/serviceaccount";
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Kubernetes {
+    name: Option<String>,
+    schedule: Option<String>,
+    depends_on: Option<Vec<String>>,
+    // path to kubeconfig, default in-cluster service account when running in a pod,
+    // otherwise $KUBECONFIG or ~/.kube/config
+    kubeconfig: Option<String>,
+    // default current-context of the kubeconfig
+    context: Option<String>,
+    // default all namespaces
+    namespace: Option<String>,
+    // label selector, eg. "app=web,tier!=cache"
+    selector: Option<String>,
+    // any of deployments, statefulsets, daemonsets and jobs, default all of them
+    kinds: Option<Vec<String>>,
+    // seconds to wait for the API server, default 10
+    timeout: Option<u64>,
+}
+
+lazy_static! {
+    static ref RUNS_TOTAL: CounterVec = register_counter_vec!(
+        "probe_kubernetes_runs_total",
+        "run counter for kubernetes probe plugin",
+        &["plugin", "namespace", "selector"]
+    )
+    .unwrap();
+    static ref TRIGGERED_TOTAL: CounterVec = register_counter_vec!(
+        "probe_kubernetes_triggered_total",
+        "triggered counter for kubernetes probe plugin",
+        &["plugin", "namespace", "selector"]
+    )
+    .unwrap();
+    static ref TRIGGERED: GaugeVec = register_gauge_vec!(
+        "probe_kubernetes_triggered",
+        "Kubernetes probe plugin triggered",
+        &["plugin", "namespace", "selector"]
+    )
+    .unwrap();
+    static ref WORKLOADS: GaugeVec = register_gauge_vec!(
+        "probe_kubernetes_workloads",
+        "number of workloads matching the selector from kubernetes probe plugin",
+        &["plugin", "namespace", "selector"]
+    )
+    .unwrap();
+    static ref UNHEALTHY_WORKLOADS: GaugeVec = register_gauge_vec!(
+        "probe_kubernetes_unhealthy_workloads",
+        "number of matching workloads that are unavailable, crash-looping or failed",
+        &["plugin", "namespace", "selector"]
+    )
+    .unwrap();
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct Kubeconfig {
+    current_context: Option<String>,
+    #[serde(default)]
+    contexts: Vec<NamedContext>,
+    #[serde(default)]
+    clusters: Vec<NamedCluster>,
+    #[serde(default)]
+    users: Vec<NamedUser>,
+}
+
+#[derive(Debug, Deserialize)]
+struct NamedContext {
+    name: String,
+    context: KubeContext,
+}
+
+#[derive(Debug, Deserialize)]
+struct KubeContext {
+    cluster: String,
+    user: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct NamedCluster {
+    name: String,
+    cluster: Cluster,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case", default)]
+struct Cluster {
+    server: String,
+    certificate_authority: Option<String>,
+    certificate_authority_data: Option<String>,
+    insecure_skip_tls_verify: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+struct NamedUser {
+    name: String,
+    user: User,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case", default)]
+struct User {
+    token: Option<String>,
+    token_file: Option<String>,
+    client_certificate: Option<String>,
+    client_certificate_data: Option<String>,
+    client_key: Option<String>,
+    client_key_data: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct List<T> {
+    items: Vec<T>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct Metadata {
+    name: String,
+    namespace: String,
+    labels: HashMap<String, String>,
+    owner_references: Vec<OwnerReference>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OwnerReference {
+    kind: String,
+    name: String,
+}
+
+// Deployments, StatefulSets, DaemonSets and Jobs, with only the fields we look at
+#[derive(Debug, Deserialize)]
+struct Workload {
+    metadata: Metadata,
+    #[serde(default)]
+    spec: WorkloadSpec,
+    #[serde(default)]
+    status: WorkloadStatus,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct WorkloadSpec {
+    replicas: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct WorkloadStatus {
+    available_replicas: u64,
+    ready_replicas: u64,
+    desired_number_scheduled: u64,
+    number_available: u64,
+    conditions: Vec<Condition>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Condition {
+    #[serde(rename = "type")]
+    kind: String,
+    status: String,
+    reason: Option<String>,
+    message: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Pod {
+    metadata: Metadata,
+    #[serde(default)]
+    status: PodStatus,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct PodStatus {
+    container_statuses: Vec<ContainerStatus>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ContainerStatus {
+    name: String,
+    #[serde(default)]
+    restart_count: u64,
+    #[serde(default)]
+    state: ContainerState,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct ContainerState {
+    waiting: Option<Waiting>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Waiting {
+    reason: Option<String>,
+}
+
+// Client for the API server of a single cluster
+struct Api {
+    server: String,
+    token: Option<String>,
+    client: Client<HttpsConnector<HttpConnector>>,
+}
+
+impl Api {
+    fn new(
+        server: &str,
+        token: Option<String>,
+        ca: Option<Vec<u8>>,
+        identity: Option<(Vec<u8>, Vec<u8>)>,
+        insecure: bool,
+    ) -> Result<Self> {
+        let mut tls = native_tls::TlsConnector::builder();
+        if let Some(ca) = ca {
+            tls.add_root_certificate(native_tls::Certificate::from_pem(&ca)?);
+        }
+        if let Some((cert, key)) = identity {
+            // native-tls only takes PKCS #8 keys, kubeconfigs usually have PKCS #1 or SEC1
+            let key = PKey::private_key_from_pem(&key)
+                .context("failed parsing client key")?
+                .private_key_to_pem_pkcs8()?;
+            tls.identity(native_tls::Identity::from_pkcs8(&cert, &key)?);
+        }
+        tls.danger_accept_invalid_certs(insecure);
+        let mut http = HttpConnector::new();
+        http.enforce_http(false);
+        let https =
+            HttpsConnector::from((http, tokio_native_tls::TlsConnector::from(tls.build()?)));
+        Ok(Self {
+            server: server.trim_end_matches('/').to_owned(),
+            token,
+            client: Client::builder().build(https),
+        })
+    }
+
+    fn in_cluster() -> Result<Self> {
+        let host = env::var("KUBERNETES_SERVICE_HOST")?;
+        let port = env::var("KUBERNETES_SERVICE_PORT").unwrap_or_else(|_| "443".to_owned());
+        let server = if host.contains(':') {
+            format!("https://[{}]:{}", host, port)
+        } else {
+            format!("https://{}:{}", host, port)
+        };
+        let token = fs::read_to_string(format!("{}/token", SERVICE_ACCOUNT))
+            .context("failed reading service account token")?;
+        let ca = fs::read(format!("{}/ca.crt", SERVICE_ACCOUNT))
+            .context("failed reading service account ca.crt")?;
+        Self::new(
+            &server,
+            Some(token.trim().to_owned()),
+            Some(ca),
+            None,
+            false,
+        )
+    }
+
+    fn from_kubeconfig(path: &Path, context: Option<&str>) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("failed reading kubeconfig {}", path.display()))?;
+        let config: Kubeconfig = serde_yaml::from_str(&content)
+            .with_context(|| format!("failed parsing kubeconfig {}", path.display()))?;
+        let context = context
+            .map(str::to_owned)
+            .or(config.current_context)
+            .ok_or_else(|| anyhow::anyhow!("no context set in {}", path.display()))?;
+        let context = config
+            .contexts
+            .into_iter()
+            .find(|named| named.name == context)
+            .ok_or_else(|| anyhow::anyhow!("context {} not found", context))?
+            .context;
+        let cluster = config
+            .clusters
+            .into_iter()
+            .find(|named| named.name == context.cluster)
+            .ok_or_else(|| anyhow::anyhow!("cluster {} not found", context.cluster))?
+            .cluster;
+        let user = config
+            .users
+            .into_iter()
+            .find(|named| named.name == context.user)
+            .map(|named| named.user)
+            .unwrap_or_default();
+
+        // file paths in kubeconfig are relative to the kubeconfig itself
+        let dir = path.parent().unwrap_or_else(|| Path::new("."));
+        let read = |data: &Option<String>, file: &Option<String>| -> Result<Option<Vec<u8>>> {
+            match (data, file) {
+                (Some(data), _) => Ok(Some(base64::decode(data.trim())?)),
+                (None, Some(file)) => Ok(Some(
+                    fs::read(dir.join(file)).with_context(|| format!("failed reading {}", file))?,
+                )),
+                (None, None) => Ok(None),
+            }
+        };
+        let token = match (user.token.as_ref(), user.token_file.as_ref()) {
+            (Some(token), _) => Some(token.to_owned()),
+            (None, Some(file)) => Some(
+                fs::read_to_string(dir.join(file))
+                    .with_context(|| format!("failed reading {}", file))?
+                    .trim()
+                    .to_owned(),
+            ),
+            (None, None) => None,
+        };
+        let identity = match (
+            read(&user.client_certificate_data, &user.client_certificate)?,
+            read(&user.client_key_data, &user.client_key)?,
+        ) {
+            (Some(cert), Some(key)) => Some((cert, key)),
+            _ => None,
+        };
+        Self::new(
+            &cluster.server,
+            token,
+            read(
+                &cluster.certificate_authority_data,
+                &cluster.certificate_authority,
+            )?,
+            identity,
+            cluster.insecure_skip_tls_verify.unwrap_or_default(),
+        )
+    }
+
+    async fn get<T: DeserializeOwned>(&self, path: &str) -> Result<T> {
+        let mut request =
+            Request::get(format!("{}{}", self.server, path)).header(ACCEPT, "application/json");
+        if let Some(token) = self.token.as_ref() {
+            request = request.header(AUTHORIZATION, format!("Bearer {}", token));
+        }
+        let response = self
+            .client
+            .request(request.body(Body::empty())?)
+            .await
+            .with_context(|| format!("failed connecting to {}", self.server))?;
+        let status = response.status();
+        let bytes = body::to_bytes(response.into_body()).await?;
+        if !status.is_success() {
+            anyhow::bail!(
+                "GET {} got {}: {}",
+                path,
+                status,
+                String::from_utf8_lossy(&bytes).trim()
+            );
+        }
+        serde_json::from_slice(&bytes).with_context(|| format!("failed parsing GET {}", path))
+    }
+}
+
+impl Kubernetes {
+    fn namespace(&self) -> String {
+        self.namespace
+            .to_owned()
+            .unwrap_or_else(|| "all".to_owned())
+    }
+
+    fn api(&self) -> Result<Api> {
+        if let Some(kubeconfig) = self.kubeconfig.as_ref() {
+            return Api::from_kubeconfig(Path::new(kubeconfig), self.context.as_deref());
+        }
+        if env::var("KUBERNETES_SERVICE_HOST").is_ok() {
+            return Api::in_cluster();
+        }
+        let path = match env::var("KUBECONFIG") {
+            Ok(paths) => PathBuf::from(paths.split(':').next().unwrap_or_default()),
+            Err(_) => PathBuf::from(env::var("HOME")?).join(".kube/config"),
+        };
+        Api::from_kubeconfig(&path, self.context.as_deref())
+    }
+
+    // Path to list resources of a kind, scoped to the namespace and filtered by the selector
+    fn path(&self, group: &str, kind: &str) -> String {
+        let mut path = match self.namespace.as_ref() {
+            Some(namespace) => format!("{}/namespaces/{}/{}", group, namespace, kind),
+            None => format!("{}/{}", group, kind),
+        };
+        if let Some(selector) = self.selector.as_ref() {
+            path.push('?');
+            path.push_str(
+                &form_urlencoded::Serializer::new(String::new())
+                    .append_pair("labelSelector", selector)
+                    .finish(),
+            );
+        }
+        path
+    }
+
+    // Problems keyed by workload, eg. "Deployment default/web", with every matched
+    // workload present even when it's healthy
+    async fn inspect(&self) -> Result<BTreeMap<String, Vec<String>>> {
+        let api = self.api()?;
+        let kinds = self.kinds.to_owned().unwrap_or_else(|| {
+            ["deployments", "statefulsets", "daemonsets", "jobs"]
+                .iter()
+                .map(|kind| kind.to_string())
+                .collect()
+        });
+        let mut workloads: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for kind in kinds.iter() {
+            let (group, title) = match kind.as_str() {
+                "deployments" => ("/apis/apps/v1", "Deployment"),
+                "statefulsets" => ("/apis/apps/v1", "StatefulSet"),
+                "daemonsets" => ("/apis/apps/v1", "DaemonSet"),
+                "jobs" => ("/apis/batch/v1", "Job"),
+                _ => anyhow::bail!("unsupported kind {}", kind),
+            };
+            let list: List<Workload> = api.get(&self.path(group, kind)).await?;
+            for workload in list.items.iter() {
+                let (status, desired) = (&workload.status, workload.spec.replicas.unwrap_or(1));
+                let problem = match title {
+                    "Deployment" if status.available_replicas < desired => Some(format!(
+                        "{} of {} replicas available",
+                        status.available_replicas, desired
+                    )),
+                    "StatefulSet" if status.ready_replicas < desired => Some(format!(
+                        "{} of {} replicas ready",
+                        status.ready_replicas, desired
+                    )),
+                    "DaemonSet" if status.number_available < status.desired_number_scheduled => {
+                        Some(format!(
+                            "{} of {} pods available",
+                            status.number_available, status.desired_number_scheduled
+                        ))
+                    }
+                    "Job" => status
+                        .conditions
+                        .iter()
+                        .find(|condition| condition.kind == "Failed" && condition.status == "True")
+                        .map(|condition| {
+                            format!(
+                                "failed: {} {}",
+                                condition.reason.to_owned().unwrap_or_default(),
+                                condition.message.to_owned().unwrap_or_default()
+                            )
+                            .trim()
+                            .to_owned()
+                        }),
+                    _ => None,
+                };
+                workloads
+                    .entry(format!(
+                        "{} {}/{}",
+                        title, workload.metadata.namespace, workload.metadata.name
+                    ))
+                    .or_default()
+                    .extend(problem);
+            }
+        }
+
+        // crash-looping pods are reported under the workload that owns them
+        let pods: List<Pod> = api.get(&self.path("/api/v1", "pods")).await?;
+        for pod in pods.items.iter() {
+            let owner = match pod.metadata.owner_references.first() {
+                // pods of a deployment are owned by a replica set named after
+                // the deployment and the pod template hash
+                Some(owner) if owner.kind == "ReplicaSet" => {
+                    match pod.metadata.labels.get("pod-template-hash") {
+                        Some(hash) => format!(
+                            "Deployment {}/{}",
+                            pod.metadata.namespace,
+                            owner.name.trim_end_matches(&format!("-{}", hash))
+                        ),
+                        None => format!("ReplicaSet {}/{}", pod.metadata.namespace, owner.name),
+                    }
+                }
+                Some(owner) => format!("{} {}/{}", owner.kind, pod.metadata.namespace, owner.name),
+                None => format!("Pod {}/{}", pod.metadata.namespace, pod.metadata.name),
+            };
+            for container in pod.status.container_statuses.iter() {
+                let waiting = container.state.waiting.as_ref();
+                if waiting.and_then(|waiting| waiting.reason.as_deref()) == Some("CrashLoopBackOff")
+                {
+                    workloads.entry(owner.clone()).or_default().push(format!(
+                        "pod {} container {} is crash-looping after {} restarts",
+                        pod.metadata.name, container.name, container.restart_count
+                    ));
+                }
+            }
+        }
+        Ok(workloads)
+    }
+}
+
+#[async_trait]
+impl Probe for Kubernetes {
+    fn new() -> Self {
+        Self {
+            ..Default::default()
+        }
+    }
+
+    fn local_schedule(&self) -> Option<String> {
+        self.schedule.to_owned()
+    }
+
+    fn local_name(&self) -> Option<String> {
+        self.name.to_owned()
+    }
+
+    fn depends_on(&self) -> Option<Vec<String>> {
+        self.depends_on.to_owned()
+    }
+
+    fn slug(&self) -> String {
+        slugify(format!(
+            "kubernetes-{}-{}-{}-{}",
+            self.kubeconfig.to_owned().unwrap_or_default(),
+            self.context.to_owned().unwrap_or_default(),
+            self.namespace(),
+            self.selector.to_owned().unwrap_or_default()
+        ))
+    }
+
+    async fn observe(
+        &self,
+        store: &Db,
+        alerts: &HashMap<String, Vec<Box<dyn Alert>>>,
+    ) -> Result<()> {
+        let namespace = self.namespace();
+        let selector = self.selector.to_owned().unwrap_or_default();
+        log::info!(
+            "checking kubernetes workloads in {} namespace(s) matching {:?}",
+            namespace,
+            selector
+        );
+        RUNS_TOTAL
+            .with_label_values(&["probe.kubernetes", &namespace, &selector])
+            .inc();
+
+        let stored = store.get(self.slug().as_bytes())?;
+        let mut to_store = NO_INCIDENT;
+        let mut triggered = 0;
+        let seconds = self.timeout.unwrap_or(10);
+        let result = match timeout(Duration::from_secs(seconds), self.inspect()).await {
+            Ok(result) => result,
+            Err(_) => Err(anyhow::anyhow!(
+                "no response from API server within {} seconds",
+                seconds
+            )),
+        };
+
+        let mut problems: Vec<String> = vec![];
+        let mut message_entries: Vec<(i8, MessageEntry)> = vec![];
+        match result {
+            Ok(workloads) => {
+                WORKLOADS
+                    .with_label_values(&["probe.kubernetes", &namespace, &selector])
+                    .set(workloads.len() as f64);
+                for (workload, failed) in workloads.iter() {
+                    if failed.is_empty() {
+                        continue;
+                    }
+                    problems.push(format!("{} {}", workload, failed.join(", ")));
+                    if message_entries.len() < i8::MAX as usize {
+                        message_entries.push((
+                            message_entries.len() as i8, // index of this message entry
+                            MessageEntry {
+                                title: workload.to_owned(),
+                                description: failed.join("\n"),
+                            },
+                        ));
+                    }
+                }
+                UNHEALTHY_WORKLOADS
+                    .with_label_values(&["probe.kubernetes", &namespace, &selector])
+                    .set(problems.len() as f64);
+            }
+            Err(err) => problems.push(format!("{:#}", err)),
+        }
+
+        if !problems.is_empty() {
+            log::info!(
+                "_TRIGGERED_: kubernetes workloads in {} namespace(s) matching {:?}: {}",
+                namespace,
+                selector,
+                problems.join(", ")
+            );
+            if stored.is_none() || stored == Some(IVec::from(NO_INCIDENT)) {
+                log::warn!(
+                    "_NOTIFY_: kubernetes workloads in {} namespace(s) matching {:?}: {}",
+                    namespace,
+                    selector,
+                    problems.join(", ")
+                );
+                self.notify(
+                    alerts,
+                    Notification {
+                        from: "kubernetes".to_owned(),
+                        name: self.name("kubernetes", self.name.to_owned()),
+                        check: format!(
+                            "kubernetes workloads in {} namespace(s) matching {:?}",
+                            namespace, selector
+                        ),
+                        title: format!("{} unhealthy kubernetes workload(s)", problems.len()),
+                        message: problems.join("\n"),
+                        message_html: None,
+                        message_entries: if message_entries.is_empty() {
+                            None
+                        } else {
+                            Some(message_entries)
+                        },
+                    },
+                )
+                .await?;
+            }
+            TRIGGERED_TOTAL
+                .with_label_values(&["probe.kubernetes", &namespace, &selector])
+                .inc();
+            triggered = 1;
+            to_store = HAS_INCIDENT;
+        }
+
+        store.insert(self.slug().as_bytes(), to_store)?;
+
+        TRIGGERED
+            .with_label_values(&["probe.kubernetes", &namespace, &selector])
+            .set(triggered as f64);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+    use std::net::SocketAddr;
+    use warp::Filter;
+
+    #[tokio::test]
+    async fn test_inspect_against_mock_api() {
+        let auth = warp::header::exact("authorization", "Bearer secret");
+        let selector = warp::query::<HashMap<String, String>>().map(|query: HashMap<_, _>| {
+            assert_eq!(query.get("labelSelector"), Some(&"app=web".to_owned()));
+        });
+        let deployments =
+            warp::path!("apis" / "apps" / "v1" / "namespaces" / "prod" / "deployments")
+                .and(selector)
+                .map(|_| {
+                    warp::reply::json(&json!({"items": [
+                        {
+                            "metadata": {"name": "web", "namespace": "prod"},
+                            "spec": {"replicas": 3},
+                            "status": {"availableReplicas": 1},
+                        },
+                        {
+                            "metadata": {"name": "api", "namespace": "prod"},
+                            "spec": {"replicas": 2},
+                            "status": {"availableReplicas": 2},
+                        },
+                    ]}))
+                });
+        let jobs =
+            warp::path!("apis" / "batch" / "v1" / "namespaces" / "prod" / "jobs").map(|| {
+                warp::reply::json(&json!({"items": [{
+                    "metadata": {"name": "migrate", "namespace": "prod"},
+                    "status": {"conditions": [{
+                        "type": "Failed",
+                        "status": "True",
+                        "reason": "BackoffLimitExceeded",
+                        "message": "Job has reached the specified backoff limit",
+                    }]},
+                }]}))
+            });
+        let pods = warp::path!("api" / "v1" / "namespaces" / "prod" / "pods").map(|| {
+            warp::reply::json(&json!({"items": [{
+                "metadata": {
+                    "name": "api-6f7c9-x2x4q",
+                    "namespace": "prod",
+                    "labels": {"pod-template-hash": "6f7c9"},
+                    "ownerReferences": [{"kind": "ReplicaSet", "name": "api-6f7c9"}],
+                },
+                "status": {"containerStatuses": [{
+                    "name": "api",
+                    "restartCount": 7,
+                    "state": {"waiting": {"reason": "CrashLoopBackOff"}},
+                }]},
+            }]}))
+        });
+        let (addr, server) = warp::serve(auth.and(deployments.or(jobs).or(pods)))
+            .bind_ephemeral("127.0.0.1:0".parse::<SocketAddr>().unwrap());
+        tokio::spawn(server);
+
+        let dir = env::temp_dir().join(format!("otto-kubernetes-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("token"), "secret\n").unwrap();
+        let kubeconfig = dir.join("config");
+        fs::write(
+            &kubeconfig,
+            format!(
+                r#"
+apiVersion: v1
+kind: Config
+current-context: mock
+contexts:
+- name: mock
+  context:
+    cluster: mock
+    user: otto
+clusters:
+- name: mock
+  cluster:
+    server: http://{}
+users:
+- name: otto
+  user:
+    token-file: token
+"#,
+                addr
+            ),
+        )
+        .unwrap();
+
+        let probe = Kubernetes {
+            kubeconfig: Some(kubeconfig.to_string_lossy().to_string()),
+            namespace: Some("prod".to_owned()),
+            selector: Some("app=web".to_owned()),
+            kinds: Some(vec!["deployments".to_owned(), "jobs".to_owned()]),
+            ..Default::default()
+        };
+        let workloads = probe.inspect().await.unwrap();
+        assert_eq!(
+            workloads.get("Deployment prod/web").unwrap(),
+            &vec!["1 of 3 replicas available".to_owned()]
+        );
+        assert_eq!(
+            workloads.get("Deployment prod/api").unwrap(),
+            &vec!["pod api-6f7c9-x2x4q container api is crash-looping after 7 restarts".to_owned()]
+        );
+        assert_eq!(
+            workloads.get("Job prod/migrate").unwrap(),
+            &vec![
+                "failed: BackoffLimitExceeded Job has reached the specified backoff limit"
+                    .to_owned()
+            ]
+        );
+
+        let unknown = Kubernetes {
+            context: Some("missing".to_owned()),
+            ..probe
+        };
+        assert!(unknown.inspect().await.is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
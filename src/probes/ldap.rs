@@ -0,0 +1,429 @@
+use crate::{
+    alerts::Alert,
+    probes::{password, Notification, Probe, HAS_INCIDENT, NO_INCIDENT},
+};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use lazy_static::lazy_static;
+use ldap3::{LdapConnAsync, LdapConnSettings, Scope, SearchOptions, SearchResult};
+use native_tls::{Certificate, TlsConnector};
+use prometheus::{register_counter_vec, register_gauge_vec, CounterVec, GaugeVec};
+use serde_derive::Deserialize;
+use sled::{Db, IVec};
+use slug::slugify;
+use std::{collections::HashMap, fs};
+use tokio::time::{timeout, Duration, Instant};
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Ldap {
+    name: Option<String>,
+    schedule: Option<String>,
+    depends_on: Option<Vec<String>>,
+    // ldap://host:389 or ldaps://host:636
+    url: String,
+    // upgrade an ldap:// connection with StartTLS
+    starttls: Option<bool>,
+    // PEM encoded CA to verify the server with, instead of system roots
+    tls_ca: Option<String>,
+    // bind anonymously when not set, a password is required when set
+    bind_dn: Option<String>,
+    password: Option<String>,
+    password_file: Option<String>,
+    search: Option<Search>,
+    // seconds to wait for binding and searching, default 10
+    timeout: Option<u64>,
+}
+
+// Search to run after binding, which should find at least min_entries entries
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Search {
+    base: String,
+    // default "(objectClass=*)"
+    filter: Option<String>,
+    // base, one or sub, default sub
+    scope: Option<String>,
+    // default 1
+    min_entries: Option<usize>,
+}
+
+lazy_static! {
+    static ref RUNS_TOTAL: CounterVec = register_counter_vec!(
+        "probe_ldap_runs_total",
+        "run counter for LDAP probe plugin",
+        &["plugin", "url"]
+    )
+    .unwrap();
+    static ref TRIGGERED_TOTAL: CounterVec = register_counter_vec!(
+        "probe_ldap_triggered_total",
+        "triggered counter for LDAP probe plugin",
+        &["plugin", "url"]
+    )
+    .unwrap();
+    static ref TRIGGERED: GaugeVec = register_gauge_vec!(
+        "probe_ldap_triggered",
+        "LDAP probe plugin triggered",
+        &["plugin", "url"]
+    )
+    .unwrap();
+    static ref BIND_SECONDS: GaugeVec = register_gauge_vec!(
+        "probe_ldap_bind_seconds",
+        "time to connect and bind to the LDAP server",
+        &["plugin", "url"]
+    )
+    .unwrap();
+    static ref SEARCH_SECONDS: GaugeVec = register_gauge_vec!(
+        "probe_ldap_search_seconds",
+        "time to run the search on the LDAP server",
+        &["plugin", "url"]
+    )
+    .unwrap();
+}
+
+impl Ldap {
+    fn settings(&self) -> Result<LdapConnSettings> {
+        let mut settings = LdapConnSettings::new().set_starttls(self.starttls.unwrap_or_default());
+        if let Some(tls_ca) = self.tls_ca.as_ref() {
+            let ca =
+                fs::read(tls_ca).with_context(|| format!("failed reading tls_ca {}", tls_ca))?;
+            settings = settings.set_connector(
+                TlsConnector::builder()
+                    .add_root_certificate(Certificate::from_pem(&ca)?)
+                    .build()?,
+            );
+        }
+        Ok(settings)
+    }
+
+    async fn check(&self) -> Result<()> {
+        let bind_dn = self.bind_dn.to_owned().unwrap_or_default();
+        let password = password(&self.password, &self.password_file)?;
+        // a DN with an empty password is an unauthenticated bind, which servers may accept
+        // without checking any credentials
+        if !bind_dn.is_empty() && password.is_empty() {
+            anyhow::bail!(
+                "bind_dn {} needs a password or password_file, an empty password binds unauthenticated",
+                bind_dn
+            );
+        }
+        let started = Instant::now();
+        let (conn, mut ldap) = LdapConnAsync::with_settings(self.settings()?, &self.url)
+            .await
+            .with_context(|| format!("failed connecting to {}", self.url))?;
+        ldap3::drive!(conn);
+        let result = ldap.simple_bind(&bind_dn, &password).await?;
+        if result.rc != 0 {
+            match bind_dn.as_str() {
+                "" => anyhow::bail!("anonymous bind failed: {}", result),
+                bind_dn => anyhow::bail!("bind as {} failed: {}", bind_dn, result),
+            }
+        }
+        BIND_SECONDS
+            .with_label_values(&["probe.ldap", &self.url])
+            .set(started.elapsed().as_secs_f64());
+
+        if let Some(search) = self.search.as_ref() {
+            let scope = match search.scope.as_deref().unwrap_or("sub") {
+                "base" => Scope::Base,
+                "one" => Scope::OneLevel,
+                "sub" => Scope::Subtree,
+                scope => anyhow::bail!("invalid search scope {}", scope),
+            };
+            let filter = search.filter.as_deref().unwrap_or("(objectClass=*)");
+            let min_entries = search.min_entries.unwrap_or(1);
+            let started = Instant::now();
+            // "1.1" asks for no attributes, and no more than min_entries entries are needed
+            // to tell, a size limit of 0 would be unlimited
+            let SearchResult(entries, result) = ldap
+                .with_search_options(SearchOptions::new().sizelimit(min_entries.max(1) as i32))
+                .search(&search.base, scope, filter, vec!["1.1"])
+                .await?;
+            // 4 is sizeLimitExceeded, the server stopped at the limit
+            if result.rc != 0 && result.rc != 4 {
+                anyhow::bail!("search {} under {} failed: {}", filter, search.base, result);
+            }
+            SEARCH_SECONDS
+                .with_label_values(&["probe.ldap", &self.url])
+                .set(started.elapsed().as_secs_f64());
+            if entries.len() < min_entries {
+                anyhow::bail!(
+                    "search {} under {} found {} entries, fewer than {}",
+                    filter,
+                    search.base,
+                    entries.len(),
+                    min_entries
+                );
+            }
+        }
+        ldap.unbind().await.ok();
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Probe for Ldap {
+    fn new() -> Self {
+        Self {
+            ..Default::default()
+        }
+    }
+
+    fn local_schedule(&self) -> Option<String> {
+        self.schedule.to_owned()
+    }
+
+    fn local_name(&self) -> Option<String> {
+        self.name.to_owned()
+    }
+
+    fn depends_on(&self) -> Option<Vec<String>> {
+        self.depends_on.to_owned()
+    }
+
+    fn slug(&self) -> String {
+        slugify(format!(
+            "ldap-{}-{}",
+            self.url,
+            self.bind_dn.to_owned().unwrap_or_default()
+        ))
+    }
+
+    async fn observe(
+        &self,
+        store: &Db,
+        alerts: &HashMap<String, Vec<Box<dyn Alert>>>,
+    ) -> Result<()> {
+        log::info!("checking ldap {}", self.url);
+        RUNS_TOTAL
+            .with_label_values(&["probe.ldap", &self.url])
+            .inc();
+
+        let stored = store.get(self.slug().as_bytes())?;
+        let mut to_store = NO_INCIDENT;
+        let mut triggered = 0;
+        let seconds = self.timeout.unwrap_or(10);
+        let result = match timeout(Duration::from_secs(seconds), self.check()).await {
+            Ok(result) => result,
+            Err(_) => Err(anyhow::anyhow!("timed out after {} seconds", seconds)),
+        };
+
+        if let Err(err) = result {
+            let message = format!("{:#}", err);
+            log::info!("_TRIGGERED_: ldap {}: {}", self.url, message);
+            if stored.is_none() || stored == Some(IVec::from(NO_INCIDENT)) {
+                log::warn!("_NOTIFY_: ldap {}: {}", self.url, message);
+                self.notify(
+                    alerts,
+                    Notification {
+                        from: "ldap".to_owned(),
+                        name: self.name("ldap", self.name.to_owned()),
+                        check: format!("ldap {}", self.url),
+                        title: format!("ldap {} check failed", self.url),
+                        message,
+                        message_html: None,
+                        message_entries: None,
+                    },
+                )
+                .await?;
+            }
+            TRIGGERED_TOTAL
+                .with_label_values(&["probe.ldap", &self.url])
+                .inc();
+            triggered = 1;
+            to_store = HAS_INCIDENT;
+        }
+
+        store.insert(self.slug().as_bytes(), to_store)?;
+
+        TRIGGERED
+            .with_label_values(&["probe.ldap", &self.url])
+            .set(triggered as f64);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+    };
+
+    fn tlv(tag: u8, value: &[u8]) -> Vec<u8> {
+        let mut out = vec![tag];
+        match value.len() {
+            len if len < 128 => out.push(len as u8),
+            len => out.extend_from_slice(&[0x82, (len >> 8) as u8, len as u8]),
+        }
+        out.extend_from_slice(value);
+        out
+    }
+
+    // Tag, value and the rest after it, of the BER element at the start of input
+    fn element(input: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+        let (tag, first) = (*input.first()?, *input.get(1)? as usize);
+        let (len, start) = match first {
+            len if len < 128 => (len, 2),
+            long => {
+                let octets = long & 0x7f;
+                let len = input
+                    .get(2..2 + octets)?
+                    .iter()
+                    .fold(0, |len, byte| len << 8 | *byte as usize);
+                (len, 2 + octets)
+            }
+        };
+        Some((tag, input.get(start..start + len)?, &input[start + len..]))
+    }
+
+    fn integer(value: &[u8]) -> i64 {
+        value.iter().fold(0, |n, byte| n << 8 | *byte as i64)
+    }
+
+    fn response(id: &[u8], op: u8, rc: u8) -> Vec<u8> {
+        let result = [tlv(0x0a, &[rc]), tlv(0x04, b""), tlv(0x04, b"")].concat();
+        tlv(0x30, &[tlv(0x02, id), tlv(op, &result)].concat())
+    }
+
+    async fn read_message(stream: &mut TcpStream) -> Option<Vec<u8>> {
+        let mut header = [0u8; 2];
+        stream.read_exact(&mut header).await.ok()?;
+        let mut message = header.to_vec();
+        if header[1] & 0x80 != 0 {
+            let mut octets = vec![0u8; (header[1] & 0x7f) as usize];
+            stream.read_exact(&mut octets).await.ok()?;
+            message.extend(octets);
+        }
+        let len = match header[1] {
+            len if len < 128 => len as usize,
+            _ => integer(&message[2..]) as usize,
+        };
+        let mut body = vec![0u8; len];
+        stream.read_exact(&mut body).await.ok()?;
+        Some(body)
+    }
+
+    // Stand-in for an LDAP server, simple binds as cn=otto,dc=example with password
+    // "secret", anonymously or unauthenticated, and ou=people,dc=example holds 3 entries
+    async fn session(mut stream: TcpStream) -> Option<()> {
+        loop {
+            let message = read_message(&mut stream).await?;
+            let (_, id, rest) = element(&message)?;
+            let (op, request, _) = element(rest)?;
+            let reply = match op {
+                // bind request: version, name, simple password
+                0x60 => {
+                    let (_, _, rest) = element(request)?;
+                    let (_, dn, rest) = element(rest)?;
+                    let (_, password, _) = element(rest)?;
+                    let rc = match (dn, password) {
+                        // like many servers, any DN with an empty password is an
+                        // unauthenticated bind and succeeds
+                        (_, b"") | (b"cn=otto,dc=example", b"secret") => 0,
+                        // invalidCredentials
+                        _ => 49,
+                    };
+                    response(id, 0x61, rc)
+                }
+                // search request: base, scope, deref aliases, size limit, ...
+                0x63 => {
+                    let (_, base, rest) = element(request)?;
+                    let (_, _, rest) = element(rest)?;
+                    let (_, _, rest) = element(rest)?;
+                    let (_, limit, _) = element(rest)?;
+                    let limit = integer(limit) as usize;
+                    let (mut found, mut rc) = match base {
+                        b"ou=people,dc=example" => (3, 0),
+                        // noSuchObject
+                        _ => (0, 32),
+                    };
+                    if limit > 0 && found > limit {
+                        // sizeLimitExceeded
+                        found = limit;
+                        rc = 4;
+                    }
+                    let mut reply = vec![];
+                    for i in 0..found {
+                        let dn = format!("uid=user{},ou=people,dc=example", i);
+                        let entry = [tlv(0x04, dn.as_bytes()), tlv(0x30, b"")].concat();
+                        reply.extend(tlv(0x30, &[tlv(0x02, id), tlv(0x64, &entry)].concat()));
+                    }
+                    reply.extend(response(id, 0x65, rc));
+                    reply
+                }
+                // unbind
+                0x42 => return Some(()),
+                _ => panic!("unexpected LDAP operation {:#x}", op),
+            };
+            stream.write_all(&reply).await.ok()?;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_check_against_stand_in() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ldap://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                tokio::spawn(session(stream));
+            }
+        });
+        let search = |base: &str, min_entries: usize| Search {
+            base: base.to_owned(),
+            min_entries: Some(min_entries),
+            ..Default::default()
+        };
+
+        let anonymous = Ldap {
+            url: url.clone(),
+            search: Some(search("ou=people,dc=example", 2)),
+            ..Default::default()
+        };
+        // stopping at the size limit of min_entries still passes
+        anonymous.check().await.unwrap();
+
+        let bound = Ldap {
+            bind_dn: Some("cn=otto,dc=example".to_owned()),
+            password: Some("secret".to_owned()),
+            search: Some(search("ou=people,dc=example", 3)),
+            ..anonymous.clone()
+        };
+        bound.check().await.unwrap();
+
+        let too_few = Ldap {
+            search: Some(search("ou=people,dc=example", 5)),
+            ..bound.clone()
+        };
+        assert_eq!(
+            too_few.check().await.unwrap_err().to_string(),
+            "search (objectClass=*) under ou=people,dc=example found 3 entries, fewer than 5"
+        );
+
+        let missing = Ldap {
+            search: Some(search("ou=missing,dc=example", 1)),
+            ..bound.clone()
+        };
+        let err = missing.check().await.unwrap_err().to_string();
+        assert!(err.starts_with("search (objectClass=*) under ou=missing,dc=example failed"));
+        assert!(err.contains("rc=32"));
+
+        let wrong_password = Ldap {
+            password: Some("wrong".to_owned()),
+            ..bound.clone()
+        };
+        let err = wrong_password.check().await.unwrap_err().to_string();
+        assert!(err.starts_with("bind as cn=otto,dc=example failed"));
+        assert!(err.contains("rc=49"));
+
+        // the stand-in would accept this unauthenticated bind, so it must not be sent
+        let no_password = Ldap {
+            password: None,
+            ..bound
+        };
+        assert_eq!(
+            no_password.check().await.unwrap_err().to_string(),
+            "bind_dn cn=otto,dc=example needs a password or password_file, an empty password binds unauthenticated"
+        );
+    }
+}
@@ -0,0 +1,333 @@
+use crate::{
+    alerts::Alert,
+    probes::{Notification, Probe, HAS_INCIDENT, NO_INCIDENT},
+};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use fancy_regex::Regex;
+use lazy_static::lazy_static;
+use prometheus::{register_counter_vec, register_gauge_vec, CounterVec, GaugeVec};
+use serde_derive::{Deserialize, Serialize};
+use sled::{Db, IVec};
+use slug::slugify;
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    os::unix::fs::MetadataExt,
+};
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Logfile {
+    name: Option<String>,
+    schedule: Option<String>,
+    depends_on: Option<Vec<String>>,
+    path: String,
+    include_regex: Option<Vec<String>>,
+    exclude_regex: Option<Vec<String>>,
+    // trigger when more lines than this matched since last check
+    threshold: Option<usize>,
+    // number of matching lines to put into the notification
+    max_lines: Option<usize>,
+    // bytes to read per check, the rest is read on the next ones, default 8 MiB
+    max_bytes: Option<u64>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Position {
+    inode: u64,
+    offset: u64,
+}
+
+lazy_static! {
+    static ref RUNS_TOTAL: CounterVec = register_counter_vec!(
+        "probe_logfile_runs_total",
+        "run counter for logfile probe plugin",
+        &["plugin", "path"]
+    )
+    .unwrap();
+    static ref MATCHED_LINES_TOTAL: CounterVec = register_counter_vec!(
+        "probe_logfile_matched_lines_total",
+        "matched lines counter for logfile probe plugin",
+        &["plugin", "path"]
+    )
+    .unwrap();
+    static ref TRIGGERED_TOTAL: CounterVec = register_counter_vec!(
+        "probe_logfile_triggered_total",
+        "triggered counter for logfile probe plugin",
+        &["plugin", "path"]
+    )
+    .unwrap();
+    static ref TRIGGERED: GaugeVec = register_gauge_vec!(
+        "probe_logfile_triggered",
+        "Logfile probe plugin triggered",
+        &["plugin", "path"]
+    )
+    .unwrap();
+}
+
+fn compile(regexes: &Option<Vec<String>>) -> Result<Vec<Regex>> {
+    regexes
+        .as_ref()
+        .unwrap_or(&vec![])
+        .iter()
+        .map(|regex| Regex::new(regex).with_context(|| format!("failed parsing regex {}", regex)))
+        .collect()
+}
+
+fn is_match_any(regexes: &[Regex], line: &str) -> Result<bool> {
+    for re in regexes.iter() {
+        if re
+            .is_match(line)
+            .with_context(|| format!("failed checking regex match {}", re.as_str()))?
+        {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+impl Logfile {
+    // Read complete lines appended since the stored position, starting over
+    // when the file was rotated (inode changed) or truncated (shrunk).
+    // Reads the file synchronously, so call it off the async runtime
+    fn read_new_lines(&self, store: &Db) -> Result<Vec<String>> {
+        let key = format!("{}-position", self.slug());
+        let mut file =
+            File::open(&self.path).with_context(|| format!("failed opening {}", self.path))?;
+        let metadata = file.metadata()?;
+        let stored: Option<Position> = match store.get(key.as_bytes())? {
+            Some(stored) => serde_json::from_slice(&stored).ok(),
+            None => None,
+        };
+        let offset = match stored {
+            Some(position) if position.inode == metadata.ino() => {
+                if metadata.len() < position.offset {
+                    log::info!("log file {} was truncated, reading from start", self.path);
+                    0
+                } else {
+                    position.offset
+                }
+            }
+            Some(_) => {
+                log::info!("log file {} was rotated, reading from start", self.path);
+                0
+            }
+            // first run, only watch lines appended from now on
+            None => metadata.len(),
+        };
+
+        file.seek(SeekFrom::Start(offset))?;
+        let max_bytes = self.max_bytes.unwrap_or(8 * 1024 * 1024);
+        let mut buffer = vec![];
+        file.take(max_bytes).read_to_end(&mut buffer)?;
+        // leave a partially written last line for the next check, unless the line alone
+        // fills max_bytes and would otherwise never be read past
+        let complete = match buffer.iter().rposition(|byte| *byte == b'\n') {
+            Some(last) => last + 1,
+            None if buffer.len() as u64 == max_bytes => buffer.len(),
+            None => 0,
+        };
+        let lines = String::from_utf8_lossy(&buffer[..complete])
+            .lines()
+            .map(String::from)
+            .collect();
+
+        let position = Position {
+            inode: metadata.ino(),
+            offset: offset + complete as u64,
+        };
+        store.insert(key.as_bytes(), serde_json::to_vec(&position)?)?;
+        Ok(lines)
+    }
+}
+
+#[async_trait]
+impl Probe for Logfile {
+    fn new() -> Self {
+        Self {
+            ..Default::default()
+        }
+    }
+
+    fn local_schedule(&self) -> Option<String> {
+        self.schedule.to_owned()
+    }
+
+    fn local_name(&self) -> Option<String> {
+        self.name.to_owned()
+    }
+
+    fn depends_on(&self) -> Option<Vec<String>> {
+        self.depends_on.to_owned()
+    }
+
+    fn slug(&self) -> String {
+        slugify(format!("logfile-{}", self.path))
+    }
+
+    async fn observe(
+        &self,
+        store: &Db,
+        alerts: &HashMap<String, Vec<Box<dyn Alert>>>,
+    ) -> Result<()> {
+        log::info!("checking new lines in log file {}", self.path);
+        RUNS_TOTAL
+            .with_label_values(&["probe.logfile", &self.path])
+            .inc();
+
+        let stored = store.get(self.slug().as_bytes())?;
+        let mut to_store = NO_INCIDENT;
+        let mut triggered = 0;
+        let include = compile(&self.include_regex)?;
+        let exclude = compile(&self.exclude_regex)?;
+        let threshold = self.threshold.unwrap_or_default();
+
+        let mut matched: Vec<String> = vec![];
+        let plugin = self.clone();
+        let db = store.clone();
+        let lines = tokio::task::spawn_blocking(move || plugin.read_new_lines(&db)).await??;
+        for line in lines.into_iter() {
+            if (include.is_empty() || is_match_any(&include, &line)?)
+                && !is_match_any(&exclude, &line)?
+            {
+                matched.push(line);
+            }
+        }
+        MATCHED_LINES_TOTAL
+            .with_label_values(&["probe.logfile", &self.path])
+            .inc_by(matched.len() as f64);
+
+        if matched.len() > threshold {
+            log::info!(
+                "_TRIGGERED_: {} matching lines in {}, threshold {}",
+                matched.len(),
+                self.path,
+                threshold
+            );
+            if stored.is_none() || stored == Some(IVec::from(NO_INCIDENT)) {
+                log::warn!(
+                    "_NOTIFY_: {} matching lines in {}, threshold {}",
+                    matched.len(),
+                    self.path,
+                    threshold
+                );
+                let max_lines = self.max_lines.unwrap_or(10);
+                let mut message = matched
+                    .iter()
+                    .take(max_lines)
+                    .cloned()
+                    .collect::<Vec<String>>()
+                    .join("\n");
+                if matched.len() > max_lines {
+                    message = format!("{}\n... and {} more", message, matched.len() - max_lines);
+                }
+                self.notify(
+                    alerts,
+                    Notification {
+                        from: "logfile".to_owned(),
+                        name: self.name("logfile", self.name.to_owned()),
+                        check: format!(
+                            "matching lines in log file {} above {}",
+                            self.path, threshold
+                        ),
+                        title: format!("{} matching lines in {}", matched.len(), self.path),
+                        message,
+                        message_html: None,
+                        message_entries: None,
+                    },
+                )
+                .await?;
+            }
+            TRIGGERED_TOTAL
+                .with_label_values(&["probe.logfile", &self.path])
+                .inc();
+            triggered = 1;
+            to_store = HAS_INCIDENT;
+        }
+
+        store.insert(self.slug().as_bytes(), to_store)?;
+
+        TRIGGERED
+            .with_label_values(&["probe.logfile", &self.path])
+            .set(triggered as f64);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::{fs, io::Write};
+
+    fn append(path: &std::path::Path, content: &str) {
+        fs::OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(path)
+            .unwrap()
+            .write_all(content.as_bytes())
+            .unwrap();
+    }
+
+    #[test]
+    fn test_read_new_lines() {
+        let dir = std::env::temp_dir().join(format!("otto-logfile-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("app.log");
+        fs::write(&path, "old line\n").unwrap();
+        let store = sled::Config::new().temporary(true).open().unwrap();
+        let probe = Logfile {
+            path: path.to_string_lossy().to_string(),
+            ..Default::default()
+        };
+
+        // the first run skips what's already there
+        assert!(probe.read_new_lines(&store).unwrap().is_empty());
+        append(&path, "first\nsecond\npart");
+        assert_eq!(
+            probe.read_new_lines(&store).unwrap(),
+            vec!["first", "second"]
+        );
+        // the partial line is carried over until it's complete
+        assert!(probe.read_new_lines(&store).unwrap().is_empty());
+        append(&path, "ial\n");
+        assert_eq!(probe.read_new_lines(&store).unwrap(), vec!["partial"]);
+
+        // truncated in place, eg. by copytruncate
+        fs::OpenOptions::new()
+            .write(true)
+            .truncate(true)
+            .open(&path)
+            .unwrap();
+        append(&path, "after truncate\n");
+        assert_eq!(
+            probe.read_new_lines(&store).unwrap(),
+            vec!["after truncate"]
+        );
+
+        // rotated, a new file with a new inode takes the path
+        let rotated = dir.join("app.log.1");
+        fs::rename(&path, &rotated).unwrap();
+        append(
+            &path,
+            "after rotate, even if the new file is already longer\n",
+        );
+        assert_eq!(
+            probe.read_new_lines(&store).unwrap(),
+            vec!["after rotate, even if the new file is already longer"]
+        );
+
+        // reads are capped, and the rest is picked up on the next checks
+        let probe = Logfile {
+            max_bytes: Some(8),
+            ..probe
+        };
+        append(&path, "one\ntwo\nthree\nlonger than max_bytes\n");
+        assert_eq!(probe.read_new_lines(&store).unwrap(), vec!["one", "two"]);
+        assert_eq!(probe.read_new_lines(&store).unwrap(), vec!["three"]);
+        assert_eq!(probe.read_new_lines(&store).unwrap(), vec!["longer t"]);
+        assert_eq!(probe.read_new_lines(&store).unwrap(), vec!["han max_"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
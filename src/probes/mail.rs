@@ -0,0 +1,63 @@
+// Line based connection shared by smtp, imap and pop3 probes
+use anyhow::{Context, Result};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader},
+    net::TcpStream,
+};
+use tokio_native_tls::TlsConnector;
+
+pub trait Io: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Io for T {}
+
+pub struct Connection {
+    host: String,
+    stream: BufReader<Box<dyn Io>>,
+}
+
+impl Connection {
+    pub async fn open(host: &str, port: u16, tls: bool) -> Result<Self> {
+        let tcp = TcpStream::connect((host, port))
+            .await
+            .with_context(|| format!("failed connecting to {}:{}", host, port))?;
+        let stream: Box<dyn Io> = Box::new(tcp);
+        let conn = Self {
+            host: host.to_owned(),
+            stream: BufReader::new(stream),
+        };
+        if tls {
+            return conn.upgrade().await;
+        }
+        Ok(conn)
+    }
+
+    // Negotiate TLS on the current connection, for implicit TLS or after STARTTLS
+    pub async fn upgrade(self) -> Result<Self> {
+        let connector = TlsConnector::from(native_tls::TlsConnector::new()?);
+        let Self { host, stream } = self;
+        let tls = connector
+            .connect(&host, stream.into_inner())
+            .await
+            .with_context(|| format!("failed TLS handshake with {}", host))?;
+        let stream: Box<dyn Io> = Box::new(tls);
+        Ok(Self {
+            host,
+            stream: BufReader::new(stream),
+        })
+    }
+
+    pub async fn read_line(&mut self) -> Result<String> {
+        let mut line = String::new();
+        if self.stream.read_line(&mut line).await? == 0 {
+            anyhow::bail!("connection closed by {}", self.host);
+        }
+        Ok(line.trim_end().to_owned())
+    }
+
+    pub async fn send(&mut self, line: &str) -> Result<()> {
+        let stream = self.stream.get_mut();
+        stream.write_all(format!("{}\r\n", line).as_bytes()).await?;
+        stream.flush().await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,362 @@
+use crate::{
+    alerts::Alert,
+    probes::{password, Notification, Probe, HAS_INCIDENT, NO_INCIDENT},
+};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::Utc;
+use lazy_static::lazy_static;
+use prometheus::{register_counter_vec, register_gauge_vec, CounterVec, GaugeVec};
+use rumqttc::{
+    AsyncClient, Event, MqttOptions, Outgoing, Packet, QoS, SubscribeReasonCode, TlsConfiguration,
+    Transport,
+};
+use serde_derive::Deserialize;
+use sled::{Db, IVec};
+use slug::slugify;
+use std::{collections::HashMap, fs};
+use tokio::time::{timeout, Duration, Instant};
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Mqtt {
+    name: Option<String>,
+    schedule: Option<String>,
+    depends_on: Option<Vec<String>>,
+    host: String,
+    // default 8883 with tls, otherwise 1883
+    port: Option<u16>,
+    tls: Option<bool>,
+    // PEM encoded CA to verify the broker with, instead of system roots
+    tls_ca: Option<String>,
+    username: Option<String>,
+    password: Option<String>,
+    password_file: Option<String>,
+    client_id: Option<String>,
+    topic: String,
+    // 0, 1 or 2, default 1
+    qos: Option<u8>,
+    // subscribe only and trigger when no message arrives within this many seconds,
+    // instead of publishing to the topic and waiting for the message to come back
+    watch_window: Option<u64>,
+    // seconds to wait for the round trip, default 10
+    timeout: Option<u64>,
+}
+
+lazy_static! {
+    static ref RUNS_TOTAL: CounterVec = register_counter_vec!(
+        "probe_mqtt_runs_total",
+        "run counter for MQTT probe plugin",
+        &["plugin", "host", "topic"]
+    )
+    .unwrap();
+    static ref TRIGGERED_TOTAL: CounterVec = register_counter_vec!(
+        "probe_mqtt_triggered_total",
+        "triggered counter for MQTT probe plugin",
+        &["plugin", "host", "topic"]
+    )
+    .unwrap();
+    static ref TRIGGERED: GaugeVec = register_gauge_vec!(
+        "probe_mqtt_triggered",
+        "MQTT probe plugin triggered",
+        &["plugin", "host", "topic"]
+    )
+    .unwrap();
+    static ref ROUND_TRIP_SECONDS: GaugeVec = register_gauge_vec!(
+        "probe_mqtt_round_trip_seconds",
+        "time from publishing to receiving the message back",
+        &["plugin", "host", "topic"]
+    )
+    .unwrap();
+}
+
+impl Mqtt {
+    fn port(&self) -> u16 {
+        self.port.unwrap_or(if self.tls.unwrap_or_default() {
+            8883
+        } else {
+            1883
+        })
+    }
+
+    fn options(&self) -> Result<MqttOptions> {
+        let client_id = self
+            .client_id
+            .to_owned()
+            .unwrap_or_else(|| format!("otto-{}", Utc::now().timestamp_millis()));
+        let mut options = MqttOptions::new(client_id, &self.host, self.port());
+        options.set_keep_alive(Duration::from_secs(30));
+        if let Some(username) = self.username.as_ref() {
+            options.set_credentials(username, password(&self.password, &self.password_file)?);
+        }
+        if self.tls.unwrap_or_default() {
+            options.set_transport(match self.tls_ca.as_ref() {
+                Some(tls_ca) => Transport::Tls(TlsConfiguration::Simple {
+                    ca: fs::read(tls_ca)
+                        .with_context(|| format!("failed reading tls_ca {}", tls_ca))?,
+                    alpn: None,
+                    client_auth: None,
+                }),
+                None => Transport::tls_with_default_config(),
+            });
+        }
+        Ok(options)
+    }
+
+    fn qos(&self) -> Result<QoS> {
+        match self.qos.unwrap_or(1) {
+            0 => Ok(QoS::AtMostOnce),
+            1 => Ok(QoS::AtLeastOnce),
+            2 => Ok(QoS::ExactlyOnce),
+            qos => anyhow::bail!("invalid qos {}", qos),
+        }
+    }
+
+    // Subscribe to the topic, then either publish a tagged message and wait for it,
+    // or wait for any fresh message when watching
+    async fn check(&self) -> Result<()> {
+        let qos = self.qos()?;
+        let (client, mut eventloop) = AsyncClient::new(self.options()?, 10);
+        client.subscribe(&self.topic, qos).await?;
+        let payload = format!("otto {} {}", self.slug(), Utc::now().timestamp_millis());
+        let mut started = Instant::now();
+        loop {
+            match eventloop.poll().await.context("connection failed")? {
+                Event::Incoming(Packet::SubAck(ack)) => {
+                    if ack.return_codes.contains(&SubscribeReasonCode::Failure) {
+                        anyhow::bail!("broker refused subscribing to {}", self.topic);
+                    }
+                    if self.watch_window.is_none() {
+                        started = Instant::now();
+                        client
+                            .publish(&self.topic, qos, false, payload.as_bytes())
+                            .await?;
+                    }
+                }
+                // retained messages could be stale, only count new ones
+                Event::Incoming(Packet::Publish(publish)) if !publish.retain => {
+                    if self.watch_window.is_some() {
+                        break;
+                    }
+                    if publish.payload == payload.as_bytes() {
+                        ROUND_TRIP_SECONDS
+                            .with_label_values(&["probe.mqtt", &self.host, &self.topic])
+                            .set(started.elapsed().as_secs_f64());
+                        break;
+                    }
+                }
+                _ => {}
+            }
+        }
+        // disconnect only queues the request, the event loop sends it when polled
+        client.disconnect().await?;
+        loop {
+            match eventloop.poll().await {
+                Ok(Event::Outgoing(Outgoing::Disconnect)) | Err(_) => break,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Probe for Mqtt {
+    fn new() -> Self {
+        Self {
+            ..Default::default()
+        }
+    }
+
+    fn local_schedule(&self) -> Option<String> {
+        self.schedule.to_owned()
+    }
+
+    fn local_name(&self) -> Option<String> {
+        self.name.to_owned()
+    }
+
+    fn depends_on(&self) -> Option<Vec<String>> {
+        self.depends_on.to_owned()
+    }
+
+    fn slug(&self) -> String {
+        slugify(format!("mqtt-{}-{}-{}", self.host, self.port(), self.topic))
+    }
+
+    async fn observe(
+        &self,
+        store: &Db,
+        alerts: &HashMap<String, Vec<Box<dyn Alert>>>,
+    ) -> Result<()> {
+        let host = format!("{}:{}", self.host, self.port());
+        log::info!("checking mqtt topic {} on {}", self.topic, host);
+        RUNS_TOTAL
+            .with_label_values(&["probe.mqtt", &self.host, &self.topic])
+            .inc();
+
+        let stored = store.get(self.slug().as_bytes())?;
+        let mut to_store = NO_INCIDENT;
+        let mut triggered = 0;
+        let result = match self.watch_window {
+            Some(window) => match timeout(Duration::from_secs(window), self.check()).await {
+                Ok(result) => result,
+                Err(_) => Err(anyhow::anyhow!(
+                    "no message on topic {} within {} seconds",
+                    self.topic,
+                    window
+                )),
+            },
+            None => {
+                let seconds = self.timeout.unwrap_or(10);
+                match timeout(Duration::from_secs(seconds), self.check()).await {
+                    Ok(result) => result,
+                    Err(_) => Err(anyhow::anyhow!(
+                        "message published to topic {} not received within {} seconds",
+                        self.topic,
+                        seconds
+                    )),
+                }
+            }
+        };
+
+        if let Err(err) = result {
+            let message = format!("{:#}", err);
+            log::info!("_TRIGGERED_: mqtt {}: {}", host, message);
+            if stored.is_none() || stored == Some(IVec::from(NO_INCIDENT)) {
+                log::warn!("_NOTIFY_: mqtt {}: {}", host, message);
+                self.notify(
+                    alerts,
+                    Notification {
+                        from: "mqtt".to_owned(),
+                        name: self.name("mqtt", self.name.to_owned()),
+                        check: format!("mqtt topic {} on {}", self.topic, host),
+                        title: format!("mqtt {} check failed", host),
+                        message,
+                        message_html: None,
+                        message_entries: None,
+                    },
+                )
+                .await?;
+            }
+            TRIGGERED_TOTAL
+                .with_label_values(&["probe.mqtt", &self.host, &self.topic])
+                .inc();
+            triggered = 1;
+            to_store = HAS_INCIDENT;
+        }
+
+        store.insert(self.slug().as_bytes(), to_store)?;
+
+        TRIGGERED
+            .with_label_values(&["probe.mqtt", &self.host, &self.topic])
+            .set(triggered as f64);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use bytes::BytesMut;
+    use rumqttc::mqttbytes::{self, v4};
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    // In-process MQTT 3.1.1 broker for a single client, echoes messages back on subscribed
+    // topics and refuses subscribing to topics under "denied/", true when the client
+    // disconnected cleanly
+    async fn broker(listener: TcpListener) -> bool {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut read = BytesMut::new();
+        let mut subscribed: Vec<String> = vec![];
+        let mut pkid = 0;
+        loop {
+            let packet = match v4::read(&mut read, 1024) {
+                Ok(packet) => packet,
+                Err(mqttbytes::Error::InsufficientBytes(_)) => {
+                    if stream.read_buf(&mut read).await.unwrap_or_default() == 0 {
+                        return false;
+                    }
+                    continue;
+                }
+                Err(err) => panic!("invalid packet: {:?}", err),
+            };
+            let mut write = BytesMut::new();
+            match packet {
+                v4::Packet::Connect(_) => {
+                    v4::ConnAck::new(v4::ConnectReturnCode::Success, false).write(&mut write)
+                }
+                v4::Packet::Subscribe(subscribe) => {
+                    let codes = subscribe
+                        .filters
+                        .iter()
+                        .map(|filter| match filter.path.starts_with("denied/") {
+                            true => SubscribeReasonCode::Failure,
+                            false => {
+                                subscribed.push(filter.path.to_owned());
+                                SubscribeReasonCode::Success(filter.qos)
+                            }
+                        })
+                        .collect();
+                    v4::SubAck::new(subscribe.pkid, codes).write(&mut write)
+                }
+                v4::Packet::Publish(publish) => {
+                    if publish.qos != QoS::AtMostOnce {
+                        v4::PubAck::new(publish.pkid).write(&mut write).unwrap();
+                    }
+                    if subscribed.contains(&publish.topic) {
+                        let mut echo =
+                            v4::Publish::new(&publish.topic, publish.qos, publish.payload.to_vec());
+                        pkid += 1;
+                        echo.pkid = pkid;
+                        echo.write(&mut write)
+                    } else {
+                        Ok(0)
+                    }
+                }
+                v4::Packet::PingReq => v4::PingResp.write(&mut write),
+                v4::Packet::Disconnect => return true,
+                _ => Ok(0),
+            }
+            .unwrap();
+            stream.write_all(&write).await.unwrap();
+        }
+    }
+
+    async fn probe_on_broker(topic: &str) -> (Mqtt, tokio::task::JoinHandle<bool>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let broker = tokio::spawn(broker(listener));
+        let probe = Mqtt {
+            host: "127.0.0.1".to_owned(),
+            port: Some(port),
+            username: Some("otto".to_owned()),
+            password: Some("secret".to_owned()),
+            topic: topic.to_owned(),
+            ..Default::default()
+        };
+        (probe, broker)
+    }
+
+    #[tokio::test]
+    async fn test_round_trip_through_broker() {
+        let (probe, broker) = probe_on_broker("otto/health").await;
+        timeout(Duration::from_secs(5), probe.check())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(broker.await.unwrap(), "no DISCONNECT received");
+
+        let (probe, _) = probe_on_broker("denied/health").await;
+        let err = timeout(Duration::from_secs(5), probe.check())
+            .await
+            .unwrap()
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "broker refused subscribing to denied/health"
+        );
+    }
+}
@@ -0,0 +1,271 @@
+use crate::{
+    alerts::Alert,
+    probes::{Notification, Probe, HAS_INCIDENT, NO_INCIDENT},
+};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use lazy_static::lazy_static;
+use prometheus::{register_counter_vec, register_gauge_vec, CounterVec, GaugeVec};
+use serde_derive::Deserialize;
+use sled::{Db, IVec};
+use slug::slugify;
+use std::{
+    collections::HashMap,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tokio::{
+    net::UdpSocket,
+    time::{timeout, Duration},
+};
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Ntp {
+    name: Option<String>,
+    schedule: Option<String>,
+    depends_on: Option<Vec<String>>,
+    host: String,
+    // default 123
+    port: Option<u16>,
+    // seconds the local clock may be off by, default 0.5
+    max_offset: Option<f64>,
+    // optional highest acceptable stratum
+    max_stratum: Option<u8>,
+    // seconds to wait for the reply, default 5
+    timeout: Option<u64>,
+}
+
+lazy_static! {
+    static ref RUNS_TOTAL: CounterVec = register_counter_vec!(
+        "probe_ntp_runs_total",
+        "run counter for NTP probe plugin",
+        &["plugin", "server"]
+    )
+    .unwrap();
+    static ref TRIGGERED_TOTAL: CounterVec = register_counter_vec!(
+        "probe_ntp_triggered_total",
+        "triggered counter for NTP probe plugin",
+        &["plugin", "server"]
+    )
+    .unwrap();
+    static ref TRIGGERED: GaugeVec = register_gauge_vec!(
+        "probe_ntp_triggered",
+        "NTP probe plugin triggered",
+        &["plugin", "server"]
+    )
+    .unwrap();
+    static ref OFFSET_SECONDS: GaugeVec = register_gauge_vec!(
+        "probe_ntp_offset_seconds",
+        "local clock offset from the NTP server, positive when local clock is behind",
+        &["plugin", "server"]
+    )
+    .unwrap();
+    static ref DELAY_SECONDS: GaugeVec = register_gauge_vec!(
+        "probe_ntp_delay_seconds",
+        "round trip delay to the NTP server",
+        &["plugin", "server"]
+    )
+    .unwrap();
+    static ref STRATUM: GaugeVec = register_gauge_vec!(
+        "probe_ntp_stratum",
+        "stratum of the NTP server",
+        &["plugin", "server"]
+    )
+    .unwrap();
+}
+
+// Seconds between the NTP era (1900) and unix epoch
+const NTP_UNIX_OFFSET: f64 = 2_208_988_800.0;
+
+#[derive(Debug)]
+struct Sample {
+    leap: u8,
+    stratum: u8,
+    offset: f64,
+    delay: f64,
+}
+
+fn now() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64()
+        + NTP_UNIX_OFFSET
+}
+
+fn read_timestamp(packet: &[u8]) -> f64 {
+    let seconds = u32::from_be_bytes([packet[0], packet[1], packet[2], packet[3]]);
+    let fraction = u32::from_be_bytes([packet[4], packet[5], packet[6], packet[7]]);
+    seconds as f64 + fraction as f64 / 4_294_967_296.0
+}
+
+fn write_timestamp(packet: &mut [u8], timestamp: f64) {
+    let seconds = timestamp.trunc() as u32;
+    let fraction = (timestamp.fract() * 4_294_967_296.0) as u32;
+    packet[..4].copy_from_slice(&seconds.to_be_bytes());
+    packet[4..8].copy_from_slice(&fraction.to_be_bytes());
+}
+
+impl Ntp {
+    fn server(&self) -> String {
+        format!("{}:{}", self.host, self.port.unwrap_or(123))
+    }
+
+    // Single SNTP v4 exchange, see RFC 4330
+    async fn query(&self) -> Result<Sample> {
+        let socket = UdpSocket::bind(if self.host.contains(':') {
+            "[::]:0"
+        } else {
+            "0.0.0.0:0"
+        })
+        .await?;
+        socket
+            .connect(self.server())
+            .await
+            .with_context(|| format!("failed resolving {}", self.server()))?;
+
+        let mut request = [0u8; 48];
+        // leap indicator 0, version 4, client mode 3
+        request[0] = 0x23;
+        let originate = now();
+        write_timestamp(&mut request[40..48], originate);
+        socket.send(&request).await?;
+
+        let mut reply = [0u8; 48];
+        let len = socket
+            .recv(&mut reply)
+            .await
+            .context("failed receiving reply")?;
+        let destination = now();
+        if len < 48 {
+            anyhow::bail!("short reply of {} bytes", len);
+        }
+        if reply[0] & 0x07 != 4 {
+            anyhow::bail!("reply is not in server mode");
+        }
+        // originate timestamp should echo what we sent, to discard stale or spoofed replies
+        if reply[24..32] != request[40..48] {
+            anyhow::bail!("reply does not match request");
+        }
+        let receive = read_timestamp(&reply[32..40]);
+        let transmit = read_timestamp(&reply[40..48]);
+        Ok(Sample {
+            leap: reply[0] >> 6,
+            stratum: reply[1],
+            offset: ((receive - originate) + (transmit - destination)) / 2.0,
+            delay: (destination - originate) - (transmit - receive),
+        })
+    }
+}
+
+#[async_trait]
+impl Probe for Ntp {
+    fn new() -> Self {
+        Self {
+            ..Default::default()
+        }
+    }
+
+    fn local_schedule(&self) -> Option<String> {
+        self.schedule.to_owned()
+    }
+
+    fn local_name(&self) -> Option<String> {
+        self.name.to_owned()
+    }
+
+    fn depends_on(&self) -> Option<Vec<String>> {
+        self.depends_on.to_owned()
+    }
+
+    fn slug(&self) -> String {
+        slugify(format!("ntp-{}", self.server()))
+    }
+
+    async fn observe(
+        &self,
+        store: &Db,
+        alerts: &HashMap<String, Vec<Box<dyn Alert>>>,
+    ) -> Result<()> {
+        let server = self.server();
+        log::info!("checking clock offset against ntp {}", server);
+        RUNS_TOTAL.with_label_values(&["probe.ntp", &server]).inc();
+
+        let stored = store.get(self.slug().as_bytes())?;
+        let mut to_store = NO_INCIDENT;
+        let mut triggered = 0;
+        let seconds = self.timeout.unwrap_or(5);
+        let result = match timeout(Duration::from_secs(seconds), self.query()).await {
+            Ok(result) => result,
+            Err(_) => Err(anyhow::anyhow!("no reply within {} seconds", seconds)),
+        };
+
+        let max_offset = self.max_offset.unwrap_or(0.5);
+        let message = match result {
+            Ok(sample) => {
+                log::debug!("ntp {} replied {:?}", server, sample);
+                OFFSET_SECONDS
+                    .with_label_values(&["probe.ntp", &server])
+                    .set(sample.offset);
+                DELAY_SECONDS
+                    .with_label_values(&["probe.ntp", &server])
+                    .set(sample.delay);
+                STRATUM
+                    .with_label_values(&["probe.ntp", &server])
+                    .set(sample.stratum as f64);
+                // leap indicator 3 and stratum 0 (kiss-o'-death) or 16 mean unsynchronised
+                if sample.leap == 3 || sample.stratum == 0 || sample.stratum >= 16 {
+                    Some(format!(
+                        "server is unsynchronised, leap indicator {} stratum {}",
+                        sample.leap, sample.stratum
+                    ))
+                } else if sample.offset.abs() > max_offset {
+                    Some(format!(
+                        "local clock is off by {:.3} seconds, more than {} seconds",
+                        sample.offset, max_offset
+                    ))
+                } else {
+                    match self.max_stratum {
+                        Some(max_stratum) if sample.stratum > max_stratum => Some(format!(
+                            "server stratum {} is above {}",
+                            sample.stratum, max_stratum
+                        )),
+                        _ => None,
+                    }
+                }
+            }
+            Err(err) => Some(format!("{:#}", err)),
+        };
+
+        if let Some(message) = message {
+            log::info!("_TRIGGERED_: ntp {}: {}", server, message);
+            if stored.is_none() || stored == Some(IVec::from(NO_INCIDENT)) {
+                log::warn!("_NOTIFY_: ntp {}: {}", server, message);
+                self.notify(
+                    alerts,
+                    Notification {
+                        from: "ntp".to_owned(),
+                        name: self.name("ntp", self.name.to_owned()),
+                        check: format!("clock offset against ntp {}", server),
+                        title: format!("ntp {} check failed", server),
+                        message,
+                        message_html: None,
+                        message_entries: None,
+                    },
+                )
+                .await?;
+            }
+            TRIGGERED_TOTAL
+                .with_label_values(&["probe.ntp", &server])
+                .inc();
+            triggered = 1;
+            to_store = HAS_INCIDENT;
+        }
+
+        store.insert(self.slug().as_bytes(), to_store)?;
+
+        TRIGGERED
+            .with_label_values(&["probe.ntp", &server])
+            .set(triggered as f64);
+        Ok(())
+    }
+}
@@ -0,0 +1,298 @@
+use crate::{
+    alerts::Alert,
+    probes::{Notification, Probe, HAS_INCIDENT, NO_INCIDENT},
+};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use fancy_regex::Regex;
+use lazy_static::lazy_static;
+use prometheus::{register_counter_vec, register_gauge_vec, CounterVec, GaugeVec};
+use scraper::{Html, Selector};
+use serde_derive::Deserialize;
+use sha2::{Digest, Sha256};
+use similar::TextDiff;
+use sled::Db;
+use slug::slugify;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct PageChange {
+    name: Option<String>,
+    schedule: Option<String>,
+    depends_on: Option<Vec<String>>,
+    url: String,
+    selector: Option<String>,
+    ignore_regex: Option<Vec<String>>,
+}
+
+lazy_static! {
+    static ref RUNS_TOTAL: CounterVec = register_counter_vec!(
+        "probe_page_change_runs_total",
+        "run counter for page change probe plugin",
+        &["plugin", "url"]
+    )
+    .unwrap();
+    static ref TRIGGERED_TOTAL: CounterVec = register_counter_vec!(
+        "probe_page_change_triggered_total",
+        "triggered counter for page change probe plugin",
+        &["plugin", "url"]
+    )
+    .unwrap();
+    static ref TRIGGERED: GaugeVec = register_gauge_vec!(
+        "probe_page_change_triggered",
+        "Page change probe plugin triggered",
+        &["plugin", "url"]
+    )
+    .unwrap();
+}
+
+impl PageChange {
+    fn to_text(&self, html: &str) -> Result<String> {
+        let fragment = match self.selector.as_ref() {
+            Some(selector) => {
+                let parsed = Selector::parse(selector).map_err(|err| {
+                    anyhow::anyhow!("failed parsing selector {}: {:?}", selector, err)
+                })?;
+                let document = Html::parse_document(html);
+                let selected: Vec<String> = document
+                    .select(&parsed)
+                    .map(|element| element.html())
+                    .collect();
+                if selected.is_empty() {
+                    anyhow::bail!("selector {} matched nothing on {}", selector, self.url);
+                }
+                selected.join("\n")
+            }
+            None => html.to_owned(),
+        };
+        let mut text = html2md::parse_html(&fragment);
+        for ignore_regex in self.ignore_regex.as_ref().unwrap_or(&vec![]).iter() {
+            let re = Regex::new(ignore_regex)
+                .with_context(|| format!("failed parsing ignore_regex {}", ignore_regex))?;
+            text = strip_matches(&re, &text)
+                .with_context(|| format!("failed checking regex match {}", ignore_regex))?;
+        }
+        Ok(text)
+    }
+}
+
+fn strip_matches(re: &Regex, text: &str) -> Result<String> {
+    let mut stripped = String::new();
+    let mut last = 0;
+    let mut pos = 0;
+    while pos <= text.len() {
+        let found = match re.captures_from_pos(text, pos)? {
+            Some(caps) => caps.get(0),
+            None => None,
+        };
+        match found {
+            Some(found) => {
+                stripped.push_str(&text[last..found.start()]);
+                last = found.end();
+                pos = match text[found.end()..].chars().next() {
+                    // step over empty matches so the search always moves forward
+                    Some(next) if found.start() == found.end() => found.end() + next.len_utf8(),
+                    None if found.start() == found.end() => break,
+                    _ => found.end(),
+                };
+            }
+            None => break,
+        }
+    }
+    stripped.push_str(&text[last..]);
+    Ok(stripped)
+}
+
+#[async_trait]
+impl Probe for PageChange {
+    fn new() -> Self {
+        Self {
+            ..Default::default()
+        }
+    }
+
+    fn local_schedule(&self) -> Option<String> {
+        self.schedule.to_owned()
+    }
+
+    fn local_name(&self) -> Option<String> {
+        self.name.to_owned()
+    }
+
+    fn depends_on(&self) -> Option<Vec<String>> {
+        self.depends_on.to_owned()
+    }
+
+    fn slug(&self) -> String {
+        slugify(format!(
+            "page-change-{}-{}",
+            self.url,
+            self.selector.to_owned().unwrap_or_default()
+        ))
+    }
+
+    async fn observe(
+        &self,
+        store: &Db,
+        alerts: &HashMap<String, Vec<Box<dyn Alert>>>,
+    ) -> Result<()> {
+        log::info!("checking page {} for changes", self.url);
+        RUNS_TOTAL
+            .with_label_values(&["probe.page_change", &self.url])
+            .inc();
+
+        let mut to_store = NO_INCIDENT;
+        let mut triggered = 0;
+        // an error or maintenance page isn't a change, fail the check and keep the snapshot
+        let html = reqwest::get(&self.url)
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+        let text = self.to_text(&html)?;
+        let hash = format!("{:x}", Sha256::digest(text.as_bytes()));
+
+        let hash_key = format!("{}-hash", self.slug());
+        let snapshot_key = format!("{}-snapshot", self.slug());
+        let stored_hash = store.get(hash_key.as_bytes())?;
+        let stored_snapshot = store.get(snapshot_key.as_bytes())?;
+
+        if let (Some(stored_hash), Some(stored_snapshot)) = (stored_hash, stored_snapshot) {
+            if stored_hash != hash.as_bytes() {
+                log::info!("_TRIGGERED_: page {} changed", self.url);
+                let previous = String::from_utf8_lossy(&stored_snapshot).to_string();
+                let diff = TextDiff::from_lines(&previous, &text)
+                    .unified_diff()
+                    .context_radius(3)
+                    .header("previous", "current")
+                    .to_string();
+                // every change is a new event, so notify each time the content changes
+                log::warn!("_NOTIFY_: page {} changed", self.url);
+                self.notify(
+                    alerts,
+                    Notification {
+                        from: "page_change".to_owned(),
+                        name: self.name("page_change", self.name.to_owned()),
+                        check: format!("Content changes on page {}", self.url),
+                        title: format!("Page {} changed", self.url),
+                        message: format!("```\n{}```", diff),
+                        message_html: Some(format!(
+                            "<pre>{}</pre>",
+                            diff.replace('&', "&amp;")
+                                .replace('<', "&lt;")
+                                .replace('>', "&gt;")
+                        )),
+                        message_entries: None,
+                    },
+                )
+                .await?;
+                TRIGGERED_TOTAL
+                    .with_label_values(&["probe.page_change", &self.url])
+                    .inc();
+                triggered = 1;
+                to_store = HAS_INCIDENT;
+            }
+        }
+
+        store.insert(hash_key.as_bytes(), hash.as_bytes())?;
+        store.insert(snapshot_key.as_bytes(), text.as_bytes())?;
+        store.insert(self.slug().as_bytes(), to_store)?;
+
+        TRIGGERED
+            .with_label_values(&["probe.page_change", &self.url])
+            .set(triggered as f64);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::probes::testing;
+    use std::{
+        net::SocketAddr,
+        sync::{Arc, Mutex},
+    };
+    use warp::{http::StatusCode, Filter};
+
+    #[test]
+    fn test_strip_matches() {
+        let re = Regex::new(r"\d+ visitors").unwrap();
+        assert_eq!(
+            strip_matches(&re, "12 visitors today, 3 visitors now").unwrap(),
+            " today,  now"
+        );
+        assert_eq!(strip_matches(&re, "nobody").unwrap(), "nobody");
+        // empty matches strip nothing and still reach the end, also past multibyte chars
+        let re = Regex::new(r"x*").unwrap();
+        assert_eq!(strip_matches(&re, "aéxxb").unwrap(), "aéb");
+    }
+
+    #[test]
+    fn test_to_text() {
+        let html = r#"<html><body>
+            <nav>Home</nav>
+            <div class="price">Price: 10 EUR</div>
+            <div class="price">Updated 2026-10-19</div>
+        </body></html>"#;
+        let page = PageChange {
+            url: "http://shop.test".to_owned(),
+            selector: Some(".price".to_owned()),
+            ignore_regex: Some(vec![r"Updated \d{4}-\d{2}-\d{2}".to_owned()]),
+            ..Default::default()
+        };
+        let text = page.to_text(html).unwrap();
+        assert!(text.contains("Price: 10 EUR"));
+        assert!(!text.contains("Home"));
+        assert!(!text.contains("2026"));
+
+        let page = PageChange {
+            selector: Some(".missing".to_owned()),
+            ..page
+        };
+        assert_eq!(
+            page.to_text(html).unwrap_err().to_string(),
+            "selector .missing matched nothing on http://shop.test"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_changed_and_unchanged_page() {
+        let page = Arc::new(Mutex::new((StatusCode::OK, "<p>Price: 10 EUR</p>")));
+        let served = page.clone();
+        let route = warp::path("shop").map(move || {
+            let (status, body) = *served.lock().unwrap();
+            warp::reply::with_status(warp::reply::html(body), status)
+        });
+        let (addr, server) =
+            warp::serve(route).bind_ephemeral("127.0.0.1:0".parse::<SocketAddr>().unwrap());
+        tokio::spawn(server);
+
+        let probe = PageChange {
+            url: format!("http://{}/shop", addr),
+            ..Default::default()
+        };
+        let (store, alerts, sent) = testing::setup();
+        // the first run only takes the snapshot
+        probe.observe(&store, &alerts).await.unwrap();
+        probe.observe(&store, &alerts).await.unwrap();
+        assert!(testing::drain(&sent).is_empty());
+
+        *page.lock().unwrap() = (
+            StatusCode::SERVICE_UNAVAILABLE,
+            "<p>Down for maintenance</p>",
+        );
+        assert!(probe.observe(&store, &alerts).await.is_err());
+        assert!(testing::drain(&sent).is_empty());
+
+        *page.lock().unwrap() = (StatusCode::OK, "<p>Price: 12 EUR</p>");
+        probe.observe(&store, &alerts).await.unwrap();
+        let notified = testing::drain(&sent);
+        assert_eq!(notified.len(), 1);
+        assert!(notified[0].contains("-Price: 10 EUR"));
+        assert!(notified[0].contains("+Price: 12 EUR"));
+        assert!(!notified[0].contains("maintenance"));
+        probe.observe(&store, &alerts).await.unwrap();
+        assert!(testing::drain(&sent).is_empty());
+    }
+}
@@ -0,0 +1,285 @@
+use crate::{
+    alerts::Alert,
+    probes::{mail::Connection, password, Notification, Probe, HAS_INCIDENT, NO_INCIDENT},
+};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use fancy_regex::Regex;
+use lazy_static::lazy_static;
+use prometheus::{register_counter_vec, register_gauge_vec, CounterVec, GaugeVec};
+use serde_derive::Deserialize;
+use sled::{Db, IVec};
+use slug::slugify;
+use std::collections::HashMap;
+use tokio::time::{timeout, Duration, Instant};
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Pop3 {
+    name: Option<String>,
+    schedule: Option<String>,
+    depends_on: Option<Vec<String>>,
+    host: String,
+    // default 995 with tls, otherwise 110
+    port: Option<u16>,
+    // implicit TLS
+    tls: Option<bool>,
+    starttls: Option<bool>,
+    banner_regex: Option<String>,
+    // log in when username is set
+    username: Option<String>,
+    password: Option<String>,
+    password_file: Option<String>,
+    // seconds to wait for the whole check, default 10
+    timeout: Option<u64>,
+}
+
+lazy_static! {
+    static ref RUNS_TOTAL: CounterVec = register_counter_vec!(
+        "probe_pop3_runs_total",
+        "run counter for POP3 probe plugin",
+        &["plugin", "host"]
+    )
+    .unwrap();
+    static ref TRIGGERED_TOTAL: CounterVec = register_counter_vec!(
+        "probe_pop3_triggered_total",
+        "triggered counter for POP3 probe plugin",
+        &["plugin", "host"]
+    )
+    .unwrap();
+    static ref TRIGGERED: GaugeVec = register_gauge_vec!(
+        "probe_pop3_triggered",
+        "POP3 probe plugin triggered",
+        &["plugin", "host"]
+    )
+    .unwrap();
+    static ref RESPONSE_SECONDS: GaugeVec = register_gauge_vec!(
+        "probe_pop3_response_seconds",
+        "time taken by the POP3 check",
+        &["plugin", "host"]
+    )
+    .unwrap();
+}
+
+// Send a command and expect a +OK reply, naming only the verb on failure
+async fn command(conn: &mut Connection, command: &str) -> Result<String> {
+    conn.send(command).await?;
+    let reply = conn.read_line().await?;
+    if !reply.starts_with("+OK") {
+        let verb = command.split_whitespace().next().unwrap_or_default();
+        anyhow::bail!("{} failed: {}", verb, reply);
+    }
+    Ok(reply)
+}
+
+impl Pop3 {
+    fn port(&self) -> u16 {
+        self.port.unwrap_or(if self.tls.unwrap_or_default() {
+            995
+        } else {
+            110
+        })
+    }
+
+    async fn check(&self) -> Result<()> {
+        let mut conn =
+            Connection::open(&self.host, self.port(), self.tls.unwrap_or_default()).await?;
+        let greeting = conn.read_line().await?;
+        if !greeting.starts_with("+OK") {
+            anyhow::bail!("unexpected greeting: {}", greeting);
+        }
+        if let Some(banner_regex) = self.banner_regex.as_ref() {
+            let re = Regex::new(banner_regex)
+                .with_context(|| format!("failed parsing banner_regex {}", banner_regex))?;
+            if !re.is_match(&greeting)? {
+                anyhow::bail!("greeting {} does not match {}", greeting, banner_regex);
+            }
+        }
+        if self.starttls.unwrap_or_default() {
+            command(&mut conn, "STLS").await?;
+            conn = conn.upgrade().await?;
+        }
+        if let Some(username) = self.username.as_ref() {
+            let password = password(&self.password, &self.password_file)?;
+            command(&mut conn, &format!("USER {}", username)).await?;
+            command(&mut conn, &format!("PASS {}", password)).await?;
+        }
+        command(&mut conn, "QUIT").await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Probe for Pop3 {
+    fn new() -> Self {
+        Self {
+            ..Default::default()
+        }
+    }
+
+    fn local_schedule(&self) -> Option<String> {
+        self.schedule.to_owned()
+    }
+
+    fn local_name(&self) -> Option<String> {
+        self.name.to_owned()
+    }
+
+    fn depends_on(&self) -> Option<Vec<String>> {
+        self.depends_on.to_owned()
+    }
+
+    fn slug(&self) -> String {
+        slugify(format!(
+            "pop3-{}-{}-{}",
+            self.host,
+            self.port(),
+            self.username.to_owned().unwrap_or_default()
+        ))
+    }
+
+    async fn observe(
+        &self,
+        store: &Db,
+        alerts: &HashMap<String, Vec<Box<dyn Alert>>>,
+    ) -> Result<()> {
+        let host = format!("{}:{}", self.host, self.port());
+        log::info!("checking pop3 {}", host);
+        RUNS_TOTAL.with_label_values(&["probe.pop3", &host]).inc();
+
+        let stored = store.get(self.slug().as_bytes())?;
+        let mut to_store = NO_INCIDENT;
+        let mut triggered = 0;
+        let seconds = self.timeout.unwrap_or(10);
+        let started = Instant::now();
+        let result = match timeout(Duration::from_secs(seconds), self.check()).await {
+            Ok(result) => result,
+            Err(_) => Err(anyhow::anyhow!("timed out after {} seconds", seconds)),
+        };
+        RESPONSE_SECONDS
+            .with_label_values(&["probe.pop3", &host])
+            .set(started.elapsed().as_secs_f64());
+
+        if let Err(err) = result {
+            let message = format!("{:#}", err);
+            log::info!("_TRIGGERED_: pop3 {}: {}", host, message);
+            if stored.is_none() || stored == Some(IVec::from(NO_INCIDENT)) {
+                log::warn!("_NOTIFY_: pop3 {}: {}", host, message);
+                self.notify(
+                    alerts,
+                    Notification {
+                        from: "pop3".to_owned(),
+                        name: self.name("pop3", self.name.to_owned()),
+                        check: format!("pop3 {}", host),
+                        title: format!("pop3 {} check failed", host),
+                        message,
+                        message_html: None,
+                        message_entries: None,
+                    },
+                )
+                .await?;
+            }
+            TRIGGERED_TOTAL
+                .with_label_values(&["probe.pop3", &host])
+                .inc();
+            triggered = 1;
+            to_store = HAS_INCIDENT;
+        }
+
+        store.insert(self.slug().as_bytes(), to_store)?;
+
+        TRIGGERED
+            .with_label_values(&["probe.pop3", &host])
+            .set(triggered as f64);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+        net::{TcpListener, TcpStream},
+    };
+
+    // Stand-in for a POP3 server without STLS, accepts otto with password "secret"
+    async fn session(stream: TcpStream) -> Option<()> {
+        let (read, mut write) = stream.into_split();
+        let mut lines = BufReader::new(read).lines();
+        write.write_all(b"+OK POP3 stand-in ready\r\n").await.ok()?;
+        let mut user = String::new();
+        while let Some(line) = lines.next_line().await.ok()? {
+            let (verb, arg) = match line.split_once(' ') {
+                Some((verb, arg)) => (verb, arg),
+                None => (line.as_str(), ""),
+            };
+            let reply = match verb {
+                "USER" => {
+                    user = arg.to_owned();
+                    "+OK send PASS"
+                }
+                "PASS" if user == "otto" && arg == "secret" => "+OK logged in",
+                "PASS" => "-ERR [AUTH] invalid login",
+                "QUIT" => {
+                    write.write_all(b"+OK bye\r\n").await.ok()?;
+                    return Some(());
+                }
+                _ => "-ERR unknown command",
+            };
+            write
+                .write_all(format!("{}\r\n", reply).as_bytes())
+                .await
+                .ok()?;
+        }
+        Some(())
+    }
+
+    #[tokio::test]
+    async fn test_check_against_stand_in() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                tokio::spawn(session(stream));
+            }
+        });
+
+        let probe = Pop3 {
+            host: "127.0.0.1".to_owned(),
+            port: Some(port),
+            banner_regex: Some("stand-in".to_owned()),
+            username: Some("otto".to_owned()),
+            password: Some("secret".to_owned()),
+            ..Default::default()
+        };
+        probe.check().await.unwrap();
+
+        let wrong_password = Pop3 {
+            password: Some("wrong".to_owned()),
+            ..probe.clone()
+        };
+        assert_eq!(
+            wrong_password.check().await.unwrap_err().to_string(),
+            "PASS failed: -ERR [AUTH] invalid login"
+        );
+
+        let other_banner = Pop3 {
+            banner_regex: Some("Dovecot".to_owned()),
+            ..probe.clone()
+        };
+        assert_eq!(
+            other_banner.check().await.unwrap_err().to_string(),
+            "greeting +OK POP3 stand-in ready does not match Dovecot"
+        );
+
+        let starttls = Pop3 {
+            starttls: Some(true),
+            ..probe
+        };
+        assert_eq!(
+            starttls.check().await.unwrap_err().to_string(),
+            "STLS failed: -ERR unknown command"
+        );
+    }
+}
@@ -0,0 +1,404 @@
+use crate::{
+    alerts::Alert,
+    probes::{MessageEntry, Notification, Probe, HAS_INCIDENT, NO_INCIDENT},
+};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use fancy_regex::Regex;
+use lazy_static::lazy_static;
+use prometheus::{register_counter_vec, register_gauge_vec, CounterVec, GaugeVec};
+use serde_derive::Deserialize;
+use sled::{Db, IVec};
+use slug::slugify;
+use std::{collections::HashMap, fs, path::Path};
+use tokio::time::{sleep, Duration};
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Process {
+    name: Option<String>,
+    schedule: Option<String>,
+    depends_on: Option<Vec<String>>,
+    // match processes by any combination of the following, names longer than the 15 bytes
+    // the kernel keeps in comm are matched against the executable or argv[0] instead
+    process_name: Option<String>,
+    cmdline_regex: Option<String>,
+    user: Option<String>,
+    pidfile: Option<String>,
+    // bounds of number of matching processes, default at least 1
+    min: Option<usize>,
+    max: Option<usize>,
+    // per process resident memory in megabytes and CPU usage in percent
+    max_rss_mb: Option<u64>,
+    max_cpu: Option<f64>,
+}
+
+lazy_static! {
+    static ref RUNS_TOTAL: CounterVec = register_counter_vec!(
+        "probe_process_runs_total",
+        "run counter for process probe plugin",
+        &["plugin", "name"]
+    )
+    .unwrap();
+    static ref TRIGGERED_TOTAL: CounterVec = register_counter_vec!(
+        "probe_process_triggered_total",
+        "triggered counter for process probe plugin",
+        &["plugin", "name"]
+    )
+    .unwrap();
+    static ref TRIGGERED: GaugeVec = register_gauge_vec!(
+        "probe_process_triggered",
+        "Process probe plugin triggered",
+        &["plugin", "name"]
+    )
+    .unwrap();
+    static ref COUNT: GaugeVec = register_gauge_vec!(
+        "probe_process_count",
+        "number of matching processes from process probe plugin",
+        &["plugin", "name"]
+    )
+    .unwrap();
+}
+
+// Resolve a user name or numeric uid from /etc/passwd
+pub fn uid_of(user: &str) -> Result<u32> {
+    if let Ok(uid) = user.parse::<u32>() {
+        return Ok(uid);
+    }
+    let passwd = fs::read_to_string("/etc/passwd").context("failed reading /etc/passwd")?;
+    for line in passwd.lines() {
+        let fields: Vec<&str> = line.split(':').collect();
+        if fields.len() > 2 && fields[0] == user {
+            return Ok(fields[2].parse()?);
+        }
+    }
+    anyhow::bail!("unknown user {}", user)
+}
+
+#[derive(Debug)]
+struct ProcessInfo {
+    pid: u32,
+    comm: String,
+    // file names of the executable and of argv[0]
+    exe: Option<String>,
+    argv0: Option<String>,
+    cmdline: String,
+    uid: Option<u32>,
+    rss_kb: u64,
+    ticks: u64,
+}
+
+fn read_process(pid: u32) -> Option<ProcessInfo> {
+    let dir = format!("/proc/{}", pid);
+    let comm = fs::read_to_string(format!("{}/comm", dir)).ok()?;
+    let cmdline = fs::read(format!("{}/cmdline", dir)).ok()?;
+    let status = fs::read_to_string(format!("{}/status", dir)).ok()?;
+    let field_of = |name: &str| {
+        status
+            .lines()
+            .find(|line| line.starts_with(name))
+            .and_then(|line| line.split_whitespace().nth(1))
+            .and_then(|value| value.parse::<u64>().ok())
+    };
+    let cmdline = String::from_utf8_lossy(&cmdline);
+    let file_name = |path: &str| {
+        Path::new(path)
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+    };
+    Some(ProcessInfo {
+        pid,
+        comm: comm.trim().to_owned(),
+        // only readable for processes of the same user, unless running as root
+        exe: fs::read_link(format!("{}/exe", dir))
+            .ok()
+            .and_then(|exe| file_name(&exe.to_string_lossy())),
+        argv0: cmdline.split('\0').next().and_then(file_name),
+        cmdline: cmdline
+            .split('\0')
+            .filter(|arg| !arg.is_empty())
+            .collect::<Vec<&str>>()
+            .join(" "),
+        uid: field_of("Uid:").map(|uid| uid as u32),
+        rss_kb: field_of("VmRSS:").unwrap_or_default(),
+        ticks: cpu_ticks(pid).unwrap_or_default(),
+    })
+}
+
+impl ProcessInfo {
+    // comm is truncated to 15 bytes, so longer names can only be found elsewhere
+    fn is_named(&self, name: &str) -> bool {
+        self.comm == name
+            || (name.len() > 15
+                && (self.exe.as_deref() == Some(name) || self.argv0.as_deref() == Some(name)))
+    }
+}
+
+// User plus system CPU time in clock ticks from /proc/{pid}/stat
+fn cpu_ticks(pid: u32) -> Option<u64> {
+    let stat = fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    // skip past the command name, which may contain spaces and parentheses
+    let fields: Vec<&str> = stat[stat.rfind(')')? + 1..].split_whitespace().collect();
+    let utime = fields.get(11)?.parse::<u64>().ok()?;
+    let stime = fields.get(12)?.parse::<u64>().ok()?;
+    Some(utime + stime)
+}
+
+impl Process {
+    fn matching(&self) -> Result<Vec<ProcessInfo>> {
+        let cmdline_regex = match self.cmdline_regex.as_ref() {
+            Some(regex) => Some(
+                Regex::new(regex)
+                    .with_context(|| format!("failed parsing cmdline_regex {}", regex))?,
+            ),
+            None => None,
+        };
+        let uid = match self.user.as_ref() {
+            Some(user) => Some(uid_of(user)?),
+            None => None,
+        };
+        let pids: Vec<u32> = match self.pidfile.as_ref() {
+            Some(pidfile) => match fs::read_to_string(pidfile) {
+                Ok(pid) => pid.trim().parse().into_iter().collect(),
+                Err(err) => {
+                    log::info!("failed reading pidfile {}: {}", pidfile, err);
+                    vec![]
+                }
+            },
+            None => fs::read_dir("/proc")?
+                .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse().ok())
+                .collect(),
+        };
+
+        let mut matching = vec![];
+        for pid in pids.into_iter() {
+            let process = match read_process(pid) {
+                Some(process) => process,
+                None => continue,
+            };
+            if let Some(process_name) = self.process_name.as_ref() {
+                if !process.is_named(process_name) {
+                    continue;
+                }
+            }
+            if let Some(re) = cmdline_regex.as_ref() {
+                if !re.is_match(&process.cmdline)? {
+                    continue;
+                }
+            }
+            if uid.is_some() && process.uid != uid {
+                continue;
+            }
+            matching.push(process);
+        }
+        Ok(matching)
+    }
+
+    fn selector(&self) -> String {
+        let mut selector = vec![];
+        if let Some(process_name) = self.process_name.as_ref() {
+            selector.push(format!("name {}", process_name));
+        }
+        if let Some(cmdline_regex) = self.cmdline_regex.as_ref() {
+            selector.push(format!("cmdline {}", cmdline_regex));
+        }
+        if let Some(user) = self.user.as_ref() {
+            selector.push(format!("user {}", user));
+        }
+        if let Some(pidfile) = self.pidfile.as_ref() {
+            selector.push(format!("pidfile {}", pidfile));
+        }
+        selector.join(", ")
+    }
+}
+
+#[async_trait]
+impl Probe for Process {
+    fn new() -> Self {
+        Self {
+            ..Default::default()
+        }
+    }
+
+    fn local_schedule(&self) -> Option<String> {
+        self.schedule.to_owned()
+    }
+
+    fn local_name(&self) -> Option<String> {
+        self.name.to_owned()
+    }
+
+    fn depends_on(&self) -> Option<Vec<String>> {
+        self.depends_on.to_owned()
+    }
+
+    fn slug(&self) -> String {
+        slugify(format!("process-{}", self.selector()))
+    }
+
+    async fn observe(
+        &self,
+        store: &Db,
+        alerts: &HashMap<String, Vec<Box<dyn Alert>>>,
+    ) -> Result<()> {
+        let name = self.name("process", self.name.to_owned());
+        log::info!("checking processes matching {}", self.selector());
+        RUNS_TOTAL
+            .with_label_values(&["probe.process", &name])
+            .inc();
+
+        let stored = store.get(self.slug().as_bytes())?;
+        let mut to_store = NO_INCIDENT;
+        let mut triggered = 0;
+        let processes = self.matching()?;
+        COUNT
+            .with_label_values(&["probe.process", &name])
+            .set(processes.len() as f64);
+
+        // sample CPU time twice to get the current usage
+        let sample = 500;
+        let mut cpu: HashMap<u32, f64> = HashMap::new();
+        if self.max_cpu.is_some() {
+            sleep(Duration::from_millis(sample)).await;
+            let ticks_per_second = unsafe { libc::sysconf(libc::_SC_CLK_TCK) }.max(1) as f64;
+            for process in processes.iter() {
+                if let Some(ticks) = cpu_ticks(process.pid) {
+                    let seconds = ticks.saturating_sub(process.ticks) as f64 / ticks_per_second;
+                    cpu.insert(process.pid, seconds / (sample as f64 / 1000.0) * 100.0);
+                }
+            }
+        }
+
+        let mut problems: Vec<String> = vec![];
+        let min = self.min.unwrap_or(1);
+        if processes.len() < min {
+            problems.push(format!(
+                "{} process(es) running, want at least {}",
+                processes.len(),
+                min
+            ));
+        }
+        if let Some(max) = self.max {
+            if processes.len() > max {
+                problems.push(format!(
+                    "{} process(es) running, want at most {}",
+                    processes.len(),
+                    max
+                ));
+            }
+        }
+        let mut message_entries: Vec<(i8, MessageEntry)> = vec![];
+        for process in processes.iter() {
+            let mut exceeded = vec![];
+            if let Some(max_rss_mb) = self.max_rss_mb {
+                if process.rss_kb > max_rss_mb * 1024 {
+                    exceeded.push(format!(
+                        "RSS {} MB above {} MB",
+                        process.rss_kb / 1024,
+                        max_rss_mb
+                    ));
+                }
+            }
+            if let (Some(max_cpu), Some(usage)) = (self.max_cpu, cpu.get(&process.pid)) {
+                if *usage > max_cpu {
+                    exceeded.push(format!("CPU {:.1}% above {}%", usage, max_cpu));
+                }
+            }
+            if !exceeded.is_empty() {
+                problems.push(format!("pid {} {}", process.pid, exceeded.join(", ")));
+            }
+            if message_entries.len() < i8::MAX as usize {
+                message_entries.push((
+                    message_entries.len() as i8, // index of this message entry
+                    MessageEntry {
+                        title: format!("pid {}", process.pid),
+                        description: if exceeded.is_empty() {
+                            process.cmdline.clone()
+                        } else {
+                            format!("{}\n{}", process.cmdline, exceeded.join(", "))
+                        },
+                    },
+                ));
+            }
+        }
+
+        if !problems.is_empty() {
+            log::info!(
+                "_TRIGGERED_: processes matching {}: {}",
+                self.selector(),
+                problems.join(", ")
+            );
+            if stored.is_none() || stored == Some(IVec::from(NO_INCIDENT)) {
+                log::warn!(
+                    "_NOTIFY_: processes matching {}: {}",
+                    self.selector(),
+                    problems.join(", ")
+                );
+                let pids: Vec<String> = processes
+                    .iter()
+                    .map(|process| format!("{} {}", process.pid, process.cmdline))
+                    .collect();
+                self.notify(
+                    alerts,
+                    Notification {
+                        from: "process".to_owned(),
+                        name: name.clone(),
+                        check: format!("processes matching {}", self.selector()),
+                        title: problems.first().cloned().unwrap_or_default(),
+                        message: format!("{}\n\n{}", problems.join("\n"), pids.join("\n")),
+                        message_html: None,
+                        message_entries: if message_entries.is_empty() {
+                            None
+                        } else {
+                            Some(message_entries)
+                        },
+                    },
+                )
+                .await?;
+            }
+            TRIGGERED_TOTAL
+                .with_label_values(&["probe.process", &name])
+                .inc();
+            triggered = 1;
+            to_store = HAS_INCIDENT;
+        }
+
+        store.insert(self.slug().as_bytes(), to_store)?;
+
+        TRIGGERED
+            .with_label_values(&["probe.process", &name])
+            .set(triggered as f64);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_long_process_name() {
+        let process = ProcessInfo {
+            pid: 1,
+            comm: "prometheus-node".to_owned(),
+            exe: None,
+            argv0: Some("prometheus-node-exporter".to_owned()),
+            cmdline: "/usr/bin/prometheus-node-exporter --web.listen-address=:9100".to_owned(),
+            uid: Some(0),
+            rss_kb: 0,
+            ticks: 0,
+        };
+        assert!(process.is_named("prometheus-node"));
+        assert!(process.is_named("prometheus-node-exporter"));
+        assert!(!process.is_named("prometheus-node-exporter-textfile"));
+        assert!(!process.is_named("prometheus"));
+
+        // the test binary itself, named otto-<hash>, which is longer than comm keeps
+        let exe = std::env::current_exe().unwrap();
+        let probe = Process {
+            process_name: Some(exe.file_name().unwrap().to_string_lossy().to_string()),
+            ..Default::default()
+        };
+        let pids: Vec<u32> = probe.matching().unwrap().iter().map(|p| p.pid).collect();
+        assert!(pids.contains(&std::process::id()));
+    }
+}
@@ -0,0 +1,454 @@
+use crate::{
+    alerts::Alert,
+    probes::{Comparison, MessageEntry, Notification, Probe, HAS_INCIDENT, NO_INCIDENT},
+};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use lazy_static::lazy_static;
+use prometheus::{register_counter_vec, register_gauge_vec, CounterVec, GaugeVec};
+use serde_derive::Deserialize;
+use serde_json::Value;
+use sled::{Db, IVec};
+use slug::slugify;
+use std::collections::{BTreeMap, HashMap};
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Prometheus {
+    name: Option<String>,
+    schedule: Option<String>,
+    depends_on: Option<Vec<String>>,
+    // scrape a metrics endpoint directly
+    scrape_url: Option<String>,
+    metric: Option<String>,
+    labels: Option<HashMap<String, String>>,
+    // or run an instant PromQL query against prometheus HTTP API
+    query_url: Option<String>,
+    query: Option<String>,
+    condition: String,
+}
+
+lazy_static! {
+    static ref RUNS_TOTAL: CounterVec = register_counter_vec!(
+        "probe_prometheus_runs_total",
+        "run counter for prometheus probe plugin",
+        &["plugin", "target"]
+    )
+    .unwrap();
+    static ref TRIGGERED_TOTAL: CounterVec = register_counter_vec!(
+        "probe_prometheus_triggered_total",
+        "triggered counter for prometheus probe plugin",
+        &["plugin", "target"]
+    )
+    .unwrap();
+    static ref TRIGGERED: GaugeVec = register_gauge_vec!(
+        "probe_prometheus_triggered",
+        "Prometheus probe plugin triggered",
+        &["plugin", "target"]
+    )
+    .unwrap();
+}
+
+#[derive(Debug)]
+struct Sample {
+    name: String,
+    labels: BTreeMap<String, String>,
+    value: String,
+}
+
+impl Sample {
+    fn series(&self) -> String {
+        let labels: Vec<String> = self
+            .labels
+            .iter()
+            .map(|(label, value)| format!("{}=\"{}\"", label, value))
+            .collect();
+        format!("{}{{{}}}", self.name, labels.join(", "))
+    }
+}
+
+// Parse one sample line of prometheus text exposition format, eg.
+// `http_requests_total{method="post",code="200"} 1027 1395066363000`
+fn parse_sample(line: &str) -> Option<Sample> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+    let name_end = line.find(|c: char| c == '{' || c.is_whitespace())?;
+    let name = line[..name_end].to_owned();
+    let mut labels = BTreeMap::new();
+    let mut rest = &line[name_end..];
+    if rest.starts_with('{') {
+        let mut chars = rest.char_indices().skip(1);
+        let mut label = String::new();
+        let mut value = String::new();
+        let mut in_value = false;
+        let mut escaped = false;
+        let mut end = None;
+        while let Some((i, c)) = chars.next() {
+            if in_value {
+                match c {
+                    _ if escaped => {
+                        value.push(match c {
+                            'n' => '\n',
+                            _ => c,
+                        });
+                        escaped = false;
+                    }
+                    '\\' => escaped = true,
+                    '"' => {
+                        labels.insert(label.trim().to_owned(), value.clone());
+                        label.clear();
+                        value.clear();
+                        in_value = false;
+                    }
+                    _ => value.push(c),
+                }
+            } else {
+                match c {
+                    '}' => {
+                        end = Some(i);
+                        break;
+                    }
+                    '=' => {
+                        // skip the opening quote of label value
+                        chars.next();
+                        in_value = true;
+                    }
+                    ',' => {}
+                    _ => label.push(c),
+                }
+            }
+        }
+        rest = &rest[end? + 1..];
+    }
+    let value = rest.split_whitespace().next()?.to_owned();
+    Some(Sample {
+        name,
+        labels,
+        value,
+    })
+}
+
+impl Prometheus {
+    fn target(&self) -> String {
+        match (self.scrape_url.as_ref(), self.query_url.as_ref()) {
+            (Some(scrape_url), _) => scrape_url.to_owned(),
+            (None, Some(query_url)) => query_url.to_owned(),
+            (None, None) => "".to_owned(),
+        }
+    }
+
+    async fn scrape(&self, scrape_url: &str) -> Result<Vec<Sample>> {
+        let metric = self
+            .metric
+            .as_ref()
+            .with_context(|| format!("metric is required to scrape {}", scrape_url))?;
+        let no_labels = HashMap::new();
+        let want_labels = self.labels.as_ref().unwrap_or(&no_labels);
+        // an error page has no samples, which would pass every condition but absent
+        let body = reqwest::get(scrape_url)
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+        Ok(body
+            .lines()
+            .filter_map(parse_sample)
+            .filter(|sample| &sample.name == metric)
+            .filter(|sample| {
+                want_labels
+                    .iter()
+                    .all(|(label, value)| sample.labels.get(label) == Some(value))
+            })
+            .collect())
+    }
+
+    async fn query(&self, query_url: &str) -> Result<Vec<Sample>> {
+        let query = self
+            .query
+            .as_ref()
+            .with_context(|| format!("query is required to query {}", query_url))?;
+        let client = reqwest::Client::new();
+        let resp: Value = client
+            .get(format!("{}/api/v1/query", query_url.trim_end_matches('/')))
+            .query(&[("query", query)])
+            .send()
+            .await?
+            .json()
+            .await?;
+        if resp["status"] != "success" {
+            anyhow::bail!(
+                "query {} failed: {}",
+                query,
+                resp["error"].as_str().unwrap_or("unknown error")
+            );
+        }
+        let value_of = |value: &Value| value[1].as_str().map(String::from);
+        let mut samples = vec![];
+        match resp["data"]["resultType"].as_str() {
+            Some("vector") => {
+                for result in resp["data"]["result"].as_array().unwrap_or(&vec![]).iter() {
+                    let mut labels = BTreeMap::new();
+                    for (label, value) in result["metric"].as_object().into_iter().flatten() {
+                        labels.insert(label.to_owned(), value.as_str().unwrap_or("").to_owned());
+                    }
+                    let name = labels.remove("__name__").unwrap_or_default();
+                    if let Some(value) = value_of(&result["value"]) {
+                        samples.push(Sample {
+                            name,
+                            labels,
+                            value,
+                        });
+                    }
+                }
+            }
+            Some("scalar") => {
+                if let Some(value) = value_of(&resp["data"]["result"]) {
+                    samples.push(Sample {
+                        name: "scalar".to_owned(),
+                        labels: BTreeMap::new(),
+                        value,
+                    });
+                }
+            }
+            other => anyhow::bail!("unsupported query result type: {:?}", other),
+        }
+        Ok(samples)
+    }
+}
+
+#[async_trait]
+impl Probe for Prometheus {
+    fn new() -> Self {
+        Self {
+            ..Default::default()
+        }
+    }
+
+    fn local_schedule(&self) -> Option<String> {
+        self.schedule.to_owned()
+    }
+
+    fn local_name(&self) -> Option<String> {
+        self.name.to_owned()
+    }
+
+    fn depends_on(&self) -> Option<Vec<String>> {
+        self.depends_on.to_owned()
+    }
+
+    fn slug(&self) -> String {
+        slugify(format!(
+            "prometheus-{}-{}-{}",
+            self.target(),
+            self.metric
+                .to_owned()
+                .or_else(|| self.query.to_owned())
+                .unwrap_or_default(),
+            self.condition
+        ))
+    }
+
+    async fn observe(
+        &self,
+        store: &Db,
+        alerts: &HashMap<String, Vec<Box<dyn Alert>>>,
+    ) -> Result<()> {
+        let target = self.target();
+        log::info!(
+            "evaluating condition {} against prometheus metrics from {}",
+            self.condition,
+            target
+        );
+        RUNS_TOTAL
+            .with_label_values(&["probe.prometheus", &target])
+            .inc();
+
+        let stored = store.get(self.slug().as_bytes())?;
+        let mut to_store = NO_INCIDENT;
+        let mut triggered = 0;
+        let condition: Comparison = self.condition.parse()?;
+        let samples = match (self.scrape_url.as_ref(), self.query_url.as_ref()) {
+            (Some(scrape_url), _) => self.scrape(scrape_url).await?,
+            (None, Some(query_url)) => self.query(query_url).await?,
+            (None, None) => anyhow::bail!("either scrape_url or query_url is required"),
+        };
+
+        let mut found_incidents = 0;
+        let mut messages: Vec<String> = vec![];
+        let mut message_entries: Vec<(i8, MessageEntry)> = vec![];
+        if condition == Comparison::Absent {
+            if samples.is_empty() {
+                messages.push("no series returned".to_owned());
+                found_incidents += 1;
+            }
+        } else {
+            for sample in samples.iter() {
+                if condition.is_met(Some(&sample.value)) {
+                    let series = sample.series();
+                    messages.push(format!("{} {}", series, sample.value));
+                    if found_incidents < i8::MAX {
+                        message_entries.push((
+                            found_incidents, // index of this message entry
+                            MessageEntry {
+                                title: series,
+                                description: format!("value {} {}", sample.value, self.condition),
+                            },
+                        ));
+                        found_incidents += 1;
+                    }
+                }
+            }
+        }
+
+        if found_incidents > 0 {
+            log::info!(
+                "_TRIGGERED_: {} series from {} met condition {}",
+                messages.len(),
+                target,
+                self.condition
+            );
+            if stored.is_none() || stored == Some(IVec::from(NO_INCIDENT)) {
+                log::warn!(
+                    "_NOTIFY_: {} series from {} met condition {}",
+                    messages.len(),
+                    target,
+                    self.condition
+                );
+                self.notify(
+                    alerts,
+                    Notification {
+                        from: "prometheus".to_owned(),
+                        name: self.name("prometheus", self.name.to_owned()),
+                        check: format!(
+                            "prometheus {} {} from {}",
+                            self.metric
+                                .to_owned()
+                                .or_else(|| self.query.to_owned())
+                                .unwrap_or_default(),
+                            self.condition,
+                            target
+                        ),
+                        title: format!(
+                            "{} series met condition {}",
+                            messages.len(),
+                            self.condition
+                        ),
+                        message: messages.join("\n"),
+                        message_html: None,
+                        message_entries: if message_entries.is_empty() {
+                            None
+                        } else {
+                            Some(message_entries)
+                        },
+                    },
+                )
+                .await?;
+            }
+            TRIGGERED_TOTAL
+                .with_label_values(&["probe.prometheus", &target])
+                .inc();
+            triggered = 1;
+            to_store = HAS_INCIDENT;
+        }
+
+        store.insert(self.slug().as_bytes(), to_store)?;
+
+        TRIGGERED
+            .with_label_values(&["probe.prometheus", &target])
+            .set(triggered as f64);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::net::SocketAddr;
+    use warp::{http::StatusCode, Filter};
+
+    fn labels(sample: &Sample) -> Vec<(&str, &str)> {
+        sample
+            .labels
+            .iter()
+            .map(|(label, value)| (label.as_str(), value.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_sample() {
+        assert!(parse_sample("# TYPE up gauge").is_none());
+        assert!(parse_sample("   ").is_none());
+
+        let sample = parse_sample("up 1").unwrap();
+        assert_eq!((sample.name.as_str(), sample.value.as_str()), ("up", "1"));
+        assert!(sample.labels.is_empty());
+        let sample = parse_sample("up{} 0").unwrap();
+        assert_eq!((sample.name.as_str(), sample.value.as_str()), ("up", "0"));
+        assert!(sample.labels.is_empty());
+
+        let sample =
+            parse_sample(r#"http_requests_total{method="post",code="200"} 1027 1395066363000"#)
+                .unwrap();
+        assert_eq!(sample.value, "1027");
+        assert_eq!(labels(&sample), vec![("code", "200"), ("method", "post")]);
+        assert_eq!(
+            sample.series(),
+            r#"http_requests_total{code="200", method="post"}"#
+        );
+
+        let sample = parse_sample(
+            r#"msdos_file_access_time_seconds{path="C:\\DIR\\FILE.TXT",error="Cannot find file:\n\"FILE.TXT\""} 1.458255915e9"#,
+        )
+        .unwrap();
+        assert_eq!(sample.value, "1.458255915e9");
+        assert_eq!(
+            labels(&sample),
+            vec![
+                ("error", "Cannot find file:\n\"FILE.TXT\""),
+                ("path", r"C:\DIR\FILE.TXT"),
+            ]
+        );
+
+        let sample = parse_sample(r#"queue_size{name="a,b}c",} +Inf"#).unwrap();
+        assert_eq!(sample.value, "+Inf");
+        assert_eq!(labels(&sample), vec![("name", "a,b}c")]);
+
+        assert!(parse_sample(r#"broken{name="unterminated} 1"#).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_scrape() {
+        let metrics = warp::path("metrics").map(|| {
+            "# HELP jobs_queued Jobs waiting\n\
+             # TYPE jobs_queued gauge\n\
+             jobs_queued{queue=\"mail\"} 3\n\
+             jobs_queued{queue=\"export\"} 12\n"
+        });
+        let broken = warp::path("broken")
+            .map(|| warp::reply::with_status("internal error", StatusCode::INTERNAL_SERVER_ERROR));
+        let (addr, server) = warp::serve(metrics.or(broken))
+            .bind_ephemeral("127.0.0.1:0".parse::<SocketAddr>().unwrap());
+        tokio::spawn(server);
+
+        let mut want_labels = HashMap::new();
+        want_labels.insert("queue".to_owned(), "export".to_owned());
+        let probe = Prometheus {
+            metric: Some("jobs_queued".to_owned()),
+            labels: Some(want_labels),
+            ..Default::default()
+        };
+        let samples = probe
+            .scrape(&format!("http://{}/metrics", addr))
+            .await
+            .unwrap();
+        assert_eq!(samples.len(), 1);
+        assert_eq!(samples[0].value, "12");
+
+        let err = probe
+            .scrape(&format!("http://{}/broken", addr))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("500 Internal Server Error"));
+    }
+}
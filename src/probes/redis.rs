@@ -0,0 +1,430 @@
+use crate::{
+    alerts::Alert,
+    probes::{
+        password, redact_url, Comparison, MessageEntry, Notification, Probe, HAS_INCIDENT,
+        NO_INCIDENT,
+    },
+};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use lazy_static::lazy_static;
+use prometheus::{register_counter_vec, register_gauge_vec, CounterVec, GaugeVec};
+use redis::{aio::MultiplexedConnection, cmd, Client, ConnectionInfo};
+use serde_derive::Deserialize;
+use sled::{Db, IVec};
+use slug::slugify;
+use std::collections::HashMap;
+use tokio::time::{timeout, Duration};
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Redis {
+    name: Option<String>,
+    schedule: Option<String>,
+    depends_on: Option<Vec<String>>,
+    // redis:// or rediss:// for TLS
+    url: String,
+    password_file: Option<String>,
+    // INFO fields and conditions to alert on
+    info: Option<HashMap<String, String>>,
+    key: Option<String>,
+    key_condition: Option<String>,
+    key_length_condition: Option<String>,
+    // seconds to wait for connecting and running commands, default 10
+    timeout: Option<u64>,
+}
+
+lazy_static! {
+    static ref RUNS_TOTAL: CounterVec = register_counter_vec!(
+        "probe_redis_runs_total",
+        "run counter for Redis probe plugin",
+        &["plugin", "url"]
+    )
+    .unwrap();
+    static ref TRIGGERED_TOTAL: CounterVec = register_counter_vec!(
+        "probe_redis_triggered_total",
+        "triggered counter for Redis probe plugin",
+        &["plugin", "url"]
+    )
+    .unwrap();
+    static ref TRIGGERED: GaugeVec = register_gauge_vec!(
+        "probe_redis_triggered",
+        "Redis probe plugin triggered",
+        &["plugin", "url"]
+    )
+    .unwrap();
+    static ref INFO: GaugeVec = register_gauge_vec!(
+        "probe_redis_info",
+        "numeric INFO field from Redis probe plugin",
+        &["plugin", "url", "field"]
+    )
+    .unwrap();
+    static ref KEY_VALUE: GaugeVec = register_gauge_vec!(
+        "probe_redis_key_value",
+        "numeric value of a key from Redis probe plugin",
+        &["plugin", "url", "key"]
+    )
+    .unwrap();
+    static ref KEY_LENGTH: GaugeVec = register_gauge_vec!(
+        "probe_redis_key_length",
+        "length of a key from Redis probe plugin",
+        &["plugin", "url", "key"]
+    )
+    .unwrap();
+}
+
+// Fields of an INFO reply, which has "# Section" headers and "field:value" lines
+fn parse_info(raw: &str) -> HashMap<&str, &str> {
+    raw.lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| {
+            let mut parts = line.trim().splitn(2, ':');
+            Some((parts.next()?, parts.next()?))
+        })
+        .collect()
+}
+
+impl Redis {
+    async fn connect(&self) -> Result<MultiplexedConnection> {
+        let mut info: ConnectionInfo = self.url.parse().context("invalid url")?;
+        if self.password_file.is_some() {
+            info.redis.password = Some(password(&None, &self.password_file)?);
+        }
+        let client = Client::open(info)?;
+        client
+            .get_multiplexed_async_connection()
+            .await
+            .context("failed connecting")
+    }
+
+    // Run PING and evaluate configured conditions, returning one entry per violation
+    async fn check(&self, url: &str) -> Result<Vec<MessageEntry>> {
+        let mut conn = self.connect().await?;
+        let pong: String = cmd("PING")
+            .query_async(&mut conn)
+            .await
+            .context("PING failed")?;
+        if pong != "PONG" {
+            anyhow::bail!("PING replied {}", pong);
+        }
+
+        let mut violations = vec![];
+        let mut violated = |what: String, value: Option<&str>, condition: &str| {
+            violations.push(MessageEntry {
+                title: format!("{} {}", what, condition),
+                description: format!("{} is {}", what, value.unwrap_or("absent")),
+            });
+        };
+
+        if let Some(info) = self.info.as_ref() {
+            let raw: String = cmd("INFO").query_async(&mut conn).await?;
+            let fields = parse_info(&raw);
+            for (field, condition) in info.iter() {
+                let value = fields.get(field.as_str()).copied();
+                if let Some(number) = value.and_then(|value| value.parse::<f64>().ok()) {
+                    INFO.with_label_values(&["probe.redis", url, field])
+                        .set(number);
+                }
+                if condition.parse::<Comparison>()?.is_met(value) {
+                    violated(format!("INFO {}", field), value, condition);
+                }
+            }
+        }
+
+        if let Some(key) = self.key.as_ref() {
+            if let Some(condition) = self.key_condition.as_ref() {
+                let value: Option<String> = cmd("GET").arg(key).query_async(&mut conn).await?;
+                if let Some(number) = value.as_ref().and_then(|value| value.parse::<f64>().ok()) {
+                    KEY_VALUE
+                        .with_label_values(&["probe.redis", url, key])
+                        .set(number);
+                }
+                if condition.parse::<Comparison>()?.is_met(value.as_deref()) {
+                    violated(format!("value of {}", key), value.as_deref(), condition);
+                }
+            }
+            if let Some(condition) = self.key_length_condition.as_ref() {
+                let key_type: String = cmd("TYPE").arg(key).query_async(&mut conn).await?;
+                let length_cmd = match key_type.as_str() {
+                    "string" => Some("STRLEN"),
+                    "list" => Some("LLEN"),
+                    "set" => Some("SCARD"),
+                    "zset" => Some("ZCARD"),
+                    "hash" => Some("HLEN"),
+                    "stream" => Some("XLEN"),
+                    _ => None,
+                };
+                let length: Option<i64> = match length_cmd {
+                    Some(length_cmd) => {
+                        Some(cmd(length_cmd).arg(key).query_async(&mut conn).await?)
+                    }
+                    None => None,
+                };
+                if let Some(length) = length {
+                    KEY_LENGTH
+                        .with_label_values(&["probe.redis", url, key])
+                        .set(length as f64);
+                }
+                let length = length.map(|length| length.to_string());
+                if condition.parse::<Comparison>()?.is_met(length.as_deref()) {
+                    violated(format!("length of {}", key), length.as_deref(), condition);
+                }
+            }
+        }
+
+        Ok(violations)
+    }
+}
+
+#[async_trait]
+impl Probe for Redis {
+    fn new() -> Self {
+        Self {
+            ..Default::default()
+        }
+    }
+
+    fn local_schedule(&self) -> Option<String> {
+        self.schedule.to_owned()
+    }
+
+    fn local_name(&self) -> Option<String> {
+        self.name.to_owned()
+    }
+
+    fn depends_on(&self) -> Option<Vec<String>> {
+        self.depends_on.to_owned()
+    }
+
+    fn slug(&self) -> String {
+        slugify(format!(
+            "redis-{}-{}",
+            redact_url(&self.url),
+            self.key.to_owned().unwrap_or_default()
+        ))
+    }
+
+    async fn observe(
+        &self,
+        store: &Db,
+        alerts: &HashMap<String, Vec<Box<dyn Alert>>>,
+    ) -> Result<()> {
+        let url = redact_url(&self.url);
+        log::info!("checking redis {}", url);
+        RUNS_TOTAL.with_label_values(&["probe.redis", &url]).inc();
+
+        let stored = store.get(self.slug().as_bytes())?;
+        let mut to_store = NO_INCIDENT;
+        let mut triggered = 0;
+        let seconds = self.timeout.unwrap_or(10);
+        let result = match timeout(Duration::from_secs(seconds), self.check(&url)).await {
+            Ok(result) => result,
+            Err(_) => Err(anyhow::anyhow!("timed out after {} seconds", seconds)),
+        };
+
+        let (title, message, message_entries) = match result {
+            Ok(violations) if violations.is_empty() => ("".to_owned(), "".to_owned(), vec![]),
+            Ok(violations) => (
+                format!("{} redis condition(s) met on {}", violations.len(), url),
+                violations
+                    .iter()
+                    .map(|entry| entry.description.clone())
+                    .collect::<Vec<String>>()
+                    .join("\n"),
+                violations,
+            ),
+            Err(err) => (
+                format!("redis {} is unavailable", url),
+                format!("{:#}", err),
+                vec![],
+            ),
+        };
+
+        if !message.is_empty() {
+            log::info!("_TRIGGERED_: redis {}: {}", url, message);
+            if stored.is_none() || stored == Some(IVec::from(NO_INCIDENT)) {
+                log::warn!("_NOTIFY_: redis {}: {}", url, message);
+                self.notify(
+                    alerts,
+                    Notification {
+                        from: "redis".to_owned(),
+                        name: self.name("redis", self.name.to_owned()),
+                        check: format!("redis {}", url),
+                        title,
+                        message,
+                        message_html: None,
+                        message_entries: if message_entries.is_empty() {
+                            None
+                        } else {
+                            Some(
+                                message_entries
+                                    .into_iter()
+                                    .enumerate()
+                                    .map(|(i, entry)| (i as i8, entry))
+                                    .collect(),
+                            )
+                        },
+                    },
+                )
+                .await?;
+            }
+            TRIGGERED_TOTAL
+                .with_label_values(&["probe.redis", &url])
+                .inc();
+            triggered = 1;
+            to_store = HAS_INCIDENT;
+        }
+
+        store.insert(self.slug().as_bytes(), to_store)?;
+
+        TRIGGERED
+            .with_label_values(&["probe.redis", &url])
+            .set(triggered as f64);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+        net::{TcpListener, TcpStream},
+    };
+
+    const INFO_REPLY: &str = "# Server\r\n\
+        redis_version:7.2.4\r\n\
+        \r\n\
+        # Memory\r\n\
+        used_memory:1048576\r\n\
+        maxmemory:4194304\r\n\
+        \r\n\
+        # Replication\r\n\
+        role:master\r\n\
+        connected_slaves:1\r\n\
+        slave0:ip=10.0.0.2,port=6379,state=online,offset=42,lag=0\r\n";
+
+    #[test]
+    fn test_parse_info() {
+        let fields = parse_info(INFO_REPLY);
+        assert_eq!(fields.get("role"), Some(&"master"));
+        assert_eq!(fields.get("connected_slaves"), Some(&"1"));
+        assert_eq!(fields.get("used_memory"), Some(&"1048576"));
+        // values keep everything after the first colon
+        assert_eq!(
+            fields.get("slave0"),
+            Some(&"ip=10.0.0.2,port=6379,state=online,offset=42,lag=0")
+        );
+        assert!(fields
+            .keys()
+            .all(|field| !field.is_empty() && !field.starts_with('#')));
+        assert_eq!(fields.len(), 6);
+    }
+
+    // One command of RESP arrays of bulk strings
+    async fn read_command(reader: &mut BufReader<TcpStream>) -> Option<Vec<String>> {
+        let mut line = String::new();
+        reader.read_line(&mut line).await.ok()?;
+        let count: usize = line.trim().strip_prefix('*')?.parse().ok()?;
+        let mut args = vec![];
+        for _ in 0..count {
+            line.clear();
+            reader.read_line(&mut line).await.ok()?;
+            let len: usize = line.trim().strip_prefix('$')?.parse().ok()?;
+            let mut arg = vec![0u8; len + 2];
+            reader.read_exact(&mut arg).await.ok()?;
+            args.push(String::from_utf8_lossy(&arg[..len]).to_string());
+        }
+        Some(args)
+    }
+
+    fn bulk(value: &str) -> String {
+        format!("${}\r\n{}\r\n", value.len(), value)
+    }
+
+    // Stand-in for a Redis server requiring the password "secret", with a list "jobs" of
+    // 3 items and a string "heartbeat"
+    async fn session(stream: TcpStream) -> Option<()> {
+        let mut reader = BufReader::new(stream);
+        let mut authenticated = false;
+        loop {
+            let args = read_command(&mut reader).await?;
+            let args: Vec<&str> = args.iter().map(String::as_str).collect();
+            let reply = match args.as_slice() {
+                ["AUTH", .., "secret"] => {
+                    authenticated = true;
+                    "+OK\r\n".to_owned()
+                }
+                ["AUTH", ..] => "-WRONGPASS invalid username-password pair\r\n".to_owned(),
+                _ if !authenticated => "-NOAUTH Authentication required.\r\n".to_owned(),
+                ["PING"] => "+PONG\r\n".to_owned(),
+                ["INFO"] => bulk(INFO_REPLY),
+                ["GET", "heartbeat"] => bulk("1760832000"),
+                ["GET", _] => "$-1\r\n".to_owned(),
+                ["TYPE", "jobs"] => "+list\r\n".to_owned(),
+                ["TYPE", "heartbeat"] => "+string\r\n".to_owned(),
+                ["TYPE", _] => "+none\r\n".to_owned(),
+                ["LLEN", "jobs"] => ":3\r\n".to_owned(),
+                ["STRLEN", "heartbeat"] => ":10\r\n".to_owned(),
+                _ => "-ERR unknown command\r\n".to_owned(),
+            };
+            reader.get_mut().write_all(reply.as_bytes()).await.ok()?;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_check_against_stand_in() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("redis://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                tokio::spawn(session(stream));
+            }
+        });
+        let password_file = std::env::temp_dir().join(format!("otto-redis-{}", std::process::id()));
+        std::fs::write(&password_file, "secret\n").unwrap();
+
+        let mut info = HashMap::new();
+        info.insert("role".to_owned(), "== master".to_owned());
+        info.insert("connected_slaves".to_owned(), "< 1".to_owned());
+        let probe = Redis {
+            url: url.clone(),
+            password_file: Some(password_file.to_string_lossy().to_string()),
+            info: Some(info),
+            key: Some("jobs".to_owned()),
+            key_length_condition: Some("> 2".to_owned()),
+            ..Default::default()
+        };
+        let violations = probe.check(&url).await.unwrap();
+        let titles: Vec<&str> = violations.iter().map(|v| v.title.as_str()).collect();
+        assert_eq!(titles, vec!["INFO role == master", "length of jobs > 2"]);
+        assert_eq!(violations[1].description, "length of jobs is 3");
+
+        let missing = Redis {
+            key: Some("missing".to_owned()),
+            key_condition: Some("absent".to_owned()),
+            key_length_condition: Some("> 0".to_owned()),
+            info: None,
+            ..probe.clone()
+        };
+        let violations = missing.check(&url).await.unwrap();
+        let titles: Vec<&str> = violations.iter().map(|v| v.title.as_str()).collect();
+        assert_eq!(titles, vec!["value of missing absent"]);
+
+        let string = Redis {
+            key: Some("heartbeat".to_owned()),
+            key_condition: Some("< 1760000000".to_owned()),
+            key_length_condition: Some("!= 10".to_owned()),
+            ..missing
+        };
+        assert!(string.check(&url).await.unwrap().is_empty());
+
+        let no_password = Redis {
+            password_file: None,
+            ..probe
+        };
+        let err = no_password.check(&url).await.unwrap_err();
+        assert!(format!("{:#}", err).contains("NOAUTH"));
+        std::fs::remove_file(&password_file).unwrap();
+    }
+}
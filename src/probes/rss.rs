@@ -1,6 +1,6 @@
 use crate::{
     alerts::Alert,
-    probes::{MessageEntry, Notification, Probe, HAS_INCIDENT, NO_INCIDENT},
+    probes::{fetch_feed, Fetched, MessageEntry, Notification, Probe, HAS_INCIDENT, NO_INCIDENT},
 };
 use anyhow::{Context, Result};
 use async_trait::async_trait;
@@ -17,6 +17,7 @@ use std::collections::HashMap;
 pub struct Rss {
     name: Option<String>,
     schedule: Option<String>,
+    depends_on: Option<Vec<String>>,
     feed_url: String,
     title_regex: Option<String>,
     description_regex: Option<String>,
@@ -35,6 +36,12 @@ lazy_static! {
         &["plugin", "feed_url"]
     )
     .unwrap();
+    static ref CACHE_HITS_TOTAL: CounterVec = register_counter_vec!(
+        "probe_rss_cache_hits_total",
+        "cache hit counter for RSS probe plugin",
+        &["plugin", "feed_url"]
+    )
+    .unwrap();
     static ref TRIGGERED: GaugeVec = register_gauge_vec!(
         "probe_rss_triggered",
         "RSS probe plugin triggered",
@@ -55,6 +62,14 @@ impl Probe for Rss {
         self.schedule.to_owned()
     }
 
+    fn local_name(&self) -> Option<String> {
+        self.name.to_owned()
+    }
+
+    fn depends_on(&self) -> Option<Vec<String>> {
+        self.depends_on.to_owned()
+    }
+
     fn slug(&self) -> String {
         slugify(format!("rss-{}", self.feed_url))
     }
@@ -72,8 +87,30 @@ impl Probe for Rss {
         let stored = store.get(self.slug().as_bytes())?;
         let mut to_store = NO_INCIDENT;
         let mut triggered = 0;
-        let content = reqwest::get(&self.feed_url).await?.bytes().await?;
+        let (content, mut cache) = match fetch_feed(store, &self.slug(), &self.feed_url).await? {
+            Fetched::Modified(content, cache) => (content, cache),
+            _ => {
+                log::info!(
+                    "RSS feed {} not modified, reusing previous result",
+                    self.feed_url
+                );
+                CACHE_HITS_TOTAL
+                    .with_label_values(&["probe.rss", &self.feed_url])
+                    .inc();
+                if stored == Some(IVec::from(HAS_INCIDENT)) {
+                    triggered = 1;
+                }
+                TRIGGERED
+                    .with_label_values(&["probe.rss", &self.feed_url])
+                    .set(triggered as f64);
+                return Ok(());
+            }
+        };
         let feed = Channel::read_from(&content[..])?;
+        // ttl is the number of minutes the channel can be cached before refreshing
+        if let Some(ttl) = feed.ttl.as_ref().and_then(|ttl| ttl.parse::<i64>().ok()) {
+            cache.keep_fresh_for(ttl * 60);
+        }
 
         let mut found_incidents = 0;
         let mut messages: Vec<String> = vec![];
@@ -150,6 +187,7 @@ impl Probe for Rss {
         }
 
         store.insert(self.slug().as_bytes(), to_store)?;
+        cache.save(store, &self.slug())?;
 
         TRIGGERED
             .with_label_values(&["probe.rss", &self.feed_url])
@@ -157,3 +195,53 @@ impl Probe for Rss {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::probes::testing;
+    use std::{
+        net::SocketAddr,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+    };
+    use warp::Filter;
+
+    #[tokio::test]
+    async fn test_ttl_skips_fetches() {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counted = requests.clone();
+        let route = warp::path("feed.xml").map(move || {
+            counted.fetch_add(1, Ordering::SeqCst);
+            let items: String = (1..=5)
+                .map(|i| format!("<item><title>Incident {}</title></item>", i))
+                .collect();
+            format!(
+                "<rss version=\"2.0\"><channel><title>Status</title><ttl>10</ttl>{}</channel></rss>",
+                items
+            )
+        });
+        let (addr, server) =
+            warp::serve(route).bind_ephemeral("127.0.0.1:0".parse::<SocketAddr>().unwrap());
+        tokio::spawn(server);
+
+        let probe = Rss {
+            feed_url: format!("http://{}/feed.xml", addr),
+            title_regex: Some("Incident 5".to_owned()),
+            ..Default::default()
+        };
+        let (store, alerts, sent) = testing::setup();
+        probe.observe(&store, &alerts).await.unwrap();
+        assert_eq!(testing::drain(&sent).len(), 1);
+        // fresh for the 10 minutes of <ttl>, the previous result stands without a request
+        probe.observe(&store, &alerts).await.unwrap();
+        assert!(testing::drain(&sent).is_empty());
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+        assert_eq!(
+            store.get(probe.slug().as_bytes()).unwrap(),
+            Some(IVec::from(HAS_INCIDENT))
+        );
+    }
+}
@@ -0,0 +1,385 @@
+use crate::{
+    alerts::Alert,
+    probes::{Notification, Probe, HAS_INCIDENT, NO_INCIDENT},
+};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use lazy_static::lazy_static;
+use prometheus::{register_counter_vec, register_gauge_vec, CounterVec, GaugeVec};
+use s3::{creds::Credentials, Bucket, Region};
+use serde_derive::Deserialize;
+use sled::{Db, IVec};
+use slug::slugify;
+use std::collections::HashMap;
+use tokio::time::{timeout, Duration, Instant};
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct S3 {
+    name: Option<String>,
+    schedule: Option<String>,
+    depends_on: Option<Vec<String>>,
+    bucket: String,
+    // S3-compatible endpoint like "http://minio:9000", default AWS
+    endpoint: Option<String>,
+    // default "us-east-1"
+    region: Option<String>,
+    // address the bucket in the path instead of the host name, default true with an endpoint
+    path_style: Option<bool>,
+    // default AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY, or the default profile
+    access_key: Option<String>,
+    secret_key: Option<String>,
+    // objects under the prefix are listed, default the whole bucket
+    prefix: Option<String>,
+    // seconds since the newest object under the prefix was modified, S3 can't
+    // list newest first so every key under the prefix is listed, keep it narrow
+    max_age: Option<u64>,
+    // put, get and delete an object at this key
+    round_trip_key: Option<String>,
+    // seconds to wait for all requests, default 10, or 60 with max_age
+    timeout: Option<u64>,
+}
+
+lazy_static! {
+    static ref RUNS_TOTAL: CounterVec = register_counter_vec!(
+        "probe_s3_runs_total",
+        "run counter for S3 probe plugin",
+        &["plugin", "bucket"]
+    )
+    .unwrap();
+    static ref TRIGGERED_TOTAL: CounterVec = register_counter_vec!(
+        "probe_s3_triggered_total",
+        "triggered counter for S3 probe plugin",
+        &["plugin", "bucket"]
+    )
+    .unwrap();
+    static ref TRIGGERED: GaugeVec = register_gauge_vec!(
+        "probe_s3_triggered",
+        "S3 probe plugin triggered",
+        &["plugin", "bucket"]
+    )
+    .unwrap();
+    static ref NEWEST_AGE_SECONDS: GaugeVec = register_gauge_vec!(
+        "probe_s3_newest_age_seconds",
+        "seconds since the newest object under the prefix was modified",
+        &["plugin", "bucket"]
+    )
+    .unwrap();
+    static ref ROUND_TRIP_SECONDS: GaugeVec = register_gauge_vec!(
+        "probe_s3_round_trip_seconds",
+        "time to put, get and delete the round trip object",
+        &["plugin", "bucket"]
+    )
+    .unwrap();
+}
+
+impl S3 {
+    fn bucket(&self) -> Result<Box<Bucket>> {
+        let region_name = self
+            .region
+            .to_owned()
+            .unwrap_or_else(|| "us-east-1".to_owned());
+        let region = match self.endpoint.to_owned() {
+            Some(endpoint) => Region::Custom {
+                region: region_name,
+                endpoint,
+            },
+            None => region_name
+                .parse()
+                .with_context(|| format!("invalid region {}", region_name))?,
+        };
+        let credentials = Credentials::new(
+            self.access_key.as_deref(),
+            self.secret_key.as_deref(),
+            None,
+            None,
+            None,
+        )
+        .context("no credentials found")?;
+        let bucket = Bucket::new(&self.bucket, region, credentials)?;
+        match self.path_style.unwrap_or_else(|| self.endpoint.is_some()) {
+            true => Ok(bucket.with_path_style()),
+            false => Ok(bucket),
+        }
+    }
+
+    async fn check(&self) -> Result<()> {
+        let bucket = self.bucket()?;
+        let prefix = self.prefix.to_owned().unwrap_or_default();
+
+        match self.max_age {
+            // a single key is enough to tell the bucket is reachable
+            None => {
+                bucket
+                    .list_page(prefix, None, None, None, Some(1))
+                    .await
+                    .with_context(|| format!("failed listing bucket {}", self.bucket))?;
+            }
+            Some(max_age) => {
+                let pages = bucket
+                    .list(prefix.to_owned(), None)
+                    .await
+                    .with_context(|| format!("failed listing bucket {}", self.bucket))?;
+                let newest = pages
+                    .iter()
+                    .flat_map(|page| page.contents.iter())
+                    .filter_map(|object| {
+                        Some((
+                            &object.key,
+                            DateTime::parse_from_rfc3339(&object.last_modified).ok()?,
+                        ))
+                    })
+                    .max_by_key(|(_, modified)| *modified);
+                let (key, modified) = newest.ok_or_else(|| {
+                    anyhow::anyhow!("no object under {:?} in bucket {}", prefix, self.bucket)
+                })?;
+                let age = (Utc::now() - modified.with_timezone(&Utc))
+                    .num_seconds()
+                    .max(0) as u64;
+                NEWEST_AGE_SECONDS
+                    .with_label_values(&["probe.s3", &self.bucket])
+                    .set(age as f64);
+                if age > max_age {
+                    anyhow::bail!(
+                        "newest object {} was modified {} seconds ago, more than {} seconds",
+                        key,
+                        age,
+                        max_age
+                    );
+                }
+            }
+        }
+
+        if let Some(key) = self.round_trip_key.as_ref() {
+            let started = Instant::now();
+            let content = format!("otto round trip at {}", Utc::now().to_rfc3339());
+            bucket
+                .put_object(key, content.as_bytes())
+                .await
+                .with_context(|| format!("failed putting {}", key))?;
+            let got = bucket
+                .get_object(key)
+                .await
+                .with_context(|| format!("failed getting {}", key))?;
+            if got.as_slice() != content.as_bytes() {
+                anyhow::bail!("object {} read back differs from what was put", key);
+            }
+            bucket
+                .delete_object(key)
+                .await
+                .with_context(|| format!("failed deleting {}", key))?;
+            ROUND_TRIP_SECONDS
+                .with_label_values(&["probe.s3", &self.bucket])
+                .set(started.elapsed().as_secs_f64());
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Probe for S3 {
+    fn new() -> Self {
+        Self {
+            ..Default::default()
+        }
+    }
+
+    fn local_schedule(&self) -> Option<String> {
+        self.schedule.to_owned()
+    }
+
+    fn local_name(&self) -> Option<String> {
+        self.name.to_owned()
+    }
+
+    fn depends_on(&self) -> Option<Vec<String>> {
+        self.depends_on.to_owned()
+    }
+
+    fn slug(&self) -> String {
+        slugify(format!(
+            "s3-{}-{}-{}",
+            self.endpoint.to_owned().unwrap_or_default(),
+            self.bucket,
+            self.prefix.to_owned().unwrap_or_default()
+        ))
+    }
+
+    async fn observe(
+        &self,
+        store: &Db,
+        alerts: &HashMap<String, Vec<Box<dyn Alert>>>,
+    ) -> Result<()> {
+        log::info!("checking s3 bucket {}", self.bucket);
+        RUNS_TOTAL
+            .with_label_values(&["probe.s3", &self.bucket])
+            .inc();
+
+        let stored = store.get(self.slug().as_bytes())?;
+        let mut to_store = NO_INCIDENT;
+        let mut triggered = 0;
+        let seconds = self
+            .timeout
+            .unwrap_or(if self.max_age.is_some() { 60 } else { 10 });
+        let result = match timeout(Duration::from_secs(seconds), self.check()).await {
+            Ok(result) => result,
+            Err(_) => Err(anyhow::anyhow!("timed out after {} seconds", seconds)),
+        };
+
+        if let Err(err) = result {
+            let message = format!("{:#}", err);
+            log::info!("_TRIGGERED_: s3 bucket {}: {}", self.bucket, message);
+            if stored.is_none() || stored == Some(IVec::from(NO_INCIDENT)) {
+                log::warn!("_NOTIFY_: s3 bucket {}: {}", self.bucket, message);
+                self.notify(
+                    alerts,
+                    Notification {
+                        from: "s3".to_owned(),
+                        name: self.name("s3", self.name.to_owned()),
+                        check: match self.endpoint.as_ref() {
+                            Some(endpoint) => format!("s3 bucket {} on {}", self.bucket, endpoint),
+                            None => format!("s3 bucket {}", self.bucket),
+                        },
+                        title: format!("s3 bucket {} check failed", self.bucket),
+                        message,
+                        message_html: None,
+                        message_entries: None,
+                    },
+                )
+                .await?;
+            }
+            TRIGGERED_TOTAL
+                .with_label_values(&["probe.s3", &self.bucket])
+                .inc();
+            triggered = 1;
+            to_store = HAS_INCIDENT;
+        }
+
+        store.insert(self.slug().as_bytes(), to_store)?;
+
+        TRIGGERED
+            .with_label_values(&["probe.s3", &self.bucket])
+            .set(triggered as f64);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::{
+        net::SocketAddr,
+        sync::{Arc, Mutex},
+    };
+    use warp::hyper::body::Bytes;
+    use warp::{http::StatusCode, Filter};
+
+    // In-memory stand-in for the parts of the S3 API the probe uses, signatures are not verified
+    fn fake_s3(
+        objects: Arc<Mutex<HashMap<String, Bytes>>>,
+    ) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+        let list = warp::get()
+            .and(warp::path!("backups"))
+            .and(warp::query::<HashMap<String, String>>())
+            .map(|query: HashMap<String, String>| {
+                assert_eq!(query.get("list-type").map(String::as_str), Some("2"));
+                let prefix = query.get("prefix").cloned().unwrap_or_default();
+                let contents: String = [
+                    ("db/2026-10-17.sql.gz", "2026-10-17T02:00:00.000Z"),
+                    ("db/2026-10-18.sql.gz", "2026-10-18T02:00:00.000Z"),
+                    ("logs/2026-10-18.tar", "2026-10-19T01:00:00.000Z"),
+                ]
+                .iter()
+                .filter(|(key, _)| key.starts_with(&prefix))
+                .map(|(key, modified)| {
+                    format!(
+                        "<Contents><Key>{}</Key><LastModified>{}</LastModified>\
+                        <Size>1</Size><StorageClass>STANDARD</StorageClass></Contents>",
+                        key, modified
+                    )
+                })
+                .collect();
+                warp::reply::with_header(
+                    format!(
+                        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\
+                        <ListBucketResult xmlns=\"http://s3.amazonaws.com/doc/2006-03-01/\">\
+                        <Name>backups</Name><Prefix>{}</Prefix><MaxKeys>1000</MaxKeys>\
+                        <IsTruncated>false</IsTruncated>{}</ListBucketResult>",
+                        prefix, contents
+                    ),
+                    "content-type",
+                    "application/xml",
+                )
+            });
+        let put = {
+            let objects = objects.clone();
+            warp::put()
+                .and(warp::path!("backups" / String))
+                .and(warp::body::bytes())
+                .map(move |key: String, body: Bytes| {
+                    objects.lock().unwrap().insert(key, body);
+                    StatusCode::OK
+                })
+        };
+        let get = {
+            let objects = objects.clone();
+            warp::get()
+                .and(warp::path!("backups" / String))
+                .map(move |key: String| match objects.lock().unwrap().get(&key) {
+                    Some(body) => warp::reply::with_status(body.to_vec(), StatusCode::OK),
+                    None => warp::reply::with_status(vec![], StatusCode::NOT_FOUND),
+                })
+        };
+        let delete = warp::delete()
+            .and(warp::path!("backups" / String))
+            .map(move |key: String| {
+                objects.lock().unwrap().remove(&key);
+                StatusCode::NO_CONTENT
+            });
+        list.or(put).or(get).or(delete)
+    }
+
+    #[tokio::test]
+    async fn test_check_against_fake_s3() {
+        let objects = Arc::new(Mutex::new(HashMap::new()));
+        let (addr, server) = warp::serve(fake_s3(objects.clone()))
+            .bind_ephemeral("127.0.0.1:0".parse::<SocketAddr>().unwrap());
+        tokio::spawn(server);
+
+        let probe = S3 {
+            bucket: "backups".to_owned(),
+            endpoint: Some(format!("http://{}", addr)),
+            access_key: Some("minioadmin".to_owned()),
+            secret_key: Some("minioadmin".to_owned()),
+            round_trip_key: Some("otto-probe".to_owned()),
+            ..Default::default()
+        };
+        probe.check().await.unwrap();
+        assert!(objects.lock().unwrap().is_empty());
+
+        let newest = DateTime::parse_from_rfc3339("2026-10-18T02:00:00Z").unwrap();
+        let age = (Utc::now() - newest.with_timezone(&Utc)).num_seconds() as u64;
+        let fresh = S3 {
+            prefix: Some("db/".to_owned()),
+            max_age: Some(age + 3600),
+            ..probe.clone()
+        };
+        fresh.check().await.unwrap();
+
+        let stale = S3 {
+            max_age: Some(age - 3600),
+            ..fresh.clone()
+        };
+        assert!(format!("{:#}", stale.check().await.unwrap_err())
+            .starts_with("newest object db/2026-10-18.sql.gz was modified"));
+
+        let empty = S3 {
+            prefix: Some("missing/".to_owned()),
+            ..fresh
+        };
+        assert_eq!(
+            format!("{:#}", empty.check().await.unwrap_err()),
+            "no object under \"missing/\" in bucket backups"
+        );
+    }
+}
@@ -0,0 +1,519 @@
+use crate::{
+    alerts::Alert,
+    probes::{
+        imap::Session, mail::Connection, password, Notification, Probe, HAS_INCIDENT, NO_INCIDENT,
+    },
+};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::Utc;
+use fancy_regex::Regex;
+use lazy_static::lazy_static;
+use lettre::{
+    message::Mailbox, transport::smtp::authentication::Credentials, Message, SmtpTransport,
+    Transport,
+};
+use prometheus::{register_counter_vec, register_gauge_vec, CounterVec, GaugeVec};
+use serde_derive::Deserialize;
+use sled::{Db, IVec};
+use slug::slugify;
+use std::collections::HashMap;
+use tokio::time::{sleep, timeout, Duration, Instant};
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Smtp {
+    name: Option<String>,
+    schedule: Option<String>,
+    depends_on: Option<Vec<String>>,
+    host: String,
+    // default 465 with tls, otherwise 25
+    port: Option<u16>,
+    // implicit TLS
+    tls: Option<bool>,
+    starttls: Option<bool>,
+    banner_regex: Option<String>,
+    // authenticate with AUTH PLAIN when username is set
+    username: Option<String>,
+    password: Option<String>,
+    password_file: Option<String>,
+    // seconds to wait for the connection check, default 10
+    timeout: Option<u64>,
+    round_trip: Option<RoundTrip>,
+}
+
+// Send a message through the smtp server and wait for it to show up over IMAP
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct RoundTrip {
+    from: String,
+    to: String,
+    imap_host: String,
+    // default 993 with imap_tls, otherwise 143
+    imap_port: Option<u16>,
+    imap_tls: Option<bool>,
+    imap_starttls: Option<bool>,
+    imap_username: String,
+    imap_password: Option<String>,
+    imap_password_file: Option<String>,
+    // default INBOX
+    mailbox: Option<String>,
+    // seconds to wait for delivery, default 120
+    timeout: Option<u64>,
+}
+
+lazy_static! {
+    static ref RUNS_TOTAL: CounterVec = register_counter_vec!(
+        "probe_smtp_runs_total",
+        "run counter for SMTP probe plugin",
+        &["plugin", "host"]
+    )
+    .unwrap();
+    static ref TRIGGERED_TOTAL: CounterVec = register_counter_vec!(
+        "probe_smtp_triggered_total",
+        "triggered counter for SMTP probe plugin",
+        &["plugin", "host"]
+    )
+    .unwrap();
+    static ref TRIGGERED: GaugeVec = register_gauge_vec!(
+        "probe_smtp_triggered",
+        "SMTP probe plugin triggered",
+        &["plugin", "host"]
+    )
+    .unwrap();
+    static ref RESPONSE_SECONDS: GaugeVec = register_gauge_vec!(
+        "probe_smtp_response_seconds",
+        "time taken by the SMTP connection check",
+        &["plugin", "host"]
+    )
+    .unwrap();
+    static ref DELIVERY_SECONDS: GaugeVec = register_gauge_vec!(
+        "probe_smtp_delivery_seconds",
+        "end to end delivery time of the SMTP round trip",
+        &["plugin", "host"]
+    )
+    .unwrap();
+}
+
+// Read a possibly multiline reply, returning its code and text lines
+async fn reply(conn: &mut Connection) -> Result<(u16, Vec<String>)> {
+    let mut lines = vec![];
+    loop {
+        let line = conn.read_line().await?;
+        let code = line
+            .get(..3)
+            .and_then(|code| code.parse::<u16>().ok())
+            .with_context(|| format!("unexpected reply: {}", line))?;
+        lines.push(line.get(4..).unwrap_or_default().to_owned());
+        if line.chars().nth(3) != Some('-') {
+            return Ok((code, lines));
+        }
+    }
+}
+
+// Send a command and expect the given reply code, naming only the verb on failure
+async fn command(conn: &mut Connection, command: &str, expect: u16) -> Result<Vec<String>> {
+    conn.send(command).await?;
+    let (code, lines) = reply(conn).await?;
+    if code != expect {
+        let verb = command.split_whitespace().next().unwrap_or_default();
+        anyhow::bail!("{} failed: {} {}", verb, code, lines.join(" "));
+    }
+    Ok(lines)
+}
+
+impl Smtp {
+    fn port(&self) -> u16 {
+        self.port.unwrap_or(if self.tls.unwrap_or_default() {
+            465
+        } else {
+            25
+        })
+    }
+
+    async fn check(&self) -> Result<()> {
+        let mut conn =
+            Connection::open(&self.host, self.port(), self.tls.unwrap_or_default()).await?;
+        let (code, greeting) = reply(&mut conn).await?;
+        let greeting = greeting.join(" ");
+        if code != 220 {
+            anyhow::bail!("unexpected greeting: {} {}", code, greeting);
+        }
+        if let Some(banner_regex) = self.banner_regex.as_ref() {
+            let re = Regex::new(banner_regex)
+                .with_context(|| format!("failed parsing banner_regex {}", banner_regex))?;
+            if !re.is_match(&greeting)? {
+                anyhow::bail!("greeting {} does not match {}", greeting, banner_regex);
+            }
+        }
+        let mut capabilities = command(&mut conn, "EHLO otto", 250).await?;
+        if self.starttls.unwrap_or_default() {
+            if !capabilities
+                .iter()
+                .any(|capability| capability.eq_ignore_ascii_case("STARTTLS"))
+            {
+                anyhow::bail!("server does not offer STARTTLS");
+            }
+            command(&mut conn, "STARTTLS", 220).await?;
+            conn = conn.upgrade().await?;
+            capabilities = command(&mut conn, "EHLO otto", 250).await?;
+        }
+        if let Some(username) = self.username.as_ref() {
+            log::debug!("smtp {} capabilities: {:?}", self.host, capabilities);
+            let password = password(&self.password, &self.password_file)?;
+            let plain = base64::encode(format!("\0{}\0{}", username, password));
+            command(&mut conn, &format!("AUTH PLAIN {}", plain), 235).await?;
+        }
+        command(&mut conn, "QUIT", 221).await?;
+        Ok(())
+    }
+
+    fn send(&self, round_trip: &RoundTrip, subject: &str) -> Result<()> {
+        let from: Mailbox = round_trip
+            .from
+            .parse()
+            .with_context(|| format!("failed parsing from address {}", round_trip.from))?;
+        let to: Mailbox = round_trip
+            .to
+            .parse()
+            .with_context(|| format!("failed parsing to address {}", round_trip.to))?;
+        let email = Message::builder().from(from).to(to).subject(subject).body(
+            "Round trip message sent by otto, it will be deleted once received.".to_owned(),
+        )?;
+        let mut builder = if self.tls.unwrap_or_default() {
+            SmtpTransport::relay(&self.host)?
+        } else if self.starttls.unwrap_or_default() {
+            SmtpTransport::starttls_relay(&self.host)?
+        } else {
+            SmtpTransport::builder_dangerous(&self.host)
+        };
+        builder = builder.port(self.port());
+        if let Some(username) = self.username.as_ref() {
+            let password = password(&self.password, &self.password_file)?;
+            builder = builder.credentials(Credentials::new(username.to_owned(), password));
+        }
+        builder
+            .build()
+            .send(&email)
+            .context("failed sending message")?;
+        Ok(())
+    }
+
+    // Send a uniquely tagged message and poll IMAP until it arrives, then delete it
+    async fn round_trip(&self, round_trip: &RoundTrip) -> Result<Duration> {
+        let prefix = format!("otto round trip {}", self.slug());
+        let subject = format!("{} {}", prefix, Utc::now().timestamp_millis());
+        let started = Instant::now();
+        let smtp = self.clone();
+        let message = round_trip.clone();
+        let sending = subject.clone();
+        tokio::task::spawn_blocking(move || smtp.send(&message, &sending)).await??;
+
+        let imap_tls = round_trip.imap_tls.unwrap_or_default();
+        let port = round_trip
+            .imap_port
+            .unwrap_or(if imap_tls { 993 } else { 143 });
+        let password = password(&round_trip.imap_password, &round_trip.imap_password_file)?;
+        let mailbox = round_trip
+            .mailbox
+            .to_owned()
+            .unwrap_or_else(|| "INBOX".to_owned());
+        loop {
+            let mut session = Session::open(
+                &round_trip.imap_host,
+                port,
+                imap_tls,
+                round_trip.imap_starttls.unwrap_or_default(),
+            )
+            .await?;
+            session.login(&round_trip.imap_username, &password).await?;
+            session.command(&format!("SELECT \"{}\"", mailbox)).await?;
+            let delivered = !session.search_subject(&subject).await?.is_empty();
+            if delivered {
+                let elapsed = started.elapsed();
+                // also clean up messages left behind by earlier round trips that timed out
+                let ids = session.search_subject(&prefix).await?;
+                session.delete(&ids).await?;
+                session.logout().await?;
+                return Ok(elapsed);
+            }
+            session.logout().await?;
+            sleep(Duration::from_secs(2)).await;
+        }
+    }
+}
+
+#[async_trait]
+impl Probe for Smtp {
+    fn new() -> Self {
+        Self {
+            ..Default::default()
+        }
+    }
+
+    fn local_schedule(&self) -> Option<String> {
+        self.schedule.to_owned()
+    }
+
+    fn local_name(&self) -> Option<String> {
+        self.name.to_owned()
+    }
+
+    fn depends_on(&self) -> Option<Vec<String>> {
+        self.depends_on.to_owned()
+    }
+
+    fn slug(&self) -> String {
+        slugify(format!(
+            "smtp-{}-{}-{}",
+            self.host,
+            self.port(),
+            self.username.to_owned().unwrap_or_default()
+        ))
+    }
+
+    async fn observe(
+        &self,
+        store: &Db,
+        alerts: &HashMap<String, Vec<Box<dyn Alert>>>,
+    ) -> Result<()> {
+        let host = format!("{}:{}", self.host, self.port());
+        log::info!("checking smtp {}", host);
+        RUNS_TOTAL.with_label_values(&["probe.smtp", &host]).inc();
+
+        let stored = store.get(self.slug().as_bytes())?;
+        let mut to_store = NO_INCIDENT;
+        let mut triggered = 0;
+        let seconds = self.timeout.unwrap_or(10);
+        let started = Instant::now();
+        let mut result = match timeout(Duration::from_secs(seconds), self.check()).await {
+            Ok(result) => result,
+            Err(_) => Err(anyhow::anyhow!("timed out after {} seconds", seconds)),
+        };
+        RESPONSE_SECONDS
+            .with_label_values(&["probe.smtp", &host])
+            .set(started.elapsed().as_secs_f64());
+
+        if let (Ok(_), Some(round_trip)) = (result.as_ref(), self.round_trip.as_ref()) {
+            let seconds = round_trip.timeout.unwrap_or(120);
+            result = match timeout(Duration::from_secs(seconds), self.round_trip(round_trip)).await
+            {
+                Ok(Ok(elapsed)) => {
+                    log::info!("smtp {} round trip took {:?}", host, elapsed);
+                    DELIVERY_SECONDS
+                        .with_label_values(&["probe.smtp", &host])
+                        .set(elapsed.as_secs_f64());
+                    Ok(())
+                }
+                Ok(Err(err)) => Err(err.context("round trip failed")),
+                Err(_) => Err(anyhow::anyhow!(
+                    "round trip message not delivered to {} within {} seconds",
+                    round_trip.to,
+                    seconds
+                )),
+            };
+        }
+
+        if let Err(err) = result {
+            let message = format!("{:#}", err);
+            log::info!("_TRIGGERED_: smtp {}: {}", host, message);
+            if stored.is_none() || stored == Some(IVec::from(NO_INCIDENT)) {
+                log::warn!("_NOTIFY_: smtp {}: {}", host, message);
+                self.notify(
+                    alerts,
+                    Notification {
+                        from: "smtp".to_owned(),
+                        name: self.name("smtp", self.name.to_owned()),
+                        check: format!("smtp {}", host),
+                        title: format!("smtp {} check failed", host),
+                        message,
+                        message_html: None,
+                        message_entries: None,
+                    },
+                )
+                .await?;
+            }
+            TRIGGERED_TOTAL
+                .with_label_values(&["probe.smtp", &host])
+                .inc();
+            triggered = 1;
+            to_store = HAS_INCIDENT;
+        }
+
+        store.insert(self.slug().as_bytes(), to_store)?;
+
+        TRIGGERED
+            .with_label_values(&["probe.smtp", &host])
+            .set(triggered as f64);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::probes::imap::test::{stand_in as imap_stand_in, Mailbox};
+    use std::sync::{Arc, Mutex};
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+        net::{TcpListener, TcpStream},
+    };
+
+    // Stand-in for an SMTP server with multiline greeting and EHLO replies, accepts
+    // AUTH PLAIN as otto with password "secret" and delivers messages to the mailbox
+    async fn session(stream: TcpStream, mailbox: Mailbox) -> Option<()> {
+        let (read, mut write) = stream.into_split();
+        let mut lines = BufReader::new(read).lines();
+        write
+            .write_all(b"220-smtp.test ESMTP stand-in\r\n220 no UCE\r\n")
+            .await
+            .ok()?;
+        while let Some(line) = lines.next_line().await.ok()? {
+            let verb = line
+                .split_whitespace()
+                .next()
+                .unwrap_or_default()
+                .to_uppercase();
+            let reply = match verb.as_str() {
+                "EHLO" => "250-smtp.test\r\n250-PIPELINING\r\n250-AUTH PLAIN\r\n250 8BITMIME",
+                "AUTH" => match line.split_whitespace().nth(2).map(base64::decode) {
+                    Some(Ok(plain)) if plain == b"\0otto\0secret" => "235 2.7.0 accepted",
+                    _ => "535 5.7.8 authentication credentials invalid",
+                },
+                "MAIL" | "RCPT" | "RSET" | "NOOP" => "250 2.1.0 ok",
+                "DATA" => {
+                    write.write_all(b"354 end with .\r\n").await.ok()?;
+                    let mut headers: Vec<String> = vec![];
+                    let mut in_headers = true;
+                    while let Some(line) = lines.next_line().await.ok()? {
+                        if line == "." {
+                            break;
+                        }
+                        if line.is_empty() {
+                            in_headers = false;
+                        }
+                        match headers.last_mut() {
+                            // unfold long headers
+                            Some(last) if in_headers && line.starts_with(char::is_whitespace) => {
+                                last.push_str(&line)
+                            }
+                            _ if in_headers => headers.push(line),
+                            _ => {}
+                        }
+                    }
+                    let subject = headers
+                        .iter()
+                        .find_map(|header| header.strip_prefix("Subject: "))?;
+                    mailbox.lock().unwrap().push(subject.to_owned());
+                    "250 2.0.0 queued"
+                }
+                "QUIT" => {
+                    write.write_all(b"221 2.0.0 bye\r\n").await.ok()?;
+                    return Some(());
+                }
+                _ => "502 5.5.2 command not recognized",
+            };
+            write
+                .write_all(format!("{}\r\n", reply).as_bytes())
+                .await
+                .ok()?;
+        }
+        Some(())
+    }
+
+    async fn stand_in(mailbox: Mailbox) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                tokio::spawn(session(stream, mailbox.clone()));
+            }
+        });
+        port
+    }
+
+    #[tokio::test]
+    async fn test_check_against_stand_in() {
+        let port = stand_in(Mailbox::default()).await;
+        let probe = Smtp {
+            host: "127.0.0.1".to_owned(),
+            port: Some(port),
+            banner_regex: Some("ESMTP stand-in".to_owned()),
+            username: Some("otto".to_owned()),
+            password: Some("secret".to_owned()),
+            ..Default::default()
+        };
+        probe.check().await.unwrap();
+
+        let wrong_password = Smtp {
+            password: Some("wrong".to_owned()),
+            ..probe.clone()
+        };
+        assert_eq!(
+            wrong_password.check().await.unwrap_err().to_string(),
+            "AUTH failed: 535 5.7.8 authentication credentials invalid"
+        );
+
+        // the greeting spans both lines of the multiline reply
+        let other_banner = Smtp {
+            banner_regex: Some("^smtp.test ESMTP Postfix".to_owned()),
+            ..probe.clone()
+        };
+        assert_eq!(
+            other_banner.check().await.unwrap_err().to_string(),
+            "greeting smtp.test ESMTP stand-in no UCE does not match ^smtp.test ESMTP Postfix"
+        );
+
+        let starttls = Smtp {
+            starttls: Some(true),
+            ..probe
+        };
+        assert_eq!(
+            starttls.check().await.unwrap_err().to_string(),
+            "server does not offer STARTTLS"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_round_trip_through_stand_ins() {
+        let mailbox: Mailbox = Arc::new(Mutex::new(vec!["newsletter".to_owned()]));
+        let smtp_port = stand_in(mailbox.clone()).await;
+        let imap_port = imap_stand_in(mailbox.clone()).await;
+        let probe = Smtp {
+            host: "127.0.0.1".to_owned(),
+            port: Some(smtp_port),
+            username: Some("otto".to_owned()),
+            password: Some("secret".to_owned()),
+            ..Default::default()
+        };
+        // left behind by an earlier round trip that timed out
+        mailbox
+            .lock()
+            .unwrap()
+            .push(format!("otto round trip {} 1", probe.slug()));
+
+        let round_trip = RoundTrip {
+            from: "otto@smtp.test".to_owned(),
+            to: "otto@smtp.test".to_owned(),
+            imap_host: "127.0.0.1".to_owned(),
+            imap_port: Some(imap_port),
+            imap_username: "otto".to_owned(),
+            imap_password: Some("secret".to_owned()),
+            ..Default::default()
+        };
+        probe.round_trip(&round_trip).await.unwrap();
+        assert_eq!(*mailbox.lock().unwrap(), vec!["newsletter"]);
+
+        let wrong_password = RoundTrip {
+            imap_password: Some("wrong".to_owned()),
+            ..round_trip
+        };
+        assert_eq!(
+            probe
+                .round_trip(&wrong_password)
+                .await
+                .unwrap_err()
+                .to_string(),
+            "LOGIN failed: NO [AUTHENTICATIONFAILED] Invalid credentials"
+        );
+    }
+}
@@ -0,0 +1,524 @@
+use crate::{
+    alerts::Alert,
+    probes::{Comparison, MessageEntry, Notification, Probe, HAS_INCIDENT, NO_INCIDENT},
+};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use lazy_static::lazy_static;
+use prometheus::{register_counter_vec, register_gauge_vec, CounterVec, GaugeVec};
+use serde_derive::Deserialize;
+use sled::{Db, IVec};
+use slug::slugify;
+use snmp2::{
+    v3::{Auth, AuthProtocol, Cipher, Security},
+    AsyncSession, Oid, Value,
+};
+use std::{collections::HashMap, net::Ipv4Addr};
+use tokio::time::{timeout, Duration};
+
+// Upper bound of values from walking a single subtree
+const MAX_WALK: usize = 10_000;
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Snmp {
+    name: Option<String>,
+    schedule: Option<String>,
+    depends_on: Option<Vec<String>>,
+    host: String,
+    // default 161
+    port: Option<u16>,
+    // "2c" or "3", default "2c"
+    version: Option<String>,
+    // v2c community, default "public"
+    community: Option<String>,
+    // v3 user, with auth_password for authNoPriv and privacy_password as well for authPriv
+    username: Option<String>,
+    // md5, sha1, sha224, sha256, sha384 or sha512, default sha1
+    auth_protocol: Option<String>,
+    auth_password: Option<String>,
+    // des, aes128, aes192 or aes256, default aes128
+    privacy_protocol: Option<String>,
+    privacy_password: Option<String>,
+    oids: Vec<Object>,
+    // seconds to wait for all requests, default 5
+    timeout: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Object {
+    // numeric OID, eg. "1.3.6.1.2.1.1.3.0"
+    oid: String,
+    // get every value under the OID instead of the OID itself, eg. one per interface
+    walk: Option<bool>,
+    // condition to alert on, eg. "!= 1" for ifOperStatus or "> 70" for a temperature
+    condition: Option<String>,
+    // shown in alerts instead of the OID, eg. "ifOperStatus"
+    label: Option<String>,
+}
+
+lazy_static! {
+    static ref RUNS_TOTAL: CounterVec = register_counter_vec!(
+        "probe_snmp_runs_total",
+        "run counter for SNMP probe plugin",
+        &["plugin", "host"]
+    )
+    .unwrap();
+    static ref TRIGGERED_TOTAL: CounterVec = register_counter_vec!(
+        "probe_snmp_triggered_total",
+        "triggered counter for SNMP probe plugin",
+        &["plugin", "host"]
+    )
+    .unwrap();
+    static ref TRIGGERED: GaugeVec = register_gauge_vec!(
+        "probe_snmp_triggered",
+        "SNMP probe plugin triggered",
+        &["plugin", "host"]
+    )
+    .unwrap();
+    static ref VALUE: GaugeVec = register_gauge_vec!(
+        "probe_snmp_value",
+        "numeric value of an OID from SNMP probe plugin",
+        &["plugin", "host", "oid"]
+    )
+    .unwrap();
+}
+
+// Value as text, None for absent values like noSuchInstance and endOfMibView
+fn value_of(value: &Value) -> Option<String> {
+    match value {
+        Value::Boolean(b) => Some(b.to_string()),
+        Value::Integer(n) => Some(n.to_string()),
+        Value::OctetString(bytes) | Value::Opaque(bytes) => {
+            Some(String::from_utf8_lossy(bytes).to_string())
+        }
+        Value::ObjectIdentifier(oid) => Some(oid.to_id_string()),
+        Value::IpAddress(ip) => Some(Ipv4Addr::from(*ip).to_string()),
+        Value::Counter32(n) | Value::Unsigned32(n) | Value::Timeticks(n) => Some(n.to_string()),
+        Value::Counter64(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+// GET or GETBULK, repeating the request when v3 engine boots and time got resynced
+async fn request(
+    session: &mut AsyncSession,
+    oid: &Oid<'_>,
+    bulk: bool,
+) -> Result<Vec<(Oid<'static>, Option<String>)>> {
+    for _ in 0..3 {
+        let result = if bulk {
+            session.getbulk(&[oid], 0, 20).await
+        } else {
+            session.get(oid).await
+        };
+        match result {
+            Ok(pdu) => {
+                if pdu.error_status != 0 {
+                    anyhow::bail!("agent replied error status {}", pdu.error_status);
+                }
+                return Ok(pdu
+                    .varbinds
+                    .map(|(oid, value)| (oid.to_owned(), value_of(&value)))
+                    .collect());
+            }
+            Err(snmp2::Error::AuthUpdated) => continue,
+            Err(err) => return Err(err).with_context(|| format!("request for {} failed", oid)),
+        }
+    }
+    anyhow::bail!("request for {} failed after resyncing engine time", oid)
+}
+
+impl Snmp {
+    fn address(&self) -> String {
+        format!("{}:{}", self.host, self.port.unwrap_or(161))
+    }
+
+    async fn session(&self) -> Result<AsyncSession> {
+        let address = self.address();
+        match self.version.as_deref().unwrap_or("2c") {
+            "2c" => Ok(AsyncSession::new_v2c(
+                &address,
+                self.community.as_deref().unwrap_or("public").as_bytes(),
+                0,
+            )
+            .await?),
+            "3" => {
+                let username = self
+                    .username
+                    .as_ref()
+                    .ok_or_else(|| anyhow::anyhow!("username is required for snmp v3"))?;
+                let auth_protocol = match self.auth_protocol.as_deref().unwrap_or("sha1") {
+                    "md5" => AuthProtocol::Md5,
+                    "sha1" => AuthProtocol::Sha1,
+                    "sha224" => AuthProtocol::Sha224,
+                    "sha256" => AuthProtocol::Sha256,
+                    "sha384" => AuthProtocol::Sha384,
+                    "sha512" => AuthProtocol::Sha512,
+                    protocol => anyhow::bail!("unsupported auth_protocol {}", protocol),
+                };
+                let auth = match (self.auth_password.as_ref(), self.privacy_password.as_ref()) {
+                    (None, _) => Auth::NoAuthNoPriv,
+                    (Some(_), None) => Auth::AuthNoPriv,
+                    (Some(_), Some(privacy_password)) => Auth::AuthPriv {
+                        cipher: match self.privacy_protocol.as_deref().unwrap_or("aes128") {
+                            "des" => Cipher::Des,
+                            "aes128" => Cipher::Aes128,
+                            "aes192" => Cipher::Aes192,
+                            "aes256" => Cipher::Aes256,
+                            protocol => anyhow::bail!("unsupported privacy_protocol {}", protocol),
+                        },
+                        privacy_password: privacy_password.as_bytes().to_vec(),
+                    },
+                };
+                let security = Security::new(
+                    username.as_bytes(),
+                    self.auth_password.to_owned().unwrap_or_default().as_bytes(),
+                )
+                .with_auth_protocol(auth_protocol)
+                .with_auth(auth);
+                let mut session = AsyncSession::new_v3(&address, 0, security).await?;
+                // discover the engine id before any authenticated request
+                session.init().await.context("engine discovery failed")?;
+                Ok(session)
+            }
+            version => anyhow::bail!("unsupported snmp version {}", version),
+        }
+    }
+
+    // Values of an OID, or of every OID under it when walking
+    async fn values(
+        &self,
+        session: &mut AsyncSession,
+        object: &Object,
+    ) -> Result<Vec<(String, Option<String>)>> {
+        let root: Oid = object
+            .oid
+            .trim_start_matches('.')
+            .parse()
+            .map_err(|_| anyhow::anyhow!("invalid oid {}", object.oid))?;
+        if !object.walk.unwrap_or_default() {
+            return Ok(request(session, &root, false)
+                .await?
+                .into_iter()
+                .map(|(oid, value)| (oid.to_id_string(), value))
+                .collect());
+        }
+
+        let mut values = vec![];
+        let mut next = root.to_owned();
+        'walk: loop {
+            let varbinds = request(session, &next, true).await?;
+            if varbinds.is_empty() {
+                break;
+            }
+            for (oid, value) in varbinds {
+                if !oid.starts_with(&root) || value.is_none() || oid == next {
+                    break 'walk;
+                }
+                values.push((oid.to_id_string(), value));
+                next = oid;
+            }
+            if values.len() >= MAX_WALK {
+                anyhow::bail!("walking {} returned over {} values", object.oid, MAX_WALK);
+            }
+        }
+        Ok(values)
+    }
+
+    // Get or walk each OID and evaluate its condition, returning one entry per violation
+    async fn check(&self) -> Result<Vec<MessageEntry>> {
+        let address = self.address();
+        let mut session = self.session().await?;
+        let mut violations = vec![];
+        for object in self.oids.iter() {
+            let condition: Option<Comparison> = match object.condition.as_ref() {
+                Some(condition) => Some(condition.parse()?),
+                None => None,
+            };
+            let mut values = self.values(&mut session, object).await?;
+            // nothing under a walked subtree is the same as a single absent value
+            if values.is_empty() {
+                values.push((object.oid.to_owned(), None));
+            }
+            for (oid, value) in values.iter() {
+                if let Some(number) = value.as_ref().and_then(|value| value.parse::<f64>().ok()) {
+                    VALUE
+                        .with_label_values(&["probe.snmp", &address, oid])
+                        .set(number);
+                }
+                if let Some(condition) = condition.as_ref() {
+                    if condition.is_met(value.as_deref()) {
+                        let what = match object.label.as_ref() {
+                            Some(label) => format!("{} ({})", label, oid),
+                            None => oid.to_owned(),
+                        };
+                        violations.push(MessageEntry {
+                            title: format!(
+                                "{} {}",
+                                what,
+                                object.condition.to_owned().unwrap_or_default()
+                            ),
+                            description: format!(
+                                "{} is {}",
+                                what,
+                                value.as_deref().unwrap_or("absent")
+                            ),
+                        });
+                    }
+                }
+            }
+        }
+        Ok(violations)
+    }
+}
+
+#[async_trait]
+impl Probe for Snmp {
+    fn new() -> Self {
+        Self {
+            ..Default::default()
+        }
+    }
+
+    fn local_schedule(&self) -> Option<String> {
+        self.schedule.to_owned()
+    }
+
+    fn local_name(&self) -> Option<String> {
+        self.name.to_owned()
+    }
+
+    fn depends_on(&self) -> Option<Vec<String>> {
+        self.depends_on.to_owned()
+    }
+
+    fn slug(&self) -> String {
+        slugify(format!(
+            "snmp-{}-{}",
+            self.address(),
+            self.oids
+                .iter()
+                .map(|object| object.oid.as_str())
+                .collect::<Vec<&str>>()
+                .join("-")
+        ))
+    }
+
+    async fn observe(
+        &self,
+        store: &Db,
+        alerts: &HashMap<String, Vec<Box<dyn Alert>>>,
+    ) -> Result<()> {
+        let address = self.address();
+        log::info!("checking snmp {}", address);
+        RUNS_TOTAL
+            .with_label_values(&["probe.snmp", &address])
+            .inc();
+
+        let stored = store.get(self.slug().as_bytes())?;
+        let mut to_store = NO_INCIDENT;
+        let mut triggered = 0;
+        let seconds = self.timeout.unwrap_or(5);
+        let result = match timeout(Duration::from_secs(seconds), self.check()).await {
+            Ok(result) => result,
+            Err(_) => Err(anyhow::anyhow!("no reply within {} seconds", seconds)),
+        };
+
+        let (title, message, message_entries) = match result {
+            Ok(violations) if violations.is_empty() => ("".to_owned(), "".to_owned(), vec![]),
+            Ok(violations) => (
+                format!("{} snmp condition(s) met on {}", violations.len(), address),
+                violations
+                    .iter()
+                    .map(|entry| entry.description.clone())
+                    .collect::<Vec<String>>()
+                    .join("\n"),
+                violations,
+            ),
+            Err(err) => (
+                format!("snmp {} is unavailable", address),
+                format!("{:#}", err),
+                vec![],
+            ),
+        };
+
+        if !message.is_empty() {
+            log::info!("_TRIGGERED_: snmp {}: {}", address, message);
+            if stored.is_none() || stored == Some(IVec::from(NO_INCIDENT)) {
+                log::warn!("_NOTIFY_: snmp {}: {}", address, message);
+                self.notify(
+                    alerts,
+                    Notification {
+                        from: "snmp".to_owned(),
+                        name: self.name("snmp", self.name.to_owned()),
+                        check: format!("snmp {}", address),
+                        title,
+                        message,
+                        message_html: None,
+                        message_entries: if message_entries.is_empty() {
+                            None
+                        } else {
+                            Some(
+                                message_entries
+                                    .into_iter()
+                                    .take(i8::MAX as usize)
+                                    .enumerate()
+                                    .map(|(i, entry)| (i as i8, entry))
+                                    .collect(),
+                            )
+                        },
+                    },
+                )
+                .await?;
+            }
+            TRIGGERED_TOTAL
+                .with_label_values(&["probe.snmp", &address])
+                .inc();
+            triggered = 1;
+            to_store = HAS_INCIDENT;
+        }
+
+        store.insert(self.slug().as_bytes(), to_store)?;
+
+        TRIGGERED
+            .with_label_values(&["probe.snmp", &address])
+            .set(triggered as f64);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use snmp2::{MessageType, Pdu};
+    use tokio::net::UdpSocket;
+
+    // BER type, length and value
+    fn tlv(tag: u8, value: &[u8]) -> Vec<u8> {
+        let mut encoded = vec![tag];
+        if value.len() < 128 {
+            encoded.push(value.len() as u8);
+        } else {
+            encoded.extend([0x82, (value.len() >> 8) as u8, value.len() as u8]);
+        }
+        encoded.extend(value);
+        encoded
+    }
+
+    fn integer(n: i64) -> Vec<u8> {
+        let bytes = n.to_be_bytes();
+        // shortest two's complement encoding
+        let skip = (0..7)
+            .take_while(|&i| {
+                (bytes[i] == 0 && bytes[i + 1] & 0x80 == 0)
+                    || (bytes[i] == 0xff && bytes[i + 1] & 0x80 != 0)
+            })
+            .count();
+        tlv(0x02, &bytes[skip..])
+    }
+
+    // Minimal v2c agent answering GET and GETBULK from a sorted table
+    async fn agent(socket: UdpSocket, table: Vec<(Vec<u64>, i64)>) {
+        let mut buf = [0; 4096];
+        loop {
+            let (len, peer) = socket.recv_from(&mut buf).await.unwrap();
+            let pdu = Pdu::from_bytes(&buf[..len]).unwrap();
+            let (requested, _) = pdu.varbinds.clone().next().unwrap();
+            let requested: Vec<u64> = requested.iter().unwrap().collect();
+            let found = |oid: &Vec<u64>, value: &i64| {
+                (Oid::from(oid).unwrap().as_bytes().to_vec(), integer(*value))
+            };
+            // noSuchInstance and endOfMibView
+            let absent = |tag: u8| {
+                (
+                    Oid::from(&requested).unwrap().as_bytes().to_vec(),
+                    vec![tag, 0],
+                )
+            };
+            let varbinds: Vec<(Vec<u8>, Vec<u8>)> = match pdu.message_type {
+                MessageType::GetRequest => match table.iter().find(|(oid, _)| *oid == requested) {
+                    Some((oid, value)) => vec![found(oid, value)],
+                    None => vec![absent(0x81)],
+                },
+                _ => {
+                    let mut next: Vec<(Vec<u8>, Vec<u8>)> = table
+                        .iter()
+                        .filter(|(oid, _)| *oid > requested)
+                        .take(20)
+                        .map(|(oid, value)| found(oid, value))
+                        .collect();
+                    if next.is_empty() {
+                        next.push(absent(0x82));
+                    }
+                    next
+                }
+            };
+            let varbinds: Vec<u8> = varbinds
+                .iter()
+                .flat_map(|(oid, value)| tlv(0x30, &[tlv(0x06, oid), value.clone()].concat()))
+                .collect();
+            let response = tlv(
+                0xa2,
+                &[
+                    integer(pdu.req_id.into()),
+                    integer(0),
+                    integer(0),
+                    tlv(0x30, &varbinds),
+                ]
+                .concat(),
+            );
+            let reply = tlv(
+                0x30,
+                &[integer(1), tlv(0x04, pdu.community), response].concat(),
+            );
+            socket.send_to(&reply, peer).await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_check_against_fake_agent() {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let port = socket.local_addr().unwrap().port();
+        let if_oper_status = [1, 3, 6, 1, 2, 1, 2, 2, 1, 8];
+        let mut table: Vec<(Vec<u64>, i64)> = (1..=3)
+            .map(|index| {
+                let mut oid = if_oper_status.to_vec();
+                oid.push(index);
+                // interface 2 is down
+                (oid, if index == 2 { 2 } else { 1 })
+            })
+            .collect();
+        table.push((vec![1, 3, 6, 1, 4, 1, 9, 9, 13, 1, 3, 1, 3, 1], 75));
+        tokio::spawn(agent(socket, table));
+
+        let object = |oid: &str, walk: bool, condition: &str| Object {
+            oid: oid.to_owned(),
+            walk: Some(walk),
+            condition: Some(condition.to_owned()),
+            label: None,
+        };
+        let probe = Snmp {
+            host: "127.0.0.1".to_owned(),
+            port: Some(port),
+            oids: vec![
+                object("1.3.6.1.2.1.2.2.1.8", true, "!= 1"),
+                object(".1.3.6.1.4.1.9.9.13.1.3.1.3.1", false, "> 70"),
+                object("1.3.6.1.2.1.1.3.0", false, "absent"),
+            ],
+            ..Default::default()
+        };
+        let violations = probe.check().await.unwrap();
+        let descriptions: Vec<&str> = violations
+            .iter()
+            .map(|entry| entry.description.as_str())
+            .collect();
+        assert_eq!(
+            descriptions,
+            vec![
+                "1.3.6.1.2.1.2.2.1.8.2 is 2",
+                "1.3.6.1.4.1.9.9.13.1.3.1.3.1 is 75",
+                "1.3.6.1.2.1.1.3.0 is absent",
+            ]
+        );
+    }
+}
@@ -0,0 +1,273 @@
+use crate::{
+    alerts::Alert,
+    probes::{Notification, Probe, HAS_INCIDENT, NO_INCIDENT},
+};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use fancy_regex::Regex;
+use lazy_static::lazy_static;
+use prometheus::{register_counter_vec, register_gauge_vec, CounterVec, GaugeVec};
+use serde_derive::Deserialize;
+use sled::{Db, IVec};
+use slug::slugify;
+use ssh2::{ExtendedData, HashType, Session};
+use std::{
+    collections::HashMap,
+    io::Read,
+    net::{TcpStream, ToSocketAddrs},
+    path::Path,
+    time::{Duration, Instant},
+};
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Ssh {
+    name: Option<String>,
+    schedule: Option<String>,
+    depends_on: Option<Vec<String>>,
+    host: String,
+    // default 22
+    port: Option<u16>,
+    // server banner should match the regex, eg. "^SSH-2.0-OpenSSH_"
+    expect_banner: Option<String>,
+    // pinned host key fingerprint as printed by `ssh-keygen -lf`, eg. "SHA256:wkbVS2A3..."
+    host_key_fingerprint: Option<String>,
+    // authenticate with the key and run the command, which should exit with 0
+    username: Option<String>,
+    private_key: Option<String>,
+    passphrase: Option<String>,
+    command: Option<String>,
+    // seconds to wait for connecting and each read, default 10
+    timeout: Option<u64>,
+}
+
+lazy_static! {
+    static ref RUNS_TOTAL: CounterVec = register_counter_vec!(
+        "probe_ssh_runs_total",
+        "run counter for SSH probe plugin",
+        &["plugin", "address"]
+    )
+    .unwrap();
+    static ref TRIGGERED_TOTAL: CounterVec = register_counter_vec!(
+        "probe_ssh_triggered_total",
+        "triggered counter for SSH probe plugin",
+        &["plugin", "address"]
+    )
+    .unwrap();
+    static ref TRIGGERED: GaugeVec = register_gauge_vec!(
+        "probe_ssh_triggered",
+        "SSH probe plugin triggered",
+        &["plugin", "address"]
+    )
+    .unwrap();
+    static ref HANDSHAKE_SECONDS: GaugeVec = register_gauge_vec!(
+        "probe_ssh_handshake_seconds",
+        "time to connect and complete the SSH handshake",
+        &["plugin", "address"]
+    )
+    .unwrap();
+}
+
+// Same format as OpenSSH, base64 of the sha256 hash without padding
+fn fingerprint(hash: &[u8]) -> String {
+    format!(
+        "SHA256:{}",
+        base64::encode_config(hash, base64::STANDARD_NO_PAD)
+    )
+}
+
+impl Ssh {
+    fn address(&self) -> String {
+        format!("{}:{}", self.host, self.port.unwrap_or(22))
+    }
+
+    // Blocking, libssh2 sessions are synchronous
+    fn check(&self) -> Result<()> {
+        let address = self.address();
+        let seconds = self.timeout.unwrap_or(10);
+        let expect_banner = match self.expect_banner.as_ref() {
+            Some(regex) => Some(
+                Regex::new(regex)
+                    .with_context(|| format!("failed parsing expect_banner {}", regex))?,
+            ),
+            None => None,
+        };
+
+        let started = Instant::now();
+        let socket_addr = address
+            .to_socket_addrs()
+            .with_context(|| format!("failed resolving {}", address))?
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("no address found for {}", address))?;
+        let tcp = TcpStream::connect_timeout(&socket_addr, Duration::from_secs(seconds))
+            .with_context(|| format!("failed connecting to {}", address))?;
+        let mut session = Session::new()?;
+        session.set_timeout(seconds as u32 * 1000);
+        session.set_tcp_stream(tcp);
+        session.handshake().context("handshake failed")?;
+        HANDSHAKE_SECONDS
+            .with_label_values(&["probe.ssh", &address])
+            .set(started.elapsed().as_secs_f64());
+
+        let banner = session.banner().unwrap_or_default();
+        if let Some(re) = expect_banner.as_ref() {
+            if !re.is_match(banner)? {
+                anyhow::bail!("banner {:?} does not match {}", banner, re.as_str());
+            }
+        }
+        if let Some(pinned) = self.host_key_fingerprint.as_ref() {
+            let actual = fingerprint(
+                session
+                    .host_key_hash(HashType::Sha256)
+                    .ok_or_else(|| anyhow::anyhow!("host key unavailable"))?,
+            );
+            if &actual != pinned {
+                anyhow::bail!(
+                    "host key fingerprint {} does not match pinned {}, the key has changed",
+                    actual,
+                    pinned
+                );
+            }
+        }
+
+        let command = match self.command.as_ref() {
+            Some(command) => command,
+            None => return Ok(()),
+        };
+        let username = self
+            .username
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("username is required to run a command"))?;
+        let private_key = self
+            .private_key
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("private_key is required to run a command"))?;
+        session
+            .userauth_pubkey_file(
+                username,
+                None,
+                Path::new(private_key),
+                self.passphrase.as_deref(),
+            )
+            .with_context(|| format!("failed authenticating as {}", username))?;
+
+        let mut channel = session.channel_session()?;
+        // merge stderr into stdout, reading one to EOF before the other can
+        // deadlock once the command fills the window of the unread stream
+        channel.handle_extended_data(ExtendedData::Merge)?;
+        channel.exec(command)?;
+        let mut output = String::new();
+        channel.read_to_string(&mut output)?;
+        channel.wait_close()?;
+        let status = channel.exit_status()?;
+        log::debug!("ssh {} command {} output: {}", address, command, output);
+        if status != 0 {
+            anyhow::bail!(
+                "command {} got exit status: {}: {}",
+                command,
+                status,
+                output.trim()
+            );
+        }
+        session.disconnect(None, "", None).ok();
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Probe for Ssh {
+    fn new() -> Self {
+        Self {
+            ..Default::default()
+        }
+    }
+
+    fn local_schedule(&self) -> Option<String> {
+        self.schedule.to_owned()
+    }
+
+    fn local_name(&self) -> Option<String> {
+        self.name.to_owned()
+    }
+
+    fn depends_on(&self) -> Option<Vec<String>> {
+        self.depends_on.to_owned()
+    }
+
+    fn slug(&self) -> String {
+        slugify(format!(
+            "ssh-{}-{}",
+            self.address(),
+            self.command.to_owned().unwrap_or_default()
+        ))
+    }
+
+    async fn observe(
+        &self,
+        store: &Db,
+        alerts: &HashMap<String, Vec<Box<dyn Alert>>>,
+    ) -> Result<()> {
+        let address = self.address();
+        log::info!("checking ssh {}", address);
+        RUNS_TOTAL.with_label_values(&["probe.ssh", &address]).inc();
+
+        let stored = store.get(self.slug().as_bytes())?;
+        let mut to_store = NO_INCIDENT;
+        let mut triggered = 0;
+        let plugin = self.clone();
+        let result = tokio::task::spawn_blocking(move || plugin.check()).await?;
+
+        if let Err(err) = result {
+            let message = format!("{:#}", err);
+            log::info!("_TRIGGERED_: ssh {}: {}", address, message);
+            if stored.is_none() || stored == Some(IVec::from(NO_INCIDENT)) {
+                log::warn!("_NOTIFY_: ssh {}: {}", address, message);
+                self.notify(
+                    alerts,
+                    Notification {
+                        from: "ssh".to_owned(),
+                        name: self.name("ssh", self.name.to_owned()),
+                        check: match self.command.as_ref() {
+                            Some(command) => format!("ssh {} command `{}`", address, command),
+                            None => format!("ssh {}", address),
+                        },
+                        title: format!("ssh {} check failed", address),
+                        message,
+                        message_html: None,
+                        message_entries: None,
+                    },
+                )
+                .await?;
+            }
+            TRIGGERED_TOTAL
+                .with_label_values(&["probe.ssh", &address])
+                .inc();
+            triggered = 1;
+            to_store = HAS_INCIDENT;
+        }
+
+        store.insert(self.slug().as_bytes(), to_store)?;
+
+        TRIGGERED
+            .with_label_values(&["probe.ssh", &address])
+            .set(triggered as f64);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use sha2::{Digest, Sha256};
+
+    #[test]
+    fn test_fingerprint() {
+        // key blob of an ed25519 public key, and its fingerprint from `ssh-keygen -lf`
+        let blob =
+            base64::decode("AAAAC3NzaC1lZDI1NTE5AAAAIE722IyOvstXY0lDjSbkX60nHz1PPf1ztTuhxvxB/Nkq")
+                .unwrap();
+        assert_eq!(
+            fingerprint(&Sha256::digest(&blob)),
+            "SHA256:wkbVS2A3ryTjg3MwEBWDLzz+XH+z9hzRu/1tQGhdNac"
+        );
+    }
+}
@@ -0,0 +1,606 @@
+use crate::{
+    alerts::Alert,
+    probes::{MessageEntry, Notification, Probe, HAS_INCIDENT, NO_INCIDENT},
+};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use lazy_static::lazy_static;
+use prometheus::{register_counter_vec, register_gauge_vec, CounterVec, GaugeVec};
+use serde_derive::Deserialize;
+use sled::{Db, IVec};
+use slug::slugify;
+use std::{collections::HashMap, ffi::CString, fs, mem};
+use wildmatch::WildMatch;
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct System {
+    name: Option<String>,
+    schedule: Option<String>,
+    depends_on: Option<Vec<String>>,
+    // glob patterns of mount points to check, default all
+    mounts_include: Option<Vec<String>>,
+    mounts_exclude: Option<Vec<String>>,
+    // glob patterns of filesystem types to skip, default network and pseudo
+    // filesystems, see FSTYPES_EXCLUDE
+    fstypes_exclude: Option<Vec<String>>,
+    // thresholds in percent
+    disk_warn: Option<f64>,
+    disk_critical: Option<f64>,
+    inodes_warn: Option<f64>,
+    inodes_critical: Option<f64>,
+    memory_warn: Option<f64>,
+    memory_critical: Option<f64>,
+    swap_warn: Option<f64>,
+    swap_critical: Option<f64>,
+    fds_warn: Option<f64>,
+    fds_critical: Option<f64>,
+    // thresholds of 5 minute load average divided by number of CPUs
+    load_warn: Option<f64>,
+    load_critical: Option<f64>,
+}
+
+lazy_static! {
+    static ref RUNS_TOTAL: CounterVec = register_counter_vec!(
+        "probe_system_runs_total",
+        "run counter for system probe plugin",
+        &["plugin", "name"]
+    )
+    .unwrap();
+    static ref TRIGGERED_TOTAL: CounterVec = register_counter_vec!(
+        "probe_system_triggered_total",
+        "triggered counter for system probe plugin",
+        &["plugin", "name"]
+    )
+    .unwrap();
+    static ref TRIGGERED: GaugeVec = register_gauge_vec!(
+        "probe_system_triggered",
+        "System probe plugin triggered",
+        &["plugin", "name"]
+    )
+    .unwrap();
+    static ref FILESYSTEM_USED: GaugeVec = register_gauge_vec!(
+        "probe_system_filesystem_used_ratio",
+        "filesystem space used ratio from system probe plugin",
+        &["plugin", "mountpoint"]
+    )
+    .unwrap();
+    static ref FILESYSTEM_INODES_USED: GaugeVec = register_gauge_vec!(
+        "probe_system_filesystem_inodes_used_ratio",
+        "filesystem inodes used ratio from system probe plugin",
+        &["plugin", "mountpoint"]
+    )
+    .unwrap();
+    static ref MEMORY_USED: GaugeVec = register_gauge_vec!(
+        "probe_system_memory_used_ratio",
+        "memory used ratio from system probe plugin",
+        &["plugin"]
+    )
+    .unwrap();
+    static ref SWAP_USED: GaugeVec = register_gauge_vec!(
+        "probe_system_swap_used_ratio",
+        "swap used ratio from system probe plugin",
+        &["plugin"]
+    )
+    .unwrap();
+    static ref LOAD_PER_CPU: GaugeVec = register_gauge_vec!(
+        "probe_system_load_per_cpu",
+        "load average per CPU from system probe plugin",
+        &["plugin", "period"]
+    )
+    .unwrap();
+    static ref OPEN_FDS: GaugeVec = register_gauge_vec!(
+        "probe_system_open_fds",
+        "open file descriptors from system probe plugin",
+        &["plugin"]
+    )
+    .unwrap();
+    static ref OPEN_FDS_USED: GaugeVec = register_gauge_vec!(
+        "probe_system_open_fds_used_ratio",
+        "open file descriptors used ratio from system probe plugin",
+        &["plugin"]
+    )
+    .unwrap();
+}
+
+// Network filesystems can hang statvfs when the server is gone, and pseudo
+// filesystems have no meaningful space to report
+const FSTYPES_EXCLUDE: &[&str] = &[
+    "9p",
+    "afs",
+    "autofs",
+    "binfmt_misc",
+    "bpf",
+    "ceph",
+    "cgroup",
+    "cgroup2",
+    "cifs",
+    "configfs",
+    "debugfs",
+    "devpts",
+    "devtmpfs",
+    "fuse.*",
+    "fusectl",
+    "glusterfs",
+    "hugetlbfs",
+    "mqueue",
+    "ncpfs",
+    "nfs",
+    "nfs4",
+    "nsfs",
+    "proc",
+    "pstore",
+    "rpc_pipefs",
+    "securityfs",
+    "smb3",
+    "smbfs",
+    "sshfs",
+    "sysfs",
+    "tracefs",
+];
+
+struct Usage {
+    space: f64,
+    inodes: Option<f64>,
+}
+
+// Space used the same way `df` calculates it, None for pseudo filesystems
+fn statvfs(mountpoint: &str) -> Result<Option<Usage>> {
+    let path = CString::new(mountpoint)?;
+    let mut stat: libc::statvfs = unsafe { mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        anyhow::bail!(
+            "failed statvfs on {}: {}",
+            mountpoint,
+            std::io::Error::last_os_error()
+        );
+    }
+    let used = stat.f_blocks.saturating_sub(stat.f_bfree) as f64;
+    let available = used + stat.f_bavail as f64;
+    if stat.f_blocks == 0 || available == 0.0 {
+        return Ok(None);
+    }
+    let inodes = if stat.f_files > 0 {
+        Some(stat.f_files.saturating_sub(stat.f_ffree) as f64 / stat.f_files as f64)
+    } else {
+        None
+    };
+    Ok(Some(Usage {
+        space: used / available,
+        inodes,
+    }))
+}
+
+// Mount points in /proc/mounts escape spaces and tabs as octal, eg. `\040`
+fn unescape(mountpoint: &str) -> String {
+    let mut unescaped = String::new();
+    let mut chars = mountpoint.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            let octal: String = (0..3).filter_map(|_| chars.next()).collect();
+            match u8::from_str_radix(&octal, 8) {
+                Ok(byte) => unescaped.push(byte as char),
+                Err(_) => {
+                    unescaped.push(c);
+                    unescaped.push_str(&octal);
+                }
+            }
+        } else {
+            unescaped.push(c);
+        }
+    }
+    unescaped
+}
+
+fn meminfo() -> Result<HashMap<String, f64>> {
+    let content = fs::read_to_string("/proc/meminfo").context("failed reading /proc/meminfo")?;
+    Ok(content
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.split_whitespace();
+            let key = parts.next()?.trim_end_matches(':').to_owned();
+            let value = parts.next()?.parse::<f64>().ok()?;
+            Some((key, value))
+        })
+        .collect())
+}
+
+impl System {
+    // Reads /proc/mounts and calls statvfs on each mount point, both of which
+    // block, so this runs on the blocking thread pool
+    fn filesystems(&self) -> Result<Vec<(String, Usage)>> {
+        let mounts = fs::read_to_string("/proc/mounts").context("failed reading /proc/mounts")?;
+        let mut checked: Vec<String> = vec![];
+        let mut filesystems = vec![];
+        for line in mounts.lines() {
+            let mut fields = line.split_whitespace();
+            let (mountpoint, fstype) = match (fields.nth(1), fields.next()) {
+                (Some(mountpoint), Some(fstype)) => (unescape(mountpoint), fstype),
+                _ => continue,
+            };
+            if checked.contains(&mountpoint)
+                || self.is_excluded_fstype(fstype)
+                || !self.should_check(&mountpoint)
+            {
+                continue;
+            }
+            checked.push(mountpoint.clone());
+            match statvfs(&mountpoint) {
+                Ok(Some(usage)) => filesystems.push((mountpoint, usage)),
+                Ok(None) => continue,
+                Err(err) => log::error!("{}", err),
+            }
+        }
+        Ok(filesystems)
+    }
+
+    fn is_excluded_fstype(&self, fstype: &str) -> bool {
+        match &self.fstypes_exclude {
+            Some(globs) => globs
+                .iter()
+                .any(|glob| WildMatch::new(glob).is_match(fstype)),
+            None => FSTYPES_EXCLUDE
+                .iter()
+                .any(|glob| WildMatch::new(glob).is_match(fstype)),
+        }
+    }
+
+    fn should_check(&self, mountpoint: &str) -> bool {
+        let is_match_any = |globs: &Option<Vec<String>>| {
+            globs.as_ref().map(|globs| {
+                globs
+                    .iter()
+                    .any(|glob| WildMatch::new(glob).is_match(mountpoint))
+            })
+        };
+        is_match_any(&self.mounts_include).unwrap_or(true)
+            && !is_match_any(&self.mounts_exclude).unwrap_or(false)
+    }
+}
+
+// Compare a value in percent against warn and critical thresholds
+fn check(
+    entries: &mut Vec<MessageEntry>,
+    what: String,
+    value: f64,
+    warn: Option<f64>,
+    critical: Option<f64>,
+) {
+    let severity = match (warn, critical) {
+        (_, Some(critical)) if value >= critical => "CRITICAL",
+        (Some(warn), _) if value >= warn => "WARNING",
+        _ => return,
+    };
+    entries.push(MessageEntry {
+        title: format!("{}: {} at {:.1}", severity, what, value),
+        description: format!(
+            "warn at {}, critical at {}",
+            warn.map_or("-".to_owned(), |warn| warn.to_string()),
+            critical.map_or("-".to_owned(), |critical| critical.to_string())
+        ),
+    });
+}
+
+#[async_trait]
+impl Probe for System {
+    fn new() -> Self {
+        Self {
+            ..Default::default()
+        }
+    }
+
+    fn local_schedule(&self) -> Option<String> {
+        self.schedule.to_owned()
+    }
+
+    fn local_name(&self) -> Option<String> {
+        self.name.to_owned()
+    }
+
+    fn depends_on(&self) -> Option<Vec<String>> {
+        self.depends_on.to_owned()
+    }
+
+    fn slug(&self) -> String {
+        slugify(format!(
+            "system-{}",
+            self.name.to_owned().unwrap_or_default()
+        ))
+    }
+
+    async fn observe(
+        &self,
+        store: &Db,
+        alerts: &HashMap<String, Vec<Box<dyn Alert>>>,
+    ) -> Result<()> {
+        let name = self.name("system", self.name.to_owned());
+        log::info!("checking system resources for {}", name);
+        RUNS_TOTAL.with_label_values(&["probe.system", &name]).inc();
+
+        let stored = store.get(self.slug().as_bytes())?;
+        let mut to_store = NO_INCIDENT;
+        let mut triggered = 0;
+        let mut entries: Vec<MessageEntry> = vec![];
+
+        let plugin = self.clone();
+        let filesystems = tokio::task::spawn_blocking(move || plugin.filesystems()).await??;
+        for (mountpoint, usage) in filesystems {
+            FILESYSTEM_USED
+                .with_label_values(&["probe.system", &mountpoint])
+                .set(usage.space);
+            check(
+                &mut entries,
+                format!("filesystem {} used %", mountpoint),
+                usage.space * 100.0,
+                self.disk_warn,
+                self.disk_critical,
+            );
+            if let Some(inodes) = usage.inodes {
+                FILESYSTEM_INODES_USED
+                    .with_label_values(&["probe.system", &mountpoint])
+                    .set(inodes);
+                check(
+                    &mut entries,
+                    format!("filesystem {} inodes used %", mountpoint),
+                    inodes * 100.0,
+                    self.inodes_warn,
+                    self.inodes_critical,
+                );
+            }
+        }
+
+        let meminfo = meminfo()?;
+        if let (Some(total), Some(available)) =
+            (meminfo.get("MemTotal"), meminfo.get("MemAvailable"))
+        {
+            if *total > 0.0 {
+                let used = (total - available) / total;
+                MEMORY_USED.with_label_values(&["probe.system"]).set(used);
+                check(
+                    &mut entries,
+                    "memory used %".to_owned(),
+                    used * 100.0,
+                    self.memory_warn,
+                    self.memory_critical,
+                );
+            }
+        }
+        if let (Some(total), Some(free)) = (meminfo.get("SwapTotal"), meminfo.get("SwapFree")) {
+            if *total > 0.0 {
+                let used = (total - free) / total;
+                SWAP_USED.with_label_values(&["probe.system"]).set(used);
+                check(
+                    &mut entries,
+                    "swap used %".to_owned(),
+                    used * 100.0,
+                    self.swap_warn,
+                    self.swap_critical,
+                );
+            }
+        }
+
+        let cpus = unsafe { libc::sysconf(libc::_SC_NPROCESSORS_ONLN) }.max(1) as f64;
+        let loadavg =
+            fs::read_to_string("/proc/loadavg").context("failed reading /proc/loadavg")?;
+        let loads: Vec<f64> = loadavg
+            .split_whitespace()
+            .take(3)
+            .filter_map(|load| load.parse::<f64>().ok())
+            .collect();
+        for (period, load) in ["1m", "5m", "15m"].iter().zip(loads.iter()) {
+            LOAD_PER_CPU
+                .with_label_values(&["probe.system", period])
+                .set(load / cpus);
+        }
+        if let Some(load) = loads.get(1) {
+            check(
+                &mut entries,
+                "5m load average per CPU".to_owned(),
+                load / cpus,
+                self.load_warn,
+                self.load_critical,
+            );
+        }
+
+        // allocated, unused and maximum number of file handles
+        let file_nr = fs::read_to_string("/proc/sys/fs/file-nr")
+            .context("failed reading /proc/sys/fs/file-nr")?;
+        let file_nr: Vec<f64> = file_nr
+            .split_whitespace()
+            .filter_map(|count| count.parse::<f64>().ok())
+            .collect();
+        if let (Some(allocated), Some(max)) = (file_nr.first(), file_nr.get(2)) {
+            OPEN_FDS
+                .with_label_values(&["probe.system"])
+                .set(*allocated);
+            if *max > 0.0 {
+                OPEN_FDS_USED
+                    .with_label_values(&["probe.system"])
+                    .set(allocated / max);
+                check(
+                    &mut entries,
+                    "open file descriptors used %".to_owned(),
+                    allocated / max * 100.0,
+                    self.fds_warn,
+                    self.fds_critical,
+                );
+            }
+        }
+
+        // severity of the open incident, so an escalation from warning to critical is
+        // notified even though the incident was already open
+        let severity_key = format!("{}-severity", self.slug());
+        let critical = entries
+            .iter()
+            .any(|entry| entry.title.starts_with("CRITICAL"));
+        let escalated = critical
+            && stored == Some(IVec::from(HAS_INCIDENT))
+            && store.get(severity_key.as_bytes())? != Some(IVec::from("CRITICAL"));
+        if !entries.is_empty() {
+            let message = entries
+                .iter()
+                .map(|entry| format!("{} ({})", entry.title, entry.description))
+                .collect::<Vec<String>>()
+                .join("\n");
+            log::info!("_TRIGGERED_: {}", message.replace('\n', ", "));
+            if stored.is_none() || stored == Some(IVec::from(NO_INCIDENT)) || escalated {
+                log::warn!("_NOTIFY_: {}", message.replace('\n', ", "));
+                self.notify(
+                    alerts,
+                    Notification {
+                        from: "system".to_owned(),
+                        name: name.clone(),
+                        check: "system disk, memory, load and file descriptors".to_owned(),
+                        title: format!("{} system resource(s) above thresholds", entries.len()),
+                        message,
+                        message_html: None,
+                        message_entries: Some(
+                            entries
+                                .into_iter()
+                                .take(i8::MAX as usize)
+                                .enumerate()
+                                .map(|(i, entry)| (i as i8, entry))
+                                .collect(),
+                        ),
+                    },
+                )
+                .await?;
+            }
+            TRIGGERED_TOTAL
+                .with_label_values(&["probe.system", &name])
+                .inc();
+            triggered = 1;
+            to_store = HAS_INCIDENT;
+        }
+
+        store.insert(self.slug().as_bytes(), to_store)?;
+        match (to_store == HAS_INCIDENT, critical) {
+            (false, _) => store.remove(severity_key.as_bytes())?,
+            (true, true) => store.insert(severity_key.as_bytes(), "CRITICAL")?,
+            (true, false) => store.insert(severity_key.as_bytes(), "WARNING")?,
+        };
+
+        TRIGGERED
+            .with_label_values(&["probe.system", &name])
+            .set(triggered as f64);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::probes::testing;
+
+    #[test]
+    fn test_unescape() {
+        assert_eq!(unescape("/"), "/");
+        assert_eq!(unescape("/mnt/my\\040disk"), "/mnt/my disk");
+        assert_eq!(
+            unescape("/mnt/tab\\011and\\134slash"),
+            "/mnt/tab\tand\\slash"
+        );
+        // not an octal escape, kept as is
+        assert_eq!(unescape("/mnt/odd\\9xy"), "/mnt/odd\\9xy");
+    }
+
+    #[test]
+    fn test_is_excluded_fstype() {
+        let default = System::default();
+        for fstype in &["nfs4", "cifs", "fuse.sshfs", "proc", "cgroup2", "devtmpfs"] {
+            assert!(default.is_excluded_fstype(fstype), "{}", fstype);
+        }
+        for fstype in &["ext4", "xfs", "btrfs", "tmpfs", "zfs", "fuseblk"] {
+            assert!(!default.is_excluded_fstype(fstype), "{}", fstype);
+        }
+        let configured = System {
+            fstypes_exclude: Some(vec!["tmpfs".to_owned(), "nfs*".to_owned()]),
+            ..Default::default()
+        };
+        assert!(configured.is_excluded_fstype("tmpfs"));
+        assert!(configured.is_excluded_fstype("nfs4"));
+        // the configured list replaces the default one
+        assert!(!configured.is_excluded_fstype("proc"));
+    }
+
+    #[test]
+    fn test_check() {
+        let mut entries = vec![];
+        check(
+            &mut entries,
+            "disk".to_owned(),
+            79.9,
+            Some(80.0),
+            Some(90.0),
+        );
+        assert!(entries.is_empty());
+        check(
+            &mut entries,
+            "disk".to_owned(),
+            80.0,
+            Some(80.0),
+            Some(90.0),
+        );
+        check(
+            &mut entries,
+            "disk".to_owned(),
+            95.0,
+            Some(80.0),
+            Some(90.0),
+        );
+        check(&mut entries, "swap".to_owned(), 95.0, None, Some(90.0));
+        check(&mut entries, "load".to_owned(), 2.0, Some(1.5), None);
+        check(&mut entries, "fds".to_owned(), 99.0, None, None);
+        let titles: Vec<&str> = entries.iter().map(|entry| entry.title.as_str()).collect();
+        assert_eq!(
+            titles,
+            vec![
+                "WARNING: disk at 80.0",
+                "CRITICAL: disk at 95.0",
+                "CRITICAL: swap at 95.0",
+                "WARNING: load at 2.0",
+            ]
+        );
+        assert_eq!(entries[2].description, "warn at -, critical at 90");
+    }
+
+    #[tokio::test]
+    async fn test_escalation_is_notified() {
+        // memory in use is always above 0%, and no filesystem matches
+        let thresholds = |warn: Option<f64>, critical: Option<f64>| System {
+            name: Some("escalation".to_owned()),
+            mounts_include: Some(vec!["/nonexistent".to_owned()]),
+            memory_warn: warn,
+            memory_critical: critical,
+            ..Default::default()
+        };
+        let (store, alerts, sent) = testing::setup();
+        let warning = thresholds(Some(0.0), None);
+        let critical = thresholds(Some(0.0), Some(0.0));
+        let notified = |sent: &testing::Sent| -> Vec<String> {
+            testing::drain(sent)
+                .into_iter()
+                .map(|notif| notif.split(": ").nth(1).unwrap_or_default().to_owned())
+                .collect()
+        };
+
+        warning.observe(&store, &alerts).await.unwrap();
+        assert_eq!(notified(&sent), vec!["WARNING"]);
+        warning.observe(&store, &alerts).await.unwrap();
+        assert!(notified(&sent).is_empty());
+        critical.observe(&store, &alerts).await.unwrap();
+        assert_eq!(notified(&sent), vec!["CRITICAL"]);
+        critical.observe(&store, &alerts).await.unwrap();
+        // back to warning while the incident is still open is not news
+        warning.observe(&store, &alerts).await.unwrap();
+        assert!(notified(&sent).is_empty());
+        critical.observe(&store, &alerts).await.unwrap();
+        assert_eq!(notified(&sent), vec!["CRITICAL"]);
+
+        thresholds(None, None)
+            .observe(&store, &alerts)
+            .await
+            .unwrap();
+        critical.observe(&store, &alerts).await.unwrap();
+        assert_eq!(notified(&sent), vec!["CRITICAL"]);
+    }
+}
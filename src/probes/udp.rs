@@ -0,0 +1,338 @@
+use crate::{
+    alerts::Alert,
+    probes::{Notification, Probe, HAS_INCIDENT, NO_INCIDENT},
+};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use fancy_regex::Regex;
+use lazy_static::lazy_static;
+use prometheus::{register_counter_vec, register_gauge_vec, CounterVec, GaugeVec};
+use serde_derive::Deserialize;
+use sled::{Db, IVec};
+use slug::slugify;
+use std::collections::HashMap;
+use tokio::{
+    net::UdpSocket,
+    time::{timeout, Duration, Instant},
+};
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Udp {
+    name: Option<String>,
+    schedule: Option<String>,
+    depends_on: Option<Vec<String>>,
+    host: String,
+    port: u16,
+    // payload to send, as text or hex
+    payload: Option<String>,
+    payload_hex: Option<String>,
+    // reply should match the regex, or contain the hex encoded bytes,
+    // any reply is accepted when neither is set
+    expect_regex: Option<String>,
+    expect_hex: Option<String>,
+    // seconds to wait for the reply, default 5
+    timeout: Option<u64>,
+}
+
+lazy_static! {
+    static ref RUNS_TOTAL: CounterVec = register_counter_vec!(
+        "probe_udp_runs_total",
+        "run counter for UDP probe plugin",
+        &["plugin", "address"]
+    )
+    .unwrap();
+    static ref TRIGGERED_TOTAL: CounterVec = register_counter_vec!(
+        "probe_udp_triggered_total",
+        "triggered counter for UDP probe plugin",
+        &["plugin", "address"]
+    )
+    .unwrap();
+    static ref TRIGGERED: GaugeVec = register_gauge_vec!(
+        "probe_udp_triggered",
+        "UDP probe plugin triggered",
+        &["plugin", "address"]
+    )
+    .unwrap();
+    static ref RESPONSE_SECONDS: GaugeVec = register_gauge_vec!(
+        "probe_udp_response_seconds",
+        "time from sending the payload to receiving the reply",
+        &["plugin", "address"]
+    )
+    .unwrap();
+}
+
+// Decode hex, ignoring whitespace so long payloads can be grouped, eg. "1b 00 00 00"
+fn decode_hex(hex: &str) -> Result<Vec<u8>> {
+    let digits: Vec<char> = hex.chars().filter(|c| !c.is_whitespace()).collect();
+    if digits.len() % 2 == 1 {
+        anyhow::bail!("odd number of hex digits in {}", hex);
+    }
+    // from_str_radix would otherwise take a leading + as a sign
+    if digits.iter().any(|c| !c.is_ascii_hexdigit()) {
+        anyhow::bail!("invalid hex {}", hex);
+    }
+    digits
+        .chunks(2)
+        .map(|pair| {
+            u8::from_str_radix(&pair.iter().collect::<String>(), 16)
+                .with_context(|| format!("invalid hex {}", hex))
+        })
+        .collect()
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+impl Udp {
+    fn address(&self) -> String {
+        format!("{}:{}", self.host, self.port)
+    }
+
+    async fn check(&self) -> Result<()> {
+        let payload = match (self.payload.as_ref(), self.payload_hex.as_ref()) {
+            (_, Some(payload_hex)) => decode_hex(payload_hex)?,
+            (Some(payload), None) => payload.as_bytes().to_vec(),
+            (None, None) => vec![],
+        };
+        let expect_regex = match self.expect_regex.as_ref() {
+            Some(regex) => Some(
+                Regex::new(regex)
+                    .with_context(|| format!("failed parsing expect_regex {}", regex))?,
+            ),
+            None => None,
+        };
+        let expect_bytes = match self.expect_hex.as_ref() {
+            Some(expect_hex) => Some(decode_hex(expect_hex)?),
+            None => None,
+        };
+
+        let socket = UdpSocket::bind(if self.host.contains(':') {
+            "[::]:0"
+        } else {
+            "0.0.0.0:0"
+        })
+        .await?;
+        socket
+            .connect(self.address())
+            .await
+            .with_context(|| format!("failed resolving {}", self.address()))?;
+        let started = Instant::now();
+        socket.send(&payload).await?;
+
+        let mut buf = vec![0; 65535];
+        // an ICMP port unreachable surfaces here as connection refused
+        let len = socket
+            .recv(&mut buf)
+            .await
+            .context("failed receiving reply")?;
+        RESPONSE_SECONDS
+            .with_label_values(&["probe.udp", &self.address()])
+            .set(started.elapsed().as_secs_f64());
+        let reply = &buf[..len];
+
+        if let Some(re) = expect_regex.as_ref() {
+            let text = String::from_utf8_lossy(reply);
+            if !re.is_match(&text)? {
+                anyhow::bail!("reply {:?} does not match {}", text, re.as_str());
+            }
+        }
+        if let Some(expect_bytes) = expect_bytes.as_ref() {
+            let found = expect_bytes.is_empty()
+                || reply
+                    .windows(expect_bytes.len())
+                    .any(|window| window == expect_bytes.as_slice());
+            if !found {
+                anyhow::bail!(
+                    "reply [{}] does not contain [{}]",
+                    encode_hex(reply),
+                    encode_hex(expect_bytes)
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Probe for Udp {
+    fn new() -> Self {
+        Self {
+            ..Default::default()
+        }
+    }
+
+    fn local_schedule(&self) -> Option<String> {
+        self.schedule.to_owned()
+    }
+
+    fn local_name(&self) -> Option<String> {
+        self.name.to_owned()
+    }
+
+    fn depends_on(&self) -> Option<Vec<String>> {
+        self.depends_on.to_owned()
+    }
+
+    fn slug(&self) -> String {
+        slugify(format!(
+            "udp-{}-{}",
+            self.address(),
+            self.payload_hex
+                .to_owned()
+                .or_else(|| self.payload.to_owned())
+                .unwrap_or_default()
+        ))
+    }
+
+    async fn observe(
+        &self,
+        store: &Db,
+        alerts: &HashMap<String, Vec<Box<dyn Alert>>>,
+    ) -> Result<()> {
+        let address = self.address();
+        log::info!("checking udp {}", address);
+        RUNS_TOTAL.with_label_values(&["probe.udp", &address]).inc();
+
+        let stored = store.get(self.slug().as_bytes())?;
+        let mut to_store = NO_INCIDENT;
+        let mut triggered = 0;
+        let seconds = self.timeout.unwrap_or(5);
+        let result = match timeout(Duration::from_secs(seconds), self.check()).await {
+            Ok(result) => result,
+            Err(_) => Err(anyhow::anyhow!("no reply within {} seconds", seconds)),
+        };
+
+        if let Err(err) = result {
+            let message = format!("{:#}", err);
+            log::info!("_TRIGGERED_: udp {}: {}", address, message);
+            if stored.is_none() || stored == Some(IVec::from(NO_INCIDENT)) {
+                log::warn!("_NOTIFY_: udp {}: {}", address, message);
+                self.notify(
+                    alerts,
+                    Notification {
+                        from: "udp".to_owned(),
+                        name: self.name("udp", self.name.to_owned()),
+                        check: format!("udp {}", address),
+                        title: format!("udp {} check failed", address),
+                        message,
+                        message_html: None,
+                        message_entries: None,
+                    },
+                )
+                .await?;
+            }
+            TRIGGERED_TOTAL
+                .with_label_values(&["probe.udp", &address])
+                .inc();
+            triggered = 1;
+            to_store = HAS_INCIDENT;
+        }
+
+        store.insert(self.slug().as_bytes(), to_store)?;
+
+        TRIGGERED
+            .with_label_values(&["probe.udp", &address])
+            .set(triggered as f64);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::probes::testing;
+
+    #[test]
+    fn test_decode_hex() {
+        assert_eq!(decode_hex("").unwrap(), Vec::<u8>::new());
+        assert_eq!(decode_hex("00ff7F").unwrap(), vec![0x00, 0xff, 0x7f]);
+        assert_eq!(
+            decode_hex(" de ad\nbe\tef ").unwrap(),
+            vec![0xde, 0xad, 0xbe, 0xef]
+        );
+        let err = decode_hex("abc").unwrap_err().to_string();
+        assert_eq!(err, "odd number of hex digits in abc");
+        assert_eq!(decode_hex("zz").unwrap_err().to_string(), "invalid hex zz");
+        assert_eq!(decode_hex("+1").unwrap_err().to_string(), "invalid hex +1");
+        assert_eq!(encode_hex(&[0xde, 0xad, 0x01]), "de ad 01");
+    }
+
+    async fn echo() -> u16 {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let port = socket.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let mut buf = vec![0; 65535];
+            while let Ok((len, peer)) = socket.recv_from(&mut buf).await {
+                let _ = socket.send_to(&buf[..len], peer).await;
+            }
+        });
+        port
+    }
+
+    #[tokio::test]
+    async fn test_check_against_echo() {
+        let port = echo().await;
+        let probe = |payload: &str, expect_regex: Option<&str>, expect_hex: Option<&str>| Udp {
+            host: "127.0.0.1".to_owned(),
+            port,
+            payload: Some(payload.to_owned()),
+            expect_regex: expect_regex.map(str::to_owned),
+            expect_hex: expect_hex.map(str::to_owned),
+            ..Default::default()
+        };
+
+        probe("PING 42", None, None).check().await.unwrap();
+        probe("PING 42", Some(r"^PING \d+$"), None)
+            .check()
+            .await
+            .unwrap();
+        let err = probe("PONG", Some(r"^PING"), None)
+            .check()
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "reply \"PONG\" does not match ^PING");
+
+        probe("PING 42", None, Some("4e 47")).check().await.unwrap();
+        let err = probe("PING", None, Some("ff")).check().await.unwrap_err();
+        assert_eq!(err.to_string(), "reply [50 49 4e 47] does not contain [ff]");
+        let err = probe("PING", None, Some("f")).check().await.unwrap_err();
+        assert_eq!(err.to_string(), "odd number of hex digits in f");
+
+        let probe = Udp {
+            host: "127.0.0.1".to_owned(),
+            port,
+            payload_hex: Some("00 01 fe".to_owned()),
+            expect_hex: Some("01fe".to_owned()),
+            ..Default::default()
+        };
+        probe.check().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_reply_timeout() {
+        // bound but never read, so nothing ever answers
+        let silent = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let probe = Udp {
+            host: "127.0.0.1".to_owned(),
+            port: silent.local_addr().unwrap().port(),
+            payload: Some("PING".to_owned()),
+            timeout: Some(1),
+            ..Default::default()
+        };
+        let (store, alerts, sent) = testing::setup();
+        probe.observe(&store, &alerts).await.unwrap();
+        let notified = testing::drain(&sent);
+        assert_eq!(notified.len(), 1);
+        assert!(notified[0].contains("no reply within 1 seconds"));
+        assert_eq!(
+            store.get(probe.slug().as_bytes()).unwrap(),
+            Some(IVec::from(HAS_INCIDENT))
+        );
+        drop(silent);
+    }
+}
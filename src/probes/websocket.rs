@@ -0,0 +1,216 @@
+use crate::{
+    alerts::Alert,
+    probes::{Notification, Probe, HAS_INCIDENT, NO_INCIDENT},
+};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use fancy_regex::Regex;
+use futures_util::{SinkExt, StreamExt};
+use lazy_static::lazy_static;
+use prometheus::{register_counter_vec, register_gauge_vec, CounterVec, GaugeVec};
+use serde_derive::Deserialize;
+use sled::{Db, IVec};
+use slug::slugify;
+use std::collections::HashMap;
+use tokio::time::{timeout, Duration, Instant};
+use tokio_tungstenite::{
+    connect_async,
+    tungstenite::{
+        client::IntoClientRequest,
+        http::{HeaderName, HeaderValue},
+        Message,
+    },
+};
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Websocket {
+    name: Option<String>,
+    schedule: Option<String>,
+    depends_on: Option<Vec<String>>,
+    // ws:// or wss:// for TLS
+    url: String,
+    headers: Option<HashMap<String, String>>,
+    // text message to send once connected
+    send: Option<String>,
+    // wait for a reply matching this regex
+    expect_regex: Option<String>,
+    // seconds to wait for connecting and the reply, default 10
+    timeout: Option<u64>,
+}
+
+lazy_static! {
+    static ref RUNS_TOTAL: CounterVec = register_counter_vec!(
+        "probe_websocket_runs_total",
+        "run counter for WebSocket probe plugin",
+        &["plugin", "url"]
+    )
+    .unwrap();
+    static ref TRIGGERED_TOTAL: CounterVec = register_counter_vec!(
+        "probe_websocket_triggered_total",
+        "triggered counter for WebSocket probe plugin",
+        &["plugin", "url"]
+    )
+    .unwrap();
+    static ref TRIGGERED: GaugeVec = register_gauge_vec!(
+        "probe_websocket_triggered",
+        "WebSocket probe plugin triggered",
+        &["plugin", "url"]
+    )
+    .unwrap();
+    static ref HANDSHAKE_SECONDS: GaugeVec = register_gauge_vec!(
+        "probe_websocket_handshake_seconds",
+        "time taken by the WebSocket opening handshake",
+        &["plugin", "url"]
+    )
+    .unwrap();
+    static ref ROUND_TRIP_SECONDS: GaugeVec = register_gauge_vec!(
+        "probe_websocket_round_trip_seconds",
+        "time from sending the message to receiving the expected reply",
+        &["plugin", "url"]
+    )
+    .unwrap();
+}
+
+impl Websocket {
+    async fn check(&self) -> Result<()> {
+        let expect_regex = match self.expect_regex.as_ref() {
+            Some(regex) => Some(
+                Regex::new(regex)
+                    .with_context(|| format!("failed parsing expect_regex {}", regex))?,
+            ),
+            None => None,
+        };
+        let mut request = self
+            .url
+            .as_str()
+            .into_client_request()
+            .with_context(|| format!("invalid url {}", self.url))?;
+        for (name, value) in self.headers.to_owned().unwrap_or_default().iter() {
+            request.headers_mut().insert(
+                HeaderName::from_bytes(name.as_bytes())?,
+                HeaderValue::from_str(value)?,
+            );
+        }
+
+        let started = Instant::now();
+        let (mut stream, _) = connect_async(request).await.context("failed handshake")?;
+        HANDSHAKE_SECONDS
+            .with_label_values(&["probe.websocket", &self.url])
+            .set(started.elapsed().as_secs_f64());
+
+        let started = Instant::now();
+        if let Some(send) = self.send.as_ref() {
+            stream.send(Message::Text(send.to_owned())).await?;
+        }
+        if let Some(re) = expect_regex.as_ref() {
+            loop {
+                let reply = match stream.next().await {
+                    Some(message) => message?,
+                    None => anyhow::bail!("connection closed before expected reply"),
+                };
+                let text = match reply {
+                    Message::Text(text) => text,
+                    Message::Binary(data) => String::from_utf8_lossy(&data).into_owned(),
+                    Message::Close(frame) => anyhow::bail!(
+                        "connection closed before expected reply: {}",
+                        frame
+                            .map(|frame| frame.reason.into_owned())
+                            .unwrap_or_default()
+                    ),
+                    _ => continue,
+                };
+                if re.is_match(&text)? {
+                    break;
+                }
+                log::debug!("websocket {} ignoring reply {}", self.url, text);
+            }
+            ROUND_TRIP_SECONDS
+                .with_label_values(&["probe.websocket", &self.url])
+                .set(started.elapsed().as_secs_f64());
+        }
+        stream.close(None).await.ok();
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Probe for Websocket {
+    fn new() -> Self {
+        Self {
+            ..Default::default()
+        }
+    }
+
+    fn local_schedule(&self) -> Option<String> {
+        self.schedule.to_owned()
+    }
+
+    fn local_name(&self) -> Option<String> {
+        self.name.to_owned()
+    }
+
+    fn depends_on(&self) -> Option<Vec<String>> {
+        self.depends_on.to_owned()
+    }
+
+    fn slug(&self) -> String {
+        slugify(format!(
+            "websocket-{}-{}",
+            self.url,
+            self.send.to_owned().unwrap_or_default()
+        ))
+    }
+
+    async fn observe(
+        &self,
+        store: &Db,
+        alerts: &HashMap<String, Vec<Box<dyn Alert>>>,
+    ) -> Result<()> {
+        log::info!("checking websocket {}", self.url);
+        RUNS_TOTAL
+            .with_label_values(&["probe.websocket", &self.url])
+            .inc();
+
+        let stored = store.get(self.slug().as_bytes())?;
+        let mut to_store = NO_INCIDENT;
+        let mut triggered = 0;
+        let seconds = self.timeout.unwrap_or(10);
+        let result = match timeout(Duration::from_secs(seconds), self.check()).await {
+            Ok(result) => result,
+            Err(_) => Err(anyhow::anyhow!("timed out after {} seconds", seconds)),
+        };
+
+        if let Err(err) = result {
+            let message = format!("{:#}", err);
+            log::info!("_TRIGGERED_: websocket {}: {}", self.url, message);
+            if stored.is_none() || stored == Some(IVec::from(NO_INCIDENT)) {
+                log::warn!("_NOTIFY_: websocket {}: {}", self.url, message);
+                self.notify(
+                    alerts,
+                    Notification {
+                        from: "websocket".to_owned(),
+                        name: self.name("websocket", self.name.to_owned()),
+                        check: format!("websocket {}", self.url),
+                        title: format!("websocket {} check failed", self.url),
+                        message,
+                        message_html: None,
+                        message_entries: None,
+                    },
+                )
+                .await?;
+            }
+            TRIGGERED_TOTAL
+                .with_label_values(&["probe.websocket", &self.url])
+                .inc();
+            triggered = 1;
+            to_store = HAS_INCIDENT;
+        }
+
+        store.insert(self.slug().as_bytes(), to_store)?;
+
+        TRIGGERED
+            .with_label_values(&["probe.websocket", &self.url])
+            .set(triggered as f64);
+        Ok(())
+    }
+}
@@ -1,16 +1,25 @@
-use super::config::Config;
+use super::{config::Config, probes::heartbeat};
 use anyhow::Result;
 use prometheus::{Encoder, TextEncoder};
+use sled::Db;
+use std::sync::Arc;
 use tokio::sync::broadcast;
-use warp::{Filter, Rejection, Reply};
+use warp::{http::StatusCode, Filter, Rejection, Reply};
 
-pub fn start(config: &Config, stop_tx: broadcast::Sender<bool>) -> Result<()> {
+pub fn start(config: &Config, store: Db, stop_tx: broadcast::Sender<bool>) -> Result<()> {
+    let tokens: Vec<String> = config
+        .probes
+        .as_ref()
+        .and_then(|probes| probes.heartbeat.as_ref())
+        .map(|heartbeats| heartbeats.iter().map(|hb| hb.token().to_owned()).collect())
+        .unwrap_or_default();
     if let Some(prometheus) = config.prometheus.clone() {
         let addr: std::net::SocketAddr = prometheus.listen.parse()?;
         let metrics_route = warp::path(prometheus.path.clone()).and_then(metrics_handler);
+        let heartbeat_route = heartbeat_route(store, tokens);
         let mut stop_rx = stop_tx.subscribe();
-        let (_, server) =
-            warp::serve(metrics_route).bind_with_graceful_shutdown(addr, async move {
+        let (_, server) = warp::serve(metrics_route.or(heartbeat_route))
+            .bind_with_graceful_shutdown(addr, async move {
                 if let Err(err) = stop_rx.recv().await {
                     log::error!("failed to install graceful shutdown handler: {}", err);
                     return;
@@ -19,10 +28,32 @@ pub fn start(config: &Config, stop_tx: broadcast::Sender<bool>) -> Result<()> {
             });
         log::info!("listening on http://{}/{}...", addr, prometheus.path);
         tokio::spawn(server);
+    } else if !tokens.is_empty() {
+        // without the server nothing can receive pings, and every heartbeat would fire
+        anyhow::bail!(
+            "heartbeat probes receive pings on the [prometheus] listen address, configure it"
+        );
     }
     Ok(())
 }
 
+// GET /heartbeat/{token} for a ping, or /heartbeat/{token}/{signal} for start and fail
+fn heartbeat_route(
+    store: Db,
+    tokens: Vec<String>,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    let tokens = Arc::new(tokens);
+    let ping = warp::path!("heartbeat" / String)
+        .map(|token: String| (token, String::from("ping")))
+        .untuple_one();
+    let signal = warp::path!("heartbeat" / String / String);
+    ping.or(signal)
+        .unify()
+        .and(warp::any().map(move || store.clone()))
+        .and(warp::any().map(move || Arc::clone(&tokens)))
+        .and_then(heartbeat_handler)
+}
+
 async fn metrics_handler() -> Result<impl Reply, Rejection> {
     let encoder = TextEncoder::new();
     let mut buffer = vec![];
@@ -38,3 +69,92 @@ async fn metrics_handler() -> Result<impl Reply, Rejection> {
         }
     }
 }
+
+async fn heartbeat_handler(
+    token: String,
+    signal: String,
+    store: Db,
+    tokens: Arc<Vec<String>>,
+) -> Result<impl Reply, Rejection> {
+    if !tokens.contains(&token) || !["ping", "start", "fail"].contains(&signal.as_str()) {
+        return Err(warp::reject::not_found());
+    }
+    match heartbeat::record(&store, &token, &signal) {
+        Ok(_) => Ok(warp::reply::with_status("OK", StatusCode::OK)),
+        Err(err) => {
+            log::error!("failed recording heartbeat {}: {}", signal, err);
+            Ok(warp::reply::with_status(
+                "failed recording heartbeat",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_heartbeat_route() {
+        let store = sled::Config::new().temporary(true).open().unwrap();
+        let route = heartbeat_route(store.clone(), vec!["nightly-backup".to_owned()]);
+        let status = |path: &'static str| {
+            let route = route.clone();
+            async move {
+                warp::test::request()
+                    .path(path)
+                    .reply(&route)
+                    .await
+                    .status()
+            }
+        };
+
+        assert_eq!(status("/heartbeat/nightly-backup").await, StatusCode::OK);
+        assert_eq!(
+            status("/heartbeat/nightly-backup/start").await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            status("/heartbeat/nightly-backup/fail").await,
+            StatusCode::OK
+        );
+        assert_eq!(status("/heartbeat/unknown").await, StatusCode::NOT_FOUND);
+        assert_eq!(
+            status("/heartbeat/nightly-backup/since").await,
+            StatusCode::NOT_FOUND
+        );
+        let recorded: Vec<String> = store
+            .scan_prefix("heartbeat-")
+            .keys()
+            .map(|key| String::from_utf8(key.unwrap().to_vec()).unwrap())
+            .collect();
+        assert_eq!(
+            recorded,
+            vec![
+                "heartbeat-nightly-backup-fail",
+                "heartbeat-nightly-backup-ping",
+                "heartbeat-nightly-backup-start",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_heartbeat_needs_server() {
+        let config: Config = toml::from_str(
+            r#"
+            schedule = "0 * * * * *"
+            [[probes.heartbeat]]
+            token = "nightly-backup"
+            period = 86400
+            "#,
+        )
+        .unwrap();
+        let store = sled::Config::new().temporary(true).open().unwrap();
+        let (stop_tx, _) = broadcast::channel(1);
+        assert!(start(&config, store, stop_tx)
+            .unwrap_err()
+            .to_string()
+            .contains("[prometheus]"));
+    }
+}